@@ -0,0 +1,638 @@
+use crate::keymap::{self, Action, Keymap};
+use crate::Style;
+use crate::Theme;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+use termion::color;
+use termion::event::Key;
+
+// Characters that don't show, or show as something they aren't: no-break and zero-width spaces
+// and joiners, the byte order mark, and the controls that reorder bidirectional text
+pub const SUSPICIOUS_CHARACTERS: [char; 20] = [
+    '\u{a0}', '\u{ad}', '\u{61c}', '\u{180e}', '\u{200b}', '\u{200c}', '\u{200d}', '\u{200e}',
+    '\u{200f}', '\u{202a}', '\u{202b}', '\u{202c}', '\u{202d}', '\u{202e}', '\u{2060}', '\u{2066}',
+    '\u{2067}', '\u{2068}', '\u{2069}', '\u{feff}',
+];
+
+// how sim lets you know an action didn't do anything
+#[derive(PartialEq, Copy, Clone)]
+pub enum Bell {
+    Audible,
+    Visual,
+    Off,
+}
+
+// What the gutter numbers the lines with: their line number, how far they are from the cursor's
+// line, or the distance everywhere but on the cursor's line, which gets its line number
+#[derive(PartialEq, Copy, Clone)]
+pub enum LineNumbers {
+    Absolute,
+    Relative,
+    Hybrid,
+}
+
+// What saving does about a file whose directory doesn't exist yet: ask about creating it, create
+// it without asking, or leave the file unsaved
+#[derive(PartialEq, Copy, Clone)]
+pub enum MissingDirectories {
+    Ask,
+    Create,
+    Refuse,
+}
+
+pub struct Config {
+    // how long a status message stays on the message bar, in seconds; 0 keeps it until the next
+    // keypress
+    pub message_timeout: u64,
+    // banner shown on an empty buffer, one line per line of text; `None` leaves the buffer blank
+    pub welcome_message: Option<String>,
+    // let the cursor go down onto the empty line after the last row, where typing appends a new
+    // row; without it the cursor stops on the last row, and new rows are added with Enter at its
+    // end instead
+    pub allow_cursor_past_eof: bool,
+    // columns kept visible to the left and right of the cursor when scrolling sideways
+    pub side_scroll_off: usize,
+    // files bigger than this many bytes open read-only, streamed from the disk as they are shown
+    // rather than loaded whole; `None` loads every file
+    pub stream_above: Option<u64>,
+    // shown in place of the first and the last column of a row that goes on past the left or the
+    // right edge of the screen, e.g. `Some(('<', '>'))`
+    pub overflow_markers: Option<(char, char)>,
+    // pad rows too short to reach a block insert's column with spaces, rather than leaving them out
+    pub pad_block_insert: bool,
+    // shift pasted blocks to the indentation of the line they are pasted into
+    pub reindent_paste: bool,
+    // start a new line with the indentation of the one it was split from, and take a level off
+    // the indentation when a closing bracket is typed first thing on a line, in file types
+    // whose blocks are delimited by brackets
+    pub auto_indent: bool,
+    // have the terminal report the mouse, so that a click puts the cursor where it points and a
+    // drag selects; the terminal's own selection is mostly out of reach meanwhile
+    pub mouse: bool,
+    // rows kept visible above and below a search match the cursor jumps to
+    pub search_context: usize,
+    pub bell: Bell,
+    // tint the background of the line the cursor is on
+    pub highlight_cursor_line: bool,
+    // tint the cursor's column on every visible row as well; syntax and search colors are drawn
+    // on top of either tint
+    pub highlight_cursor_column: bool,
+    // number the lines in a gutter left of the text
+    pub line_numbers: bool,
+    pub line_number_style: LineNumbers,
+    // drawn between the line numbers and the text, taking up a column per character
+    pub gutter_separator: String,
+    // show spaces as `·` and tabs as `→`
+    pub show_whitespace: bool,
+    // Tab in the prompts that take a file name completes it from the files on disk
+    pub complete_paths: bool,
+    // an all-lowercase search query ignores case, one with any uppercase letter respects it
+    pub smart_case: bool,
+    // rows shared between one page and the next when paging up or down
+    pub scroll_overlap: usize,
+    // color indentation that mixes tabs and spaces, in file types where that is a mistake
+    pub warn_mixed_indentation: bool,
+    // mark `suspicious_characters` with a placeholder in a warning color, outside of prose, so
+    // that invisible and reordering characters hidden in code stand out
+    pub warn_suspicious_characters: bool,
+    pub suspicious_characters: Vec<char>,
+    // how many characters a row can have before the rest of it is colored, by file type name
+    // ("Rust"), with the one under "*" going for every other file type; file types without one
+    // have no limit
+    pub line_length_limits: HashMap<String, usize>,
+    // copies of the previous contents kept when saving over a file: 0 keeps none, 1 keeps a
+    // single `name~`, and more keep numbered `name.~1~` (the newest) to `name.~N~` ones
+    pub backups: usize,
+    // keep each file's undo history under `~/.local/share/sim/undo/` when saving, for the next
+    // time it's opened
+    pub persistent_undo: bool,
+    // keep each file's folds under `~/.local/share/sim/folds/` when quitting, for the next time
+    // it's opened
+    pub remember_folds: bool,
+    // quietly save every named buffer with unsaved changes when the terminal loses focus
+    pub autosave_on_focus_loss: bool,
+    // a command to pipe the buffer through before saving, by file type name ("Rust"), e.g.
+    // `rustfmt --emit stdout`; file types without one are saved as they are
+    pub formatters: HashMap<String, String>,
+    // a command that checks a file, by file type name ("Shell"), e.g. `shellcheck -f gcc`; it is
+    // run on the file's name after every save, and whatever it prints as `file:line:col: message`
+    // gets marked in the gutter
+    pub linters: HashMap<String, String>,
+    // the program the `grep` command searches the working directory with, and its arguments;
+    // the pattern goes on the end, and whatever it prints as `file:line:text` or
+    // `file:line:col:text` can be stepped through, e.g. `rg --vimgrep`
+    pub grep_command: String,
+    // triggers and what Tab expands them to, by file type name ("Rust"), with the ones under "*"
+    // going for every file type; `$1`, `$2` and so on mark where later presses of Tab jump to,
+    // and `$0` where the cursor ends up
+    pub snippets: HashMap<String, HashMap<String, String>>,
+    // pick a preset with `Theme::named`, then change single colors on it as needed
+    pub theme: Theme,
+    // spaces per indentation level
+    pub tab_width: usize,
+    // Tab with the cursor in a row's leading whitespace indents the whole row a level, and
+    // Shift-Tab takes a level off it; further right, Tab inserts a tab as usual
+    pub tab_indents: bool,
+    // Tab inserts spaces up to the next multiple of `tab_width` rather than a tab, and Backspace
+    // over nothing but spaces deletes back to the one before
+    pub soft_tabs: bool,
+    // take the indentation, line ending, trailing whitespace and final newline a file gets from
+    // the nearest `.editorconfig` files above it, over the options here
+    pub editorconfig: bool,
+    pub missing_directories: MissingDirectories,
+    // how many more times the quit key has to be pressed to quit with unsaved changes
+    pub quit_times: u8,
+    pub keymap: Keymap,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            message_timeout: 5,
+            welcome_message: Some(format!("SIM editor -- version {}", env!("CARGO_PKG_VERSION"))),
+            allow_cursor_past_eof: true,
+            side_scroll_off: 0,
+            overflow_markers: None,
+            stream_above: None,
+            pad_block_insert: false,
+            reindent_paste: false,
+            auto_indent: false,
+            mouse: false,
+            search_context: 0,
+            bell: Bell::Audible,
+            highlight_cursor_line: false,
+            highlight_cursor_column: false,
+            line_numbers: false,
+            line_number_style: LineNumbers::Absolute,
+            gutter_separator: " ".to_string(),
+            show_whitespace: false,
+            complete_paths: true,
+            smart_case: false,
+            scroll_overlap: 0,
+            warn_mixed_indentation: false,
+            warn_suspicious_characters: false,
+            suspicious_characters: SUSPICIOUS_CHARACTERS.to_vec(),
+            line_length_limits: HashMap::new(),
+            backups: 0,
+            persistent_undo: false,
+            remember_folds: false,
+            autosave_on_focus_loss: false,
+            formatters: HashMap::new(),
+            linters: HashMap::new(),
+            grep_command: "grep -rnI".to_string(),
+            snippets: HashMap::new(),
+            theme: Theme::default(),
+            tab_width: 4,
+            tab_indents: false,
+            soft_tabs: false,
+            editorconfig: true,
+            missing_directories: MissingDirectories::Ask,
+            quit_times: 3,
+            keymap: Keymap::default(),
+        }
+    }
+}
+
+impl Config {
+    // The defaults with `~/.simrc` read over them, if there is one, along with what was wrong in
+    // it. The command-line flags go over both, and a file's `.editorconfig` over both again for
+    // the settings it has.
+    #[must_use]
+    pub fn load() -> (Self, Vec<String>) {
+        let mut config = Self::default();
+        let path = match env::var_os("HOME") {
+            Some(home) => Path::new(&home).join(".simrc"),
+            None => return (config, Vec::new()),
+        };
+        let problems = match fs::read_to_string(&path) {
+            Ok(contents) => config.read_rc(&contents),
+            Err(error) if error.kind() == ErrorKind::NotFound => Vec::new(),
+            Err(error) => vec![format!("~/.simrc: {}", error)],
+        };
+
+        (config, problems)
+    }
+
+    // Sets what a `.simrc` does, a TOML file of `key = value` lines named after the fields here,
+    // giving what it couldn't make sense of. A `theme` is put in place before the rest, so the
+    // colors given go on top of it wherever they come in the file, and `false` leaves an optional
+    // setting like `welcome_message` unset. The maps go under headers of the same name:
+    // `[formatters]`, `[linters]` and `[line_length_limits]` by file type name, like
+    // `Rust = "rustfmt --emit stdout"`, and `[snippets.Rust]` for the snippets of a file type, or
+    // `[snippets."*"]` for every one of them. Under `[colors]` and `[styles]` come the colors and
+    // styles of the theme, like `comment = "#859900"` and `comment = "italic"`, and under `[keys]`
+    // bindings like `"ctrl-k d" = "save"`, or `= "none"` to take a key's binding away.
+    pub fn read_rc(&mut self, contents: &str) -> Vec<String> {
+        let mut problems = Vec::new();
+        let mut settings = Vec::new();
+        let mut section: Option<String> = None;
+        let mut known_section = true;
+        for (index, line) in contents.lines().enumerate() {
+            let line_number = index.saturating_add(1);
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+                let name = name.trim();
+                known_section = SECTIONS.contains(&name) || snippets_file_type(name).is_some();
+                if !known_section {
+                    problems.push((line_number, format!("unknown section {}", name)));
+                }
+                section = Some(name.to_string());
+                continue;
+            }
+            match split_setting(line) {
+                // what is under an unknown header can't be anything else either
+                Some(_) if !known_section => (),
+                Some((key, value)) => settings.push((line_number, section.clone(), key, value)),
+                None => problems.push((line_number, "expected `key = value`".to_string())),
+            }
+        }
+        // the ones before the first header come first anyway, as TOML has no way back to them
+        settings.sort_by_key(|(_, section, key, _)| section.is_some() || *key != "theme");
+
+        // binding the same keys twice in the file is a mistake, where rebinding a default isn't
+        let mut bound = Vec::new();
+        for (line_number, section, key, value) in settings {
+            let set = match section.as_deref() {
+                None => self.set(key, value),
+                Some("keys") => self.bind(key, value, &bound).map(|keys| bound.push(keys)),
+                Some(section) => self.set_in(section, &unquote(key), value),
+            };
+            if let Err(problem) = set {
+                problems.push((line_number, problem));
+            }
+        }
+
+        problems.sort_by_key(|(line_number, _)| *line_number);
+        problems
+            .into_iter()
+            .map(|(line_number, problem)| format!("~/.simrc line {}: {}", line_number, problem))
+            .collect()
+    }
+
+    fn bind(&mut self, keys: &str, action: &str, bound: &[Vec<Key>]) -> Result<Vec<Key>, String> {
+        let keys = keymap::parse_keys(&unquote(keys))?;
+        if bound.contains(&keys) {
+            return Err(format!("{} is bound twice", keymap::describe(&keys)));
+        }
+        match string(action)?.as_str() {
+            "none" => self.keymap.unbind(&keys),
+            name => {
+                let action = Action::named(name).ok_or_else(|| format!("unknown action {}", name))?;
+                self.keymap.bind(keys.clone(), action)?;
+            },
+        }
+
+        Ok(keys)
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "message_timeout" => self.message_timeout = number(value)?,
+            "welcome_message" => self.welcome_message = optional(value, string)?,
+            "allow_cursor_past_eof" => self.allow_cursor_past_eof = boolean(value)?,
+            "side_scroll_off" => self.side_scroll_off = number(value)?,
+            "stream_above" => self.stream_above = optional(value, number)?,
+            "overflow_markers" => self.overflow_markers = optional(value, markers)?,
+            "pad_block_insert" => self.pad_block_insert = boolean(value)?,
+            "reindent_paste" => self.reindent_paste = boolean(value)?,
+            "auto_indent" => self.auto_indent = boolean(value)?,
+            "mouse" => self.mouse = boolean(value)?,
+            "search_context" => self.search_context = number(value)?,
+            "bell" => {
+                self.bell = choice(value, &[("audible", Bell::Audible), ("visual", Bell::Visual), ("off", Bell::Off)])?;
+            },
+            "highlight_cursor_line" => self.highlight_cursor_line = boolean(value)?,
+            "highlight_cursor_column" => self.highlight_cursor_column = boolean(value)?,
+            "line_numbers" => self.line_numbers = boolean(value)?,
+            "line_number_style" => {
+                let styles = [
+                    ("absolute", LineNumbers::Absolute),
+                    ("relative", LineNumbers::Relative),
+                    ("hybrid", LineNumbers::Hybrid),
+                ];
+                self.line_number_style = choice(value, &styles)?;
+            },
+            "gutter_separator" => self.gutter_separator = string(value)?,
+            "show_whitespace" => self.show_whitespace = boolean(value)?,
+            "complete_paths" => self.complete_paths = boolean(value)?,
+            "smart_case" => self.smart_case = boolean(value)?,
+            "scroll_overlap" => self.scroll_overlap = number(value)?,
+            "warn_mixed_indentation" => self.warn_mixed_indentation = boolean(value)?,
+            "warn_suspicious_characters" => self.warn_suspicious_characters = boolean(value)?,
+            "suspicious_characters" => self.suspicious_characters = string(value)?.chars().collect(),
+            "backups" => self.backups = number(value)?,
+            "persistent_undo" => self.persistent_undo = boolean(value)?,
+            "remember_folds" => self.remember_folds = boolean(value)?,
+            "autosave_on_focus_loss" => self.autosave_on_focus_loss = boolean(value)?,
+            "grep_command" => self.grep_command = string(value)?,
+            "theme" => {
+                let name = string(value)?;
+                self.theme = Theme::named(&name).ok_or_else(|| format!("unknown theme {}", name))?;
+            },
+            "status_fg" => self.theme.status_fg = rgb(&string(value)?)?,
+            "status_bg" => self.theme.status_bg = rgb(&string(value)?)?,
+            "tab_width" => self.tab_width = number(value)?,
+            "tab_indents" => self.tab_indents = boolean(value)?,
+            "soft_tabs" => self.soft_tabs = boolean(value)?,
+            "editorconfig" => self.editorconfig = boolean(value)?,
+            "missing_directories" => {
+                let choices = [
+                    ("ask", MissingDirectories::Ask),
+                    ("create", MissingDirectories::Create),
+                    ("refuse", MissingDirectories::Refuse),
+                ];
+                self.missing_directories = choice(value, &choices)?;
+            },
+            "quit_times" => self.quit_times = number(value)?,
+            _ => return Err(format!("unknown setting {}", key)),
+        }
+
+        Ok(())
+    }
+
+    // what a line under a `[section]` header other than `[keys]` sets
+    fn set_in(&mut self, section: &str, key: &str, value: &str) -> Result<(), String> {
+        match section {
+            "colors" => *self.color(key)? = rgb(&string(value)?)?,
+            "styles" => *self.style(key)? = style(&string(value)?)?,
+            "formatters" => {
+                self.formatters.insert(key.to_string(), string(value)?);
+            },
+            "linters" => {
+                self.linters.insert(key.to_string(), string(value)?);
+            },
+            "line_length_limits" => {
+                self.line_length_limits.insert(key.to_string(), number(value)?);
+            },
+            _ => {
+                let file_type = snippets_file_type(section).ok_or_else(|| format!("unknown section {}", section))?;
+                self.snippets.entry(file_type).or_default().insert(key.to_string(), string(value)?);
+            },
+        }
+
+        Ok(())
+    }
+
+    fn color(&mut self, name: &str) -> Result<&mut color::Rgb, String> {
+        let theme = &mut self.theme;
+        let palette = &mut theme.palette;
+        Ok(match name {
+            "text" => &mut palette.text,
+            "number" => &mut palette.number,
+            "string" => &mut palette.string,
+            "character" => &mut palette.character,
+            "comment" => &mut palette.comment,
+            "primary_keyword" => &mut palette.primary_keyword,
+            "secondary_keyword" => &mut palette.secondary_keyword,
+            "search_match" => &mut palette.search_match,
+            "occurrence" => &mut palette.occurrence,
+            "mixed_indentation" => &mut palette.mixed_indentation,
+            "suspicious" => &mut palette.suspicious,
+            "overflow" => &mut palette.overflow,
+            "crosshair" => &mut palette.crosshair,
+            "whitespace" => &mut palette.whitespace,
+            "status_fg" => &mut theme.status_fg,
+            "status_bg" => &mut theme.status_bg,
+            "gutter_fg" => &mut theme.gutter_fg,
+            "gutter_match_fg" => &mut theme.gutter_match_fg,
+            _ => return Err(format!("unknown color {}", name)),
+        })
+    }
+
+    fn style(&mut self, name: &str) -> Result<&mut Style, String> {
+        let styles = &mut self.theme.palette.styles;
+        Ok(match name {
+            "number" => &mut styles.number,
+            "string" => &mut styles.string,
+            "character" => &mut styles.character,
+            "comment" => &mut styles.comment,
+            "primary_keyword" => &mut styles.primary_keyword,
+            "secondary_keyword" => &mut styles.secondary_keyword,
+            "search_match" => &mut styles.search_match,
+            "occurrence" => &mut styles.occurrence,
+            _ => return Err(format!("unknown style {}", name)),
+        })
+    }
+}
+
+// the headers a `.simrc` can have, besides one `[snippets.<file type>]` per file type
+const SECTIONS: [&str; 6] = ["keys", "colors", "styles", "formatters", "linters", "line_length_limits"];
+
+// the file type a `[snippets.Rust]` or `[snippets."*"]` header is for
+fn snippets_file_type(section: &str) -> Option<String> {
+    section.strip_prefix("snippets.").map(|file_type| unquote(file_type.trim()))
+}
+
+// The key and the value of a `key = value` line, where a quoted key may have a `=` in it
+fn split_setting(line: &str) -> Option<(&str, &str)> {
+    let (key, rest) = match line.strip_prefix('"') {
+        Some(quoted) => {
+            let end = quoted.find('"')?.saturating_add(2);
+            (line.get(..end)?, line.get(end..)?)
+        },
+        None => {
+            let end = line.find('=')?;
+            (line.get(..end)?, line.get(end..)?)
+        },
+    };
+
+    Some((key.trim(), rest.trim_start().strip_prefix('=')?.trim()))
+}
+
+// a TOML key without its quotes, if it has any
+fn unquote(key: &str) -> String {
+    string(key).unwrap_or_else(|_| key.to_string())
+}
+
+// the line up to a `#` that isn't inside a string
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (index, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '#' if !in_string => return line.get(..index).unwrap_or(line),
+            _ => (),
+        }
+    }
+
+    line
+}
+
+fn number<T: std::str::FromStr>(value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("{} isn't a number that fits", value))
+}
+
+fn boolean(value: &str) -> Result<bool, String> {
+    match value {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        _ => Err(format!("{} isn't true or false", value)),
+    }
+}
+
+// `false`, for a setting that can be left unset, or what `parse` makes of it
+fn optional<T>(value: &str, parse: fn(&str) -> Result<T, String>) -> Result<Option<T>, String> {
+    if value == "false" {
+        return Ok(None);
+    }
+
+    parse(value).map(Some)
+}
+
+// a quoted string that is one of the names in `choices`
+fn choice<T: Copy>(value: &str, choices: &[(&str, T)]) -> Result<T, String> {
+    let name = string(value)?;
+    choices.iter().find(|(choice, _)| *choice == name).map(|(_, chosen)| *chosen).ok_or_else(|| {
+        let names = choices.iter().map(|(choice, _)| format!("\"{}\"", choice)).collect::<Vec<String>>();
+        format!("{} isn't one of {}", value, names.join(", "))
+    })
+}
+
+// A TOML basic string, with escapes like `\n` and `\u00a0` turned into what they stand for
+fn string(value: &str) -> Result<String, String> {
+    let invalid = || format!("{} isn't a quoted string", value);
+    let mut chars = value.strip_prefix('"').ok_or_else(invalid)?.chars();
+    let mut string = String::new();
+    loop {
+        match chars.next().ok_or_else(invalid)? {
+            '"' if chars.as_str().is_empty() => return Ok(string),
+            '"' => return Err(invalid()),
+            '\\' => string.push(unescape(&mut chars).ok_or_else(|| format!("{} has an unknown escape", value))?),
+            c => string.push(c),
+        }
+    }
+}
+
+// the character an escape stands for, from right after its backslash
+fn unescape(chars: &mut std::str::Chars<'_>) -> Option<char> {
+    let digits = match chars.next()? {
+        'n' => return Some('\n'),
+        't' => return Some('\t'),
+        'r' => return Some('\r'),
+        '"' => return Some('"'),
+        '\\' => return Some('\\'),
+        'u' => 4,
+        'U' => 8,
+        _ => return None,
+    };
+    let hex: String = chars.take(digits).collect();
+    if hex.len() != digits {
+        return None;
+    }
+
+    u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32)
+}
+
+// the two characters of `overflow_markers`, like "<>"
+fn markers(value: &str) -> Result<(char, char), String> {
+    let markers = string(value)?;
+    let mut chars = markers.chars();
+    match (chars.next(), chars.next(), chars.next()) {
+        (Some(left), Some(right), None) => Ok((left, right)),
+        _ => Err(format!("{} isn't two characters like \"<>\"", value)),
+    }
+}
+
+// the attributes of a style, like "bold italic", or "none"
+fn style(value: &str) -> Result<Style, String> {
+    let mut style = Style::default();
+    for attribute in value.split_whitespace() {
+        match attribute {
+            "bold" => style.bold = true,
+            "italic" => style.italic = true,
+            "underline" => style.underline = true,
+            "none" => (),
+            _ => return Err(format!("{} isn't bold, italic, underline or none", attribute)),
+        }
+    }
+
+    Ok(style)
+}
+
+// a color written as "#rrggbb"
+fn rgb(value: &str) -> Result<color::Rgb, String> {
+    let invalid = || format!("{} isn't a color like \"#3f3f3f\"", value);
+    let digits = value.strip_prefix('#').filter(|digits| digits.len() == 6).ok_or_else(invalid)?;
+    let channel = |range| {
+        digits
+            .get(range)
+            .and_then(|channel| u8::from_str_radix(channel, 16).ok())
+            .ok_or_else(invalid)
+    };
+
+    Ok(color::Rgb(channel(0..2)?, channel(2..4)?, channel(4..6)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_settings_and_tables() {
+        let mut config = Config::default();
+        let problems = config.read_rc(
+            r##"
+            message_timeout = 0
+            welcome_message = false
+            overflow_markers = "<>"
+            bell = "visual"
+            suspicious_characters = "\u00a0\t" # a no-break space and a tab
+            theme = "light"
+
+            [colors]
+            comment = "#859900"
+
+            [styles]
+            comment = "italic underline"
+
+            [formatters]
+            Rust = "rustfmt --emit stdout"
+
+            [line_length_limits]
+            "*" = 80
+
+            [snippets."*"]
+            fn = "fn $1() {\n    $0\n}"
+            "##,
+        );
+
+        assert!(problems.is_empty(), "{:?}", problems);
+        assert_eq!(config.message_timeout, 0);
+        assert!(config.welcome_message.is_none());
+        assert_eq!(config.overflow_markers, Some(('<', '>')));
+        assert!(config.bell == Bell::Visual);
+        assert_eq!(config.suspicious_characters, vec!['\u{a0}', '\t']);
+        // the colors go on top of the theme, though it comes after them in the file
+        assert_eq!(config.theme.name, "light");
+        assert!(config.theme.palette.comment == color::Rgb(0x85, 0x99, 0));
+        assert!(config.theme.palette.styles.comment.italic && config.theme.palette.styles.comment.underline);
+        assert_eq!(config.formatters.get("Rust").map(String::as_str), Some("rustfmt --emit stdout"));
+        assert_eq!(config.line_length_limits.get("*"), Some(&80));
+        let snippet = config.snippets.get("*").and_then(|snippets| snippets.get("fn"));
+        assert_eq!(snippet.map(String::as_str), Some("fn $1() {\n    $0\n}"));
+    }
+
+    #[test]
+    fn tells_what_it_could_not_read() {
+        let mut config = Config::default();
+        let problems = config.read_rc("tab_width = wide\nbell = \"loud\"\n[nothing]\nthis = 1\n[colors]\ntext = \"red\"\n");
+        assert_eq!(
+            problems,
+            vec![
+                "~/.simrc line 1: wide isn't a number that fits",
+                "~/.simrc line 2: \"loud\" isn't one of \"audible\", \"visual\", \"off\"",
+                "~/.simrc line 3: unknown section nothing",
+                "~/.simrc line 6: red isn't a color like \"#3f3f3f\"",
+            ]
+        );
+    }
+}
@@ -0,0 +1,90 @@
+use std::collections::HashMap;
+use std::fs;
+use std::process::Command;
+
+// ordered from the least to the most serious
+#[derive(PartialEq, Eq, PartialOrd, Ord, Copy, Clone)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+// One complaint of a compiler or linter about a line
+#[derive(Clone)]
+pub struct Diagnostic {
+    // counted from 1, as the tools print it
+    pub column: usize,
+    pub message: String,
+    pub severity: Severity,
+}
+
+// The diagnostics in `output` that are about `file_name`, by row index. Every line of the form
+// `file:line:col: message` or `file:line: message` counts, anything else is passed over; a
+// message starting with "warning", "note" or "help" is a warning, any other one an error.
+#[must_use]
+pub fn parse(output: &str, file_name: &str) -> HashMap<usize, Vec<Diagnostic>> {
+    let mut diagnostics: HashMap<usize, Vec<Diagnostic>> = HashMap::new();
+    for (path, line, column, message) in output.lines().filter_map(parse_line) {
+        if line == 0 || !same_file(path, file_name) {
+            continue;
+        }
+        let lowercase = message.to_lowercase();
+        let severity = if ["warning", "note", "help"].iter().any(|word| lowercase.starts_with(word)) {
+            Severity::Warning
+        } else {
+            Severity::Error
+        };
+        diagnostics.entry(line.saturating_sub(1)).or_default().push(Diagnostic {
+            column,
+            message: message.to_string(),
+            severity,
+        });
+    }
+
+    diagnostics
+}
+
+// Runs `command`, a program and its arguments split on whitespace, on `file_name`, which goes on
+// the end, and picks the diagnostics about it out of everything it prints. Linters exit with an
+// error whenever they find something, so only a command that can't be run at all fails.
+/// # Errors
+///
+pub fn run(command: &str, file_name: &str) -> Result<HashMap<usize, Vec<Diagnostic>>, String> {
+    let mut words = command.split_whitespace();
+    let program = words.next().ok_or_else(|| "No lint command".to_string())?;
+    let output = Command::new(program)
+        .args(words)
+        .arg(file_name)
+        .output()
+        .map_err(|error| format!("Couldn't run {}: {}", program, error))?;
+
+    let printed = format!(
+        "{}\n{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    Ok(parse(&printed, file_name))
+}
+
+pub(crate) fn parse_line(line: &str) -> Option<(&str, usize, usize, &str)> {
+    let mut parts = line.splitn(3, ':');
+    let path = parts.next()?.trim();
+    let number = parts.next()?.trim().parse().ok()?;
+    let rest = parts.next()?;
+    let (column, message) = match rest.split_once(':') {
+        Some((column, message)) if column.trim().parse::<usize>().is_ok() => {
+            (column.trim().parse().ok()?, message)
+        },
+        _ => (1, rest),
+    };
+
+    Some((path, number, column, message.trim()))
+}
+
+// Paths name the same file once both are made absolute, or failing that when they are the same
+pub(crate) fn same_file(a: &str, b: &str) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
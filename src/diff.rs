@@ -0,0 +1,102 @@
+use std::cmp;
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum DiffKind {
+    Same,
+    Removed,
+    Added,
+    Changed,
+}
+
+// One row of a side by side diff. A side is `None` where the other side has a line it lacks.
+pub struct DiffLine {
+    pub kind: DiffKind,
+    pub left: Option<String>,
+    pub right: Option<String>,
+}
+
+impl DiffLine {
+    fn new(kind: DiffKind, left: Option<&String>, right: Option<&String>) -> Self {
+        Self {
+            kind,
+            left: left.cloned(),
+            right: right.cloned(),
+        }
+    }
+}
+
+// Lines up `left` and `right` along their longest common subsequence. A run of removed lines
+// followed by a run of added ones is paired up into changed lines, as far as both runs go.
+#[must_use]
+#[allow(clippy::indexing_slicing, clippy::integer_arithmetic)]
+pub fn diff(left: &[String], right: &[String]) -> Vec<DiffLine> {
+    // the common head and tail don't need the quadratic table below
+    let prefix = left.iter().zip(right).take_while(|(a, b)| a == b).count();
+    let suffix = left[prefix..]
+        .iter()
+        .rev()
+        .zip(right[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old = &left[prefix..left.len() - suffix];
+    let new = &right[prefix..right.len() - suffix];
+
+    // lcs[i][j] is the length of the longest common subsequence of old[i..] and new[j..]
+    let mut lcs = vec![vec![0_usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                cmp::max(lcs[i + 1][j], lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut lines: Vec<DiffLine> = left[..prefix]
+        .iter()
+        .map(|line| DiffLine::new(DiffKind::Same, Some(line), Some(line)))
+        .collect();
+    let mut removed = Vec::new();
+    let mut added = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            flush_changes(&mut lines, &mut removed, &mut added);
+            lines.push(DiffLine::new(DiffKind::Same, Some(&old[i]), Some(&new[j])));
+            i += 1;
+            j += 1;
+        } else if j == new.len() || (i < old.len() && lcs[i + 1][j] >= lcs[i][j + 1]) {
+            removed.push(&old[i]);
+            i += 1;
+        } else {
+            added.push(&new[j]);
+            j += 1;
+        }
+    }
+    flush_changes(&mut lines, &mut removed, &mut added);
+
+    lines.extend(
+        left[left.len() - suffix..]
+            .iter()
+            .map(|line| DiffLine::new(DiffKind::Same, Some(line), Some(line))),
+    );
+
+    lines
+}
+
+fn flush_changes(lines: &mut Vec<DiffLine>, removed: &mut Vec<&String>, added: &mut Vec<&String>) {
+    let paired = cmp::min(removed.len(), added.len());
+    for (left, right) in removed.iter().zip(added.iter()) {
+        lines.push(DiffLine::new(DiffKind::Changed, Some(left), Some(right)));
+    }
+    for left in removed.iter().skip(paired) {
+        lines.push(DiffLine::new(DiffKind::Removed, Some(left), None));
+    }
+    for right in added.iter().skip(paired) {
+        lines.push(DiffLine::new(DiffKind::Added, None, Some(right)));
+    }
+
+    removed.clear();
+    added.clear();
+}
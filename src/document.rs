@@ -2,8 +2,80 @@ use crate::Position;
 use crate::Row;
 use crate::SearchDirection;
 use crate::FileType;
+use crate::Outline;
+use crate::Diagnostic;
+use crate::EditorConfig;
+use crate::Pattern;
+use crate::state;
+use crate::undo::{Edit, History, Selection};
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::fs;
-use std::io::{Error, Write};
+use std::io::{BufRead, BufReader, BufWriter, Error, ErrorKind, Write};
+use std::process::{Command, Stdio};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::SystemTime;
+use unicode_segmentation::UnicodeSegmentation;
+
+// how many rows the background highlighter hands back at a time
+const HIGHLIGHT_CHUNK: usize = 256;
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum LineEnding {
+    Lf,
+    Crlf,
+}
+
+impl Default for LineEnding {
+    fn default() -> Self {
+        Self::Lf
+    }
+}
+
+impl LineEnding {
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Lf => "\n",
+            Self::Crlf => "\r\n",
+        }
+    }
+
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Lf => "LF",
+            Self::Crlf => "CRLF",
+        }
+    }
+}
+
+// Where a search matched, and how many graphemes it matched
+#[derive(Clone)]
+pub struct Match {
+    pub position: Position,
+    pub len: usize,
+}
+
+// A range of rows that can be folded away; the first row stays on screen and stands in for the
+// rest
+struct Fold {
+    first: usize,
+    last: usize,
+    closed: bool,
+    // made from a selection rather than from indentation
+    manual: bool,
+}
+
+impl Fold {
+    fn hides(&self, y: usize) -> bool {
+        self.closed && y > self.first && y <= self.last
+    }
+}
 
 #[derive(Default)]
 pub struct Document {
@@ -11,34 +83,245 @@ pub struct Document {
     pub file_name: Option<String>,
     dirty: bool,
     file_type: FileType,
+    // taken from the first line of the file and used for every line when saving
+    line_ending: LineEnding,
+    // the last row had no line ending in the file, and gets none when saving either; a row is a
+    // line of text, never an empty stand-in for the end of the file
+    missing_final_newline: bool,
+    // the word whose occurrences are marked on top of the regular highlighting
+    occurrence: Option<String>,
+    folds: Vec<Fold>,
+    // flag indentation that mixes tabs and spaces, where the file type cares
+    warn_mixed_indentation: bool,
+    // characters to flag, where the file type cares
+    suspicious_characters: Vec<char>,
+    // the line length limit by file type name, with the one under "*" going for the rest
+    line_length_limits: HashMap<String, usize>,
+    history: History,
+    // set while undoing or redoing, so that the edits made along the way aren't recorded
+    replaying: bool,
+    // how many bytes the file took up when it was opened
+    size: u64,
+    // when the file was last changed on disk as far as we know, from opening, saving or reloading
+    // it
+    modified: Option<SystemTime>,
+    // the rows holding a match for the word highlighted last, in order
+    match_rows: Vec<usize>,
+    // rows highlighted on a worker thread, coming in a chunk at a time until it's done
+    highlights: Option<Receiver<Vec<(usize, Row)>>>,
+    // what a linter said about each row, by row index, as of when it last ran
+    diagnostics: HashMap<usize, Vec<Diagnostic>>,
+    // how many words there are, counted when first asked for since the last edit
+    word_count: Cell<Option<usize>>,
+    // what the project's `.editorconfig` says about the file, over the config
+    editorconfig: EditorConfig,
 }
 
 impl Document {
     /// # Errors
     ///
     pub fn open(filename: &str) -> Result<Self, std::io::Error> {
-        let contents = fs::read_to_string(filename)?;
-        let file_type = FileType::from(filename);
+        Self::open_with_progress(filename, |_, _| {})
+    }
+
+    // Streams the file line by line instead of reading it in one go, reporting `(bytes_read,
+    // total_bytes)` after each line so that the caller can show how far along the load is. The
+    // rows are highlighted in the background afterwards.
+    /// # Errors
+    ///
+    pub fn open_with_progress<F>(filename: &str, mut on_progress: F) -> Result<Self, std::io::Error>
+    where
+        F: FnMut(u64, u64),
+    {
+        let file = fs::File::open(filename)?;
+        let metadata = file.metadata()?;
+        let total = metadata.len();
+        let mut reader = BufReader::new(file);
+        // the reader's first buffer holds the first few KB of the file, which is plenty to tell
+        // text from binary without reading the whole thing
+        if looks_binary(reader.fill_buf()?) {
+            return Err(Error::new(ErrorKind::InvalidData, "looks like a binary file"));
+        }
 
         let mut rows = Vec::new();
-        for line in contents.lines() {
-            let mut row = Row::from(line);
-            row.highlight(&file_type.highlighting_options(), None, false);
-            rows.push(row);
+        let mut line = String::new();
+        let mut line_ending = None;
+        let mut read: u64 = 0;
+        let mut missing_final_newline = false;
+        loop {
+            line.clear();
+            let bytes = reader.read_line(&mut line)?;
+            if bytes == 0 {
+                break;
+            }
+
+            // strip the line ending the same way `str::lines` does
+            if line.ends_with('\n') {
+                line.pop();
+                let ending = if line.ends_with('\r') {
+                    line.pop();
+                    LineEnding::Crlf
+                } else {
+                    LineEnding::Lf
+                };
+                line_ending.get_or_insert(ending);
+            } else {
+                // only the last line can end without one
+                missing_final_newline = true;
+            }
+
+            rows.push(Row::from(&line[..]));
+
+            read = read.saturating_add(bytes as u64);
+            on_progress(read, total);
         }
 
-        Ok(Self {
+        let first_line = rows.first().map(Row::to_string).unwrap_or_default();
+        let file_type = FileType::detect(filename, &first_line);
+        let mut document = Self {
             rows,
             file_name: Some(filename.to_string()),
+            file_type,
+            line_ending: line_ending.unwrap_or_default(),
+            missing_final_newline,
+            size: read,
+            modified: metadata.modified().ok(),
+            ..Self::default()
+        };
+        document.history = History::load(filename, &document.to_string()).unwrap_or_default();
+        document.history.mark_saved();
+        document.restore_folds();
+        document.highlight_in_background();
+
+        Ok(document)
+    }
+
+    // Builds an unnamed document straight from a string, without touching the file system.
+    // Handy for driving edits and searches headlessly, e.g. in tests or benchmarks.
+    #[must_use]
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(contents: &str) -> Self {
+        let file_type = FileType::default();
+
+        Self {
+            rows: Self::rows_from(contents, &file_type),
+            file_name: None,
             dirty: false,
             file_type,
-        })
+            line_ending: if contents.contains("\r\n") {
+                LineEnding::Crlf
+            } else {
+                LineEnding::Lf
+            },
+            missing_final_newline: lacks_final_newline(contents),
+            ..Self::default()
+        }
+    }
+
+    fn rows_from(contents: &str, file_type: &FileType) -> Vec<Row> {
+        let mut rows = Vec::new();
+        for line in contents.lines() {
+            let mut row = Row::from(line);
+            row.highlight(&file_type.highlighting_options(), None, false);
+            rows.push(row);
+        }
+
+        rows
+    }
+
+    // whether the file type's blocks are delimited by brackets
+    #[must_use]
+    pub fn bracket_blocks(&self) -> bool {
+        self.file_type.bracket_blocks()
     }
 
     pub fn file_type(&self) -> String {
         self.file_type.name()
     }
 
+    #[must_use]
+    pub fn is_prose(&self) -> bool {
+        self.file_type.is_prose()
+    }
+
+    // The words in the document, as runs of anything but whitespace with a letter or digit in
+    // them, so that list bullets and heading marks don't count
+    #[must_use]
+    pub fn word_count(&self) -> usize {
+        if let Some(count) = self.word_count.get() {
+            return count;
+        }
+        let count = self.rows.iter().map(Row::word_count).sum();
+        self.word_count.set(Some(count));
+        count
+    }
+
+    // The directory of the document's file, which relative paths typed while it is open start from;
+    // a document without a file leaves them to the working directory
+    #[must_use]
+    pub fn directory(&self) -> Option<&Path> {
+        Path::new(self.file_name.as_ref()?).parent()
+    }
+
+    // `path` as seen from the document's directory, unless it is absolute
+    #[must_use]
+    pub fn resolve(&self, path: &str) -> String {
+        match self.directory() {
+            Some(directory) if Path::new(path).is_relative() => {
+                directory.join(path).to_string_lossy().into_owned()
+            },
+            _ => path.to_string(),
+        }
+    }
+
+    // the size of the file when it was opened, in bytes
+    #[must_use]
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    #[must_use]
+    pub fn line_ending(&self) -> LineEnding {
+        self.line_ending
+    }
+
+    #[must_use]
+    pub fn editorconfig(&self) -> &EditorConfig {
+        &self.editorconfig
+    }
+
+    // Takes on what an `.editorconfig` says about the file. The line ending is the document's own
+    // and changes with it, to be written the next time the file is saved; the rest is left to
+    // whoever edits and saves it.
+    pub fn set_editorconfig(&mut self, settings: EditorConfig) {
+        if let Some(line_ending) = settings.line_ending {
+            self.line_ending = line_ending;
+        }
+        self.editorconfig = settings;
+    }
+
+    // whether the last row ends with a line ending, as every other one does
+    #[must_use]
+    pub fn has_final_newline(&self) -> bool {
+        !self.missing_final_newline
+    }
+
+    // Gives the last row a line ending when saving, or leaves it without
+    pub fn set_final_newline(&mut self, final_newline: bool) {
+        if self.missing_final_newline == final_newline {
+            self.missing_final_newline = !final_newline;
+            self.dirty = true;
+        }
+    }
+
+    // whether the file starts with a UTF-8 byte order mark, which stays in the first row as is
+    #[must_use]
+    pub fn has_bom(&self) -> bool {
+        self.rows
+            .first()
+            .map_or(false, |row| row.to_string().starts_with('\u{feff}'))
+    }
+
     #[must_use]
     pub fn row(&self, index: usize) -> Option<&Row> {
         self.rows.get(index)
@@ -60,6 +343,7 @@ impl Document {
         }
 
         self.dirty = true;
+        self.word_count.set(None);
 
         if c == '\n' {
             self.insert_newline(at);
@@ -71,11 +355,15 @@ impl Document {
             row.insert(0, c);
             row.highlight(&self.file_type.highlighting_options(), None, false);
             self.rows.push(row);
+            self.record(Edit::AddRow(at.y));
+            self.record(Edit::Insert(Position { x: 0, y: at.y }, c));
         } else {
             #[allow(clippy::indexing_slicing)]
             let row = &mut self.rows[at.y];
-            row.insert(at.x, c);
+            let x = at.x.min(row.len());
+            row.insert(x, c);
             row.highlight(&self.file_type.highlighting_options(), None, false);
+            self.record(Edit::Insert(Position { x, y: at.y }, c));
         }
     }
 
@@ -86,17 +374,21 @@ impl Document {
 
         if at.y == self.len() {
             self.rows.push(Row::default());
+            self.record(Edit::AddRow(at.y));
             return;
         }
 
         #[allow(clippy::indexing_slicing)]
         let current_row = &mut self.rows[at.y];
-        let mut new_row = current_row.split(at.x);
+        let x = at.x.min(current_row.len());
+        let mut new_row = current_row.split(x);
         current_row.highlight(&self.file_type.highlighting_options(), None, false);
         new_row.highlight(&self.file_type.highlighting_options(), None, false);
 
         #[allow(clippy::integer_arithmetic)]
         self.rows.insert(at.y + 1, new_row);
+        self.shift_folds(at.y, true);
+        self.record(Edit::NewlineInsert(Position { x, y: at.y }));
     }
 
     #[allow(clippy::integer_arithmetic, clippy::indexing_slicing)]
@@ -107,6 +399,7 @@ impl Document {
         }
 
         self.dirty = true;
+        self.word_count.set(None);
 
         // checking if we are at the end of a line, and if a line follows after this one
         if at.x == self.rows[at.y].len() && at.y + 1< len {
@@ -120,38 +413,1106 @@ impl Document {
             let row = &mut self.rows[at.y];
             row.append(&next_row);
             row.highlight(&self.file_type.highlighting_options(), None, false);
-        } else {
+            self.shift_folds(at.y, false);
+            self.record(Edit::RowJoin(at.clone()));
+        } else if at.x < self.rows[at.y].len() {
             //  If that’s not the case, we simply try to delete from the current row
             let row = &mut self.rows[at.y];
+            let deleted = row.slice(at.x, at.x + 1);
             row.delete(at.x);
             row.highlight(&self.file_type.highlighting_options(), None, false);
+            self.record(Edit::Delete(at.clone(), deleted));
+        }
+    }
+
+    fn record(&mut self, edit: Edit) {
+        if !self.replaying {
+            self.history.record(edit);
+        }
+    }
+
+    // Makes the next edit start a group of its own instead of joining the one before it
+    pub fn break_undo_group(&mut self) {
+        self.history.break_group();
+    }
+
+    // Tells the history what is selected now, so the edits made from here on can bring it back
+    // when they are undone, and the ones just made when they are redone
+    pub fn note_selection(&mut self, selection: Option<Selection>) {
+        self.history.note_selection(selection);
+    }
+
+    // Takes back the newest group of edits, returning where the cursor should go and what was
+    // selected before them
+    pub fn undo(&mut self) -> Option<(Position, Option<Selection>)> {
+        let group = self.history.undo()?;
+        self.replaying = true;
+        let mut position = None;
+        for edit in group.edits.iter().rev() {
+            position = Some(self.revert(edit));
+        }
+        self.replaying = false;
+        // back where the file was saved, there is nothing left to save
+        self.dirty = !self.history.is_at_save();
+        self.word_count.set(None);
+
+        position.map(|position| (position, group.selection_before))
+    }
+
+    // Makes the newest undone group of edits again, returning where the cursor should go and
+    // what was selected after them
+    pub fn redo(&mut self) -> Option<(Position, Option<Selection>)> {
+        let group = self.history.redo()?;
+        self.replaying = true;
+        let mut position = None;
+        for edit in &group.edits {
+            position = Some(self.apply(edit));
+        }
+        self.replaying = false;
+        self.dirty = !self.history.is_at_save();
+        self.word_count.set(None);
+
+        position.map(|position| (position, group.selection_after))
+    }
+
+    #[allow(clippy::integer_arithmetic)]
+    fn revert(&mut self, edit: &Edit) -> Position {
+        match edit {
+            Edit::Insert(at, _) => {
+                self.delete(at);
+                at.clone()
+            },
+            Edit::Delete(at, text) => {
+                for (i, c) in text.chars().enumerate() {
+                    self.insert(&Position { x: at.x + i, y: at.y }, c);
+                }
+                at.clone()
+            },
+            Edit::NewlineInsert(at) => {
+                let x = self.rows.get(at.y).map_or(0, Row::len);
+                self.delete(&Position { x, y: at.y });
+                at.clone()
+            },
+            Edit::RowJoin(at) => {
+                self.insert_newline(at);
+                at.clone()
+            },
+            Edit::AddRow(y) => {
+                if *y < self.len() {
+                    self.rows.remove(*y);
+                }
+                Position { x: 0, y: *y }
+            },
+            Edit::Replace(y, old, _) => {
+                self.replace_row(*y, old);
+                Position { x: 0, y: *y }
+            },
+            Edit::Rewrite(old, _) => {
+                self.set_contents(old);
+                Position::default()
+            },
+        }
+    }
+
+    #[allow(clippy::integer_arithmetic)]
+    fn apply(&mut self, edit: &Edit) -> Position {
+        match edit {
+            Edit::Insert(at, c) => {
+                self.insert(at, *c);
+                Position { x: at.x + 1, y: at.y }
+            },
+            Edit::Delete(at, _) | Edit::RowJoin(at) => {
+                self.delete(at);
+                at.clone()
+            },
+            Edit::NewlineInsert(at) => {
+                self.insert_newline(at);
+                Position { x: 0, y: at.y + 1 }
+            },
+            Edit::AddRow(y) => {
+                self.rows.push(Row::default());
+                Position { x: 0, y: *y }
+            },
+            Edit::Replace(y, _, new) => {
+                self.replace_row(*y, new);
+                Position { x: 0, y: *y }
+            },
+            Edit::Rewrite(_, new) => {
+                self.set_contents(new);
+                Position::default()
+            },
+        }
+    }
+
+    fn replace_row(&mut self, y: usize, text: &str) {
+        if let Some(row) = self.rows.get_mut(y) {
+            *row = Row::from(text);
+            row.highlight(&self.file_type.highlighting_options(), None, false);
+        }
+    }
+
+    // Swaps every row for `contents`, dropping the folds that no longer fit
+    fn set_contents(&mut self, contents: &str) {
+        self.rows = Self::rows_from(contents, &self.file_type);
+        self.word_count.set(None);
+        self.missing_final_newline = lacks_final_newline(contents);
+        let len = self.len();
+        self.folds.retain(|fold| fold.last < len);
+        self.highlight(None, false);
+    }
+
+    // Pipes the document through `command`, a program and its arguments split on whitespace, and
+    // takes what it prints as the new contents if it succeeds. Otherwise the document stays as
+    // it was and the error is whatever the command printed to stderr.
+    /// # Errors
+    ///
+    pub fn format(&mut self, command: &str) -> Result<(), String> {
+        let mut words = command.split_whitespace();
+        let program = words.next().ok_or_else(|| "No formatter command".to_string())?;
+        let mut child = Command::new(program)
+            .args(words)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|error| format!("Couldn't run {}: {}", program, error))?;
+
+        // writing on a thread of its own keeps a formatter that answers before it has read
+        // everything from filling its pipe and waiting on us forever
+        let contents = self.to_string();
+        let stdin = child.stdin.take();
+        let writer = thread::spawn(move || stdin.map(|mut stdin| stdin.write_all(contents.as_bytes())));
+        let output = child
+            .wait_with_output()
+            .map_err(|error| format!("Couldn't run {}: {}", program, error))?;
+        let _ = writer.join();
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(match stderr.lines().find(|line| !line.trim().is_empty()) {
+                Some(line) => format!("{} failed: {}", program, line),
+                None => format!("{} failed with {}", program, output.status),
+            });
+        }
+        let formatted = String::from_utf8(output.stdout)
+            .map_err(|_| format!("{} printed something that isn't UTF-8", program))?;
+
+        let old = self.to_string();
+        if formatted != old {
+            self.set_contents(&formatted);
+            self.dirty = true;
+            self.word_count.set(None);
+            self.record(Edit::Rewrite(old, formatted));
+        }
+
+        Ok(())
+    }
+
+    // Reads the file in again, over whatever changes weren't saved. Taking the file's contents is
+    // an edit like any other, so undo brings the changes back.
+    /// # Errors
+    ///
+    pub fn reload(&mut self) -> Result<(), Error> {
+        let file_name = self.file_name.clone().ok_or_else(|| Error::new(ErrorKind::NotFound, "no file name"))?;
+        let bytes = fs::read(&file_name)?;
+        if looks_binary(&bytes) {
+            return Err(Error::new(ErrorKind::InvalidData, "looks like a binary file"));
+        }
+        let contents = String::from_utf8(bytes).map_err(|error| Error::new(ErrorKind::InvalidData, error))?;
+
+        let old = self.to_string();
+        if contents != old {
+            self.set_contents(&contents);
+            self.record(Edit::Rewrite(old, contents.clone()));
+        }
+        // taken from the first line as on opening, unless the `.editorconfig` says otherwise
+        let first_line = contents.split('\n').next().filter(|_| contents.contains('\n'));
+        if let Some(first_line) = first_line {
+            let ending = if first_line.ends_with('\r') { LineEnding::Crlf } else { LineEnding::Lf };
+            self.line_ending = self.editorconfig.line_ending.unwrap_or(ending);
+        }
+        self.size = contents.len() as u64;
+        self.modified = modified_time(&file_name);
+        self.dirty = false;
+        self.history.mark_saved();
+
+        Ok(())
+    }
+
+    // Whether something other than us wrote to the file since it was opened, saved or reloaded,
+    // going by its modification time. A file whose time can't be read counts as unchanged.
+    #[must_use]
+    pub fn has_changed_on_disk(&self) -> bool {
+        match (&self.file_name, self.modified) {
+            (Some(file_name), Some(modified)) => modified_time(file_name).map_or(false, |now| now != modified),
+            _ => false,
+        }
+    }
+
+    // Takes the file's current modification time as the one we know, so that a change already
+    // warned about isn't reported again
+    pub fn mark_disk_change_seen(&mut self) {
+        if let Some(file_name) = &self.file_name {
+            self.modified = modified_time(file_name).or(self.modified);
+        }
+    }
+
+    // Keeps the undo history next to the saved contents, so that it's there the next time the
+    // file is opened
+    /// # Errors
+    ///
+    pub fn save_undo_history(&self) -> Result<(), Error> {
+        match &self.file_name {
+            Some(file_name) => self.history.save(file_name, &self.to_string()),
+            None => Ok(()),
         }
     }
 
     /// # Errors
     ///
     pub fn save(&mut self) -> Result<(), Error> {
+        self.save_with_progress(|_, _| {})
+    }
+
+    // Like `save`, reporting `(bytes_written, total_bytes)` after each row so that the caller can
+    // show how far along a big save is
+    /// # Errors
+    ///
+    pub fn save_with_progress<F>(&mut self, mut on_progress: F) -> Result<(), Error>
+    where
+        F: FnMut(u64, u64),
+    {
         if let Some(file_name) = &self.file_name {
-            let mut file = fs::File::create(file_name)?;
-            self.file_type = FileType::from(file_name);
-            let mut starts_with_comment = false;
-            for row in &mut self.rows {
-                file.write_all(row.as_bytes())?;
-                file.write_all(b"\n")?;
-                starts_with_comment = row.highlight(&self.file_type.highlighting_options(), None, starts_with_comment)
-            }
+            let first_line = self.rows.first().map(Row::to_string).unwrap_or_default();
+            self.file_type = FileType::detect(file_name, &first_line);
+            self.file_type.warn_mixed_indentation(self.warn_mixed_indentation);
+            self.file_type.warn_suspicious_characters(&self.suspicious_characters);
+            self.file_type.limit_line_length(self.line_length_limit());
+            let opts = self.file_type.highlighting_options();
+            let rows = &mut self.rows;
+            let line_ending = self.line_ending.as_str().as_bytes();
+            let last = rows.len().saturating_sub(1);
+            let missing_final_newline = self.missing_final_newline;
+            let ends = |y: usize| y < last || !missing_final_newline;
+            let total = rows
+                .iter()
+                .enumerate()
+                .map(|(y, row)| row.as_bytes().len().saturating_add(if ends(y) { line_ending.len() } else { 0 }))
+                .sum::<usize>() as u64;
+            write_atomically(file_name, |file| {
+                let mut file = BufWriter::new(file);
+                let mut written: u64 = 0;
+                let mut starts_with_comment = false;
+                for (y, row) in rows.iter_mut().enumerate() {
+                    file.write_all(row.as_bytes())?;
+                    written = written.saturating_add(row.as_bytes().len() as u64);
+                    if ends(y) {
+                        file.write_all(line_ending)?;
+                        written = written.saturating_add(line_ending.len() as u64);
+                    }
+                    on_progress(written, total);
+                    starts_with_comment = row.highlight(opts, None, starts_with_comment)
+                }
+
+                file.flush()
+            })?;
 
+            // our own write isn't a change made behind our back
+            self.modified = modified_time(file_name);
             self.dirty = false;
+            self.history.mark_saved();
         }
 
         Ok(())
     }
 
-    pub fn highlight(&mut self, word: Option<&str>) {
+    // Adds `amount` to the number under the cursor at `at`, or the first one after it on the row,
+    // keeping the width of one written with leading zeros. A `-` right before the digits makes it
+    // negative, unless it follows a letter or digit like in `foo-1`. Returns where the number ends
+    // now, or `None` when there is no number or the sum doesn't fit.
+    pub fn add_to_number(&mut self, at: &Position, amount: i64) -> Option<Position> {
+        let graphemes: Vec<String> = self
+            .rows
+            .get(at.y)?
+            .to_string()
+            .graphemes(true)
+            .map(str::to_string)
+            .collect();
+        let is_digit = |x: usize| graphemes.get(x).map_or(false, |g| g.len() == 1 && g.chars().all(|c| c.is_ascii_digit()));
+
+        let mut start = (at.x..graphemes.len()).find(|&x| is_digit(x))?;
+        if start == at.x {
+            while start > 0 && is_digit(start.saturating_sub(1)) {
+                start = start.saturating_sub(1);
+            }
+        }
+        let end = (start..graphemes.len()).find(|&x| !is_digit(x)).unwrap_or(graphemes.len());
+        let digits = graphemes.get(start..end)?.concat();
+        let word_before = |x: usize| graphemes.get(x).map_or(false, |g| g.chars().all(char::is_alphanumeric));
+        let negative = start > 0
+            && graphemes.get(start.saturating_sub(1)).map_or(false, |g| g == "-")
+            && (start < 2 || !word_before(start.saturating_sub(2)));
+
+        let value: i64 = digits.parse().ok()?;
+        let value = if negative { value.checked_neg()? } else { value };
+        let sum = value.checked_add(amount)?;
+        let magnitude = sum.unsigned_abs();
+        let number = if digits.len() > 1 && digits.starts_with('0') {
+            format!("{:0width$}", magnitude, width = digits.len())
+        } else {
+            magnitude.to_string()
+        };
+        let number = if sum < 0 { format!("-{}", number) } else { number };
+
+        let from = if negative { start.saturating_sub(1) } else { start };
+        self.replace_at(&Position { x: from, y: at.y }, end.saturating_sub(from), &number);
+        Some(Position {
+            x: from.saturating_add(number.len()).saturating_sub(1),
+            y: at.y,
+        })
+    }
+
+    // Turns rows `start` to `end` upside down, both of them included, returning how many of them
+    // ended up holding something else
+    pub fn reverse_lines(&mut self, start: usize, end: usize) -> usize {
+        let end = end.min(self.len().saturating_sub(1));
+        let rows = match self.rows.get_mut(start..=end) {
+            Some(rows) if rows.len() > 1 => rows,
+            _ => return 0,
+        };
+        let old: Vec<String> = rows.iter().map(Row::to_string).collect();
+        rows.reverse();
+        let edits: Vec<Edit> = rows
+            .iter()
+            .zip(old)
+            .enumerate()
+            .filter(|(_, (row, old))| row.to_string() != *old)
+            .map(|(offset, (row, old))| Edit::Replace(start.saturating_add(offset), old, row.to_string()))
+            .collect();
+
+        let changed = edits.len();
+        if changed > 0 {
+            self.dirty = true;
+            // a row may now start or end a multiline comment somewhere else
+            self.highlight(None, false);
+        }
+        for edit in edits {
+            self.record(edit);
+        }
+        changed
+    }
+
+    // Drops the rows from `start` to `end` that repeat one further up in that range, or with
+    // `adjacent` only the ones repeating the row right above, returning how many went
+    pub fn unique_lines(&mut self, start: usize, end: usize, adjacent: bool) -> usize {
+        let mut seen = HashSet::new();
+        let mut previous = None;
+        let mut kept = Vec::new();
+        for (y, row) in self.rows.iter().enumerate() {
+            let text = row.to_string();
+            if y >= start && y <= end {
+                let repeated = if adjacent {
+                    previous.as_ref() == Some(&text)
+                } else {
+                    !seen.insert(text.clone())
+                };
+                previous = Some(text.clone());
+                if repeated {
+                    continue;
+                }
+            }
+            kept.push(text);
+        }
+
+        let removed = self.len().saturating_sub(kept.len());
+        if removed > 0 {
+            let old = self.to_string();
+            let ending = self.line_ending.as_str();
+            let mut new = kept.join(ending);
+            if !self.missing_final_newline {
+                new.push_str(ending);
+            }
+            self.set_contents(&new);
+            self.dirty = true;
+            self.record(Edit::Rewrite(old, new));
+        }
+        removed
+    }
+
+    // Cuts the spaces and tabs off the end of every row, returning how many rows changed
+    pub fn trim_trailing_whitespace(&mut self) -> usize {
+        let mut trimmed = 0_usize;
+        for y in 0..self.len() {
+            let (len, kept) = match self.rows.get(y) {
+                Some(row) => {
+                    let text = row.to_string();
+                    (row.len(), text.trim_end_matches(|c| c == ' ' || c == '\t').graphemes(true).count())
+                },
+                None => continue,
+            };
+            if kept < len {
+                self.replace_at(&Position { x: kept, y }, len.saturating_sub(kept), "");
+                trimmed = trimmed.saturating_add(1);
+            }
+        }
+
+        trimmed
+    }
+
+    // Whether the file is indented with tabs, going by the first indented row outside of a
+    // multiline comment, whose continuation rows are often lined up with a space; `None` when no
+    // row is indented
+    #[must_use]
+    pub fn indents_with_tabs(&self) -> Option<bool> {
+        self.rows
+            .iter()
+            .filter(|row| !row.starts_in_comment())
+            .find_map(|row| row.indentation().chars().next())
+            .map(|c| c == '\t')
+    }
+
+    // Indents every row by a level per bracket it is nested in, a level being `tab_width` spaces
+    // with `soft_tabs` and a tab without, and a closing bracket at the start of a row counting as
+    // already closed. Brackets in strings and comments don't count, and rows continuing a
+    // multiline comment keep their indentation. Returns how many rows changed, or `None` for a
+    // file type without bracket delimited blocks.
+    pub fn reindent(&mut self, tab_width: usize, soft_tabs: bool) -> Option<usize> {
+        if !self.file_type.bracket_blocks() {
+            return None;
+        }
+        // rows highlighted one at a time while editing may not know they are in a comment
+        self.highlight(None, false);
+
+        let level = if soft_tabs { " ".repeat(tab_width) } else { "\t".to_string() };
+        let mut depth: usize = 0;
+        let mut changed: usize = 0;
+        let mut edits = Vec::new();
+        for (y, row) in self.rows.iter_mut().enumerate() {
+            let brackets = row.code_brackets();
+            if !row.starts_in_comment() {
+                let trimmed = row.to_string();
+                let trimmed = trimmed.trim_start_matches(|c| c == ' ' || c == '\t');
+                let leading_closers = trimmed.chars().take_while(|c| "}])".contains(*c)).count();
+                let indentation = if trimmed.is_empty() {
+                    String::new()
+                } else {
+                    level.repeat(depth.saturating_sub(leading_closers))
+                };
+                if row.indentation() != indentation {
+                    let old = row.to_string();
+                    row.set_indentation(&indentation);
+                    edits.push(Edit::Replace(y, old, row.to_string()));
+                    changed = changed.saturating_add(1);
+                }
+            }
+
+            for bracket in brackets {
+                if "{([".contains(bracket) {
+                    depth = depth.saturating_add(1);
+                } else {
+                    depth = depth.saturating_sub(1);
+                }
+            }
+        }
+
+        if changed > 0 {
+            self.dirty = true;
+            self.word_count.set(None);
+            self.highlight(None, false);
+        }
+        for edit in edits {
+            self.record(edit);
+        }
+        Some(changed)
+    }
+
+    // Puts `with` in place of the `find_len` graphemes at `at`
+    pub fn replace_at(&mut self, at: &Position, find_len: usize, with: &str) {
+        if let Some(row) = self.rows.get_mut(at.y) {
+            let old = row.to_string();
+            row.replace(at.x, find_len, with);
+            row.highlight(&self.file_type.highlighting_options(), None, false);
+            let edit = Edit::Replace(at.y, old, row.to_string());
+            self.dirty = true;
+            self.word_count.set(None);
+            self.record(edit);
+        }
+    }
+
+    // Row `at.y` as `replace_at` would leave it, with `with` marked as a match, while the document
+    // itself stays as it is
+    #[must_use]
+    pub fn preview_replace(&self, at: &Position, find_len: usize, with: &str) -> Option<Row> {
+        let mut row = Row::from(self.rows.get(at.y)?.to_string().as_str());
+        row.replace(at.x, find_len, with);
+        row.highlight(&self.file_type.highlighting_options(), Some(with), false);
+        Some(row)
+    }
+
+    // The text from `start` up to, but not including, `end`, with rows joined by newlines
+    #[must_use]
+    pub fn extract(&self, start: &Position, end: &Position) -> String {
+        let (start, end) = if precedes(end, start) { (end, start) } else { (start, end) };
+        let mut text = String::new();
+        for y in start.y..=end.y {
+            if let Some(row) = self.rows.get(y) {
+                let from = if y == start.y { start.x } else { 0 };
+                let to = if y == end.y { end.x } else { row.len() };
+                text.push_str(&row.slice(from, to));
+            }
+            if y != end.y {
+                text.push('\n');
+            }
+        }
+
+        text
+    }
+
+    // Takes out the text from `start` up to `end`, in either order, the way `extract` reads it
+    pub fn delete_range(&mut self, start: &Position, end: &Position) {
+        let (start, end) = if precedes(end, start) { (end, start) } else { (start, end) };
+        let row_len = |document: &Self, y: usize| document.row(y).map_or(0, Row::len);
+        if start.y == end.y {
+            self.replace_at(start, end.x.saturating_sub(start.x), "");
+            return;
+        }
+
+        // the rows in between are emptied and joined onto the first, one at a time
+        self.replace_at(start, row_len(self, start.y).saturating_sub(start.x), "");
+        for y in start.y..end.y {
+            self.delete(start);
+            let len = if y.saturating_add(1) == end.y {
+                end.x
+            } else {
+                row_len(self, start.y).saturating_sub(start.x)
+            };
+            self.replace_at(start, len, "");
+        }
+    }
+
+    // The definitions or headings of the document as labels with the row each sits on, in the
+    // order they come; what counts is up to the file type
+    #[must_use]
+    pub fn outline(&self) -> Vec<(String, usize)> {
+        let mut entries = Vec::new();
+        let mut in_fence = false;
+        for (y, row) in self.rows.iter().enumerate() {
+            let line = row.to_string();
+            let line = line.trim();
+            let label = match self.file_type.outline() {
+                Outline::Nothing => return entries,
+                Outline::Definitions(keywords, qualifiers) => definition(line, keywords, qualifiers),
+                Outline::Headings => {
+                    if line.starts_with("```") || line.starts_with("~~~") {
+                        in_fence = !in_fence;
+                    }
+                    let is_heading = line.starts_with('#') && line.trim_start_matches('#').starts_with(' ');
+                    if is_heading && !in_fence { Some(line.to_string()) } else { None }
+                },
+            };
+            if let Some(label) = label {
+                entries.push((label, y));
+            }
+        }
+
+        entries
+    }
+
+    // Wraps the text from `start` to `end` in the file type's block comment delimiters, or takes
+    // away the ones already around it, be they just inside or just outside the range. A range that
+    // holds a block comment of its own is left alone, as the inner closer would end the new comment
+    // early. Returns where the range starts and ends after the change, or an error message.
+    pub fn toggle_block_comment(
+        &mut self,
+        start: &Position,
+        end: &Position,
+    ) -> Result<(Position, Position), &'static str> {
+        let (open, close) = match self.file_type.block_comment() {
+            Some((open, close)) => (open.to_string(), close.to_string()),
+            None => return Err("No block comments for this file type."),
+        };
+        let (start, end) = if precedes(end, start) { (end, start) } else { (start, end) };
+        let open_len = open[..].graphemes(true).count();
+        let close_len = close[..].graphemes(true).count();
+
+        let text = self.extract(start, end);
+        let within = text.starts_with(&open)
+            && text.ends_with(&close)
+            && text[..].graphemes(true).count() >= open_len.saturating_add(close_len);
+        let before = Position {
+            x: start.x.saturating_sub(open_len),
+            y: start.y,
+        };
+        let after = Position {
+            x: end.x.saturating_add(close_len),
+            y: end.y,
+        };
+        let around = start.x >= open_len
+            && self.extract(&before, start) == open
+            && self.extract(end, &after) == close;
+
+        let range = if within || around {
+            let (open_at, close_at) = if within {
+                (start.clone(), Position { x: end.x.saturating_sub(close_len), y: end.y })
+            } else {
+                (before, end.clone())
+            };
+            // the closer goes first so that the opener's position still holds
+            for _ in 0..close_len {
+                self.delete(&close_at);
+            }
+            for _ in 0..open_len {
+                self.delete(&open_at);
+            }
+            let shift = if open_at.y == close_at.y { open_len } else { 0 };
+            let new_end = Position {
+                x: close_at.x.saturating_sub(shift),
+                y: end.y,
+            };
+            (open_at, new_end)
+        } else {
+            if text.contains(&open) || text.contains(&close) {
+                return Err("Selection already holds a block comment.");
+            }
+            for (index, c) in close.chars().enumerate() {
+                self.insert(&Position { x: end.x.saturating_add(index), y: end.y }, c);
+            }
+            for (index, c) in open.chars().enumerate() {
+                self.insert(&Position { x: start.x.saturating_add(index), y: start.y }, c);
+            }
+            let shift = if start.y == end.y { open_len } else { 0 };
+            let new_end = Position {
+                x: end.x.saturating_add(close_len).saturating_add(shift),
+                y: end.y,
+            };
+            (start.clone(), new_end)
+        };
+        // the comment may reach over several rows
+        self.highlight(None, false);
+
+        Ok(range)
+    }
+
+    // Writes the buffer to `path` while it stays tied to its own file, dirty or not
+    /// # Errors
+    ///
+    pub fn save_copy(&self, path: &str) -> Result<(), Error> {
+        write_atomically(path, |file| file.write_all(self.to_string().as_bytes()))
+    }
+
+    pub fn highlight(&mut self, word: Option<&str>, ignore_case: bool) {
+        self.highlight_pattern(word.map(|word| Pattern::plain(word, ignore_case)).as_ref());
+    }
+
+    // Like `highlight`, marking the matches of a plain query or a regular expression
+    pub fn highlight_pattern(&mut self, pattern: Option<&Pattern>) {
+        // whatever the background highlighter is still working on is out of date now
+        self.highlights = None;
+        self.match_rows = match pattern {
+            Some(pattern) => self
+                .rows
+                .iter()
+                .enumerate()
+                .filter(|(_, row)| row.find_pattern(pattern, 0, SearchDirection::Forward).is_some())
+                .map(|(y, _)| y)
+                .collect(),
+            None => Vec::new(),
+        };
         let mut starts_with_comment = false;
         for row in &mut self.rows {
-            starts_with_comment = row.highlight(&self.file_type.highlighting_options(), word, starts_with_comment)
+            starts_with_comment = row.highlight(&self.file_type.highlighting_options(), None, starts_with_comment);
+            row.highlight_match(pattern);
+            if let Some(occurrence) = &self.occurrence {
+                row.highlight_occurrences(occurrence);
+            }
+        }
+    }
+
+    // whether row `y` matched the word highlighted last
+    #[must_use]
+    pub fn has_match(&self, y: usize) -> bool {
+        self.match_rows.binary_search(&y).is_ok()
+    }
+
+    pub fn set_diagnostics(&mut self, diagnostics: HashMap<usize, Vec<Diagnostic>>) {
+        self.diagnostics = diagnostics;
+    }
+
+    #[must_use]
+    pub fn has_diagnostics(&self) -> bool {
+        !self.diagnostics.is_empty()
+    }
+
+    #[must_use]
+    pub fn diagnostics_at(&self, y: usize) -> &[Diagnostic] {
+        self.diagnostics.get(&y).map_or(&[], Vec::as_slice)
+    }
+
+    // The nearest row after `y` with diagnostics, or before it going backward, wrapping around
+    // the end of the document
+    #[must_use]
+    pub fn next_diagnostic(&self, y: usize, direction: SearchDirection) -> Option<usize> {
+        let mut rows: Vec<usize> = self.diagnostics.keys().copied().collect();
+        rows.sort_unstable();
+        if direction == SearchDirection::Forward {
+            rows.iter().find(|&&row| row > y).or_else(|| rows.first()).copied()
+        } else {
+            rows.iter().rev().find(|&&row| row < y).or_else(|| rows.last()).copied()
+        }
+    }
+
+    pub fn set_warn_mixed_indentation(&mut self, enabled: bool) {
+        self.warn_mixed_indentation = enabled;
+        self.file_type.warn_mixed_indentation(enabled);
+        self.rehighlight();
+    }
+
+    pub fn set_line_length_limits(&mut self, limits: &HashMap<String, usize>) {
+        self.line_length_limits = limits.clone();
+        self.file_type.limit_line_length(self.line_length_limit());
+        self.rehighlight();
+    }
+
+    // the limit for the current file type, if it has one
+    fn line_length_limit(&self) -> Option<usize> {
+        let limits = &self.line_length_limits;
+        limits.get(&self.file_type.name()).or_else(|| limits.get("*")).copied()
+    }
+
+    pub fn set_suspicious_characters(&mut self, characters: &[char]) {
+        self.suspicious_characters = characters.to_vec();
+        self.file_type.warn_suspicious_characters(characters);
+        self.rehighlight();
+    }
+
+    #[must_use]
+    pub fn occurrence(&self) -> Option<&str> {
+        self.occurrence.as_deref()
+    }
+
+    // Marks the whole-word occurrences of `word` everywhere, or clears them with `None`
+    pub fn highlight_occurrences(&mut self, word: Option<&str>) {
+        self.occurrence = word.map(str::to_string);
+        self.rehighlight();
+    }
+
+    // Highlights everything again, in the background if that's where it's being done already
+    fn rehighlight(&mut self) {
+        if self.is_highlighting() {
+            self.highlight_in_background();
+        } else {
+            self.highlight(None, false);
+        }
+    }
+
+    // Highlights every row on a worker thread, so that a big file can be shown and edited before
+    // that's done. Rows show up plain until `apply_highlights` picks up their turn.
+    fn highlight_in_background(&mut self) {
+        let opts = self.file_type.highlighting_options().clone();
+        let occurrence = self.occurrence.clone();
+        let lines: Vec<String> = self.rows.iter().map(Row::to_string).collect();
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let mut starts_with_comment = false;
+            let mut chunk = Vec::new();
+            for (index, line) in lines.iter().enumerate() {
+                let mut row = Row::from(&line[..]);
+                starts_with_comment = row.highlight(&opts, None, starts_with_comment);
+                if let Some(occurrence) = &occurrence {
+                    row.highlight_occurrences(occurrence);
+                }
+                chunk.push((index, row));
+                // a send fails once the document stops listening, and then there's no point
+                // going on
+                if chunk.len() == HIGHLIGHT_CHUNK && sender.send(mem::take(&mut chunk)).is_err() {
+                    return;
+                }
+            }
+            let _ = sender.send(chunk);
+        });
+        self.highlights = Some(receiver);
+    }
+
+    // Swaps in the rows the background highlighter has finished so far, except for rows that
+    // were edited in the meantime and so got highlighted already. Returns whether any changed.
+    pub fn apply_highlights(&mut self) -> bool {
+        let receiver = match &self.highlights {
+            Some(receiver) => receiver,
+            None => return false,
+        };
+
+        let mut applied = false;
+        let mut done = false;
+        loop {
+            match receiver.try_recv() {
+                Ok(chunk) => {
+                    for (index, row) in chunk {
+                        if let Some(current) = self.rows.get_mut(index) {
+                            if current.as_bytes() == row.as_bytes() {
+                                *current = row;
+                                applied = true;
+                            }
+                        }
+                    }
+                },
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    done = true;
+                    break;
+                },
+            }
         }
+        if done {
+            self.highlights = None;
+        }
+
+        applied
+    }
+
+    // whether the background highlighter still has rows to hand back
+    #[must_use]
+    pub fn is_highlighting(&self) -> bool {
+        self.highlights.is_some()
+    }
+
+    // Folds the rows after `y` that are indented deeper than it, the trailing blank ones aside.
+    // Returns how many rows got hidden.
+    pub fn fold_indented(&mut self, y: usize) -> usize {
+        let depth = match self.rows.get(y) {
+            Some(row) => row.indentation().len(),
+            None => return 0,
+        };
+
+        let mut last = y;
+        for (index, row) in self.rows.iter().enumerate().skip(y.saturating_add(1)) {
+            let indentation = row.indentation().len();
+            if indentation == row.len() {
+                continue;
+            }
+            if indentation <= depth {
+                break;
+            }
+            last = index;
+        }
+
+        self.add_fold(y, last, false)
+    }
+
+    // Folds exactly the rows from `first` to `last`. Unlike an indentation fold, it stays around
+    // when opened so it can be closed again. Returns how many rows got hidden.
+    pub fn fold_range(&mut self, first: usize, last: usize) -> usize {
+        let last = last.min(self.len().saturating_sub(1));
+        self.add_fold(first, last, true)
+    }
+
+    fn add_fold(&mut self, first: usize, last: usize, manual: bool) -> usize {
+        if last <= first {
+            return 0;
+        }
+
+        self.folds.push(Fold {
+            first,
+            last,
+            closed: true,
+            manual,
+        });
+        last.saturating_sub(first)
+    }
+
+    // Remembers the folds for the next time the file is opened, one `first last closed manual`
+    // line each
+    /// # Errors
+    ///
+    pub fn save_folds(&self) -> Result<(), Error> {
+        let file_name = match &self.file_name {
+            Some(file_name) => file_name,
+            None => return Ok(()),
+        };
+        let file = state::file_for("folds", file_name)?;
+        if let Some(dir) = file.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let mut out = String::new();
+        for fold in &self.folds {
+            out.push_str(&format!(
+                "{} {} {} {}\n",
+                fold.first,
+                fold.last,
+                u8::from(fold.closed),
+                u8::from(fold.manual)
+            ));
+        }
+
+        fs::write(file, out)
+    }
+
+    // Brings back the folds saved for the file, leaving out any that no longer fit in it
+    fn restore_folds(&mut self) {
+        let saved = match self
+            .file_name
+            .as_deref()
+            .and_then(|file_name| state::file_for("folds", file_name).ok())
+            .and_then(|file| fs::read_to_string(file).ok())
+        {
+            Some(saved) => saved,
+            None => return,
+        };
+
+        for line in saved.lines() {
+            let fields: Vec<usize> = line.split(' ').filter_map(|field| field.parse().ok()).collect();
+            if let [first, last, closed, manual] = fields[..] {
+                if first < last && last < self.len() {
+                    self.folds.push(Fold {
+                        first,
+                        last,
+                        closed: closed == 1,
+                        manual: manual == 1,
+                    });
+                }
+            }
+        }
+    }
+
+    // Opens the fold shown on row `y`, returning whether there was one
+    pub fn unfold(&mut self, y: usize) -> bool {
+        // of several folds starting on the same row, the outermost is the one on screen
+        let outermost = self
+            .folds
+            .iter()
+            .enumerate()
+            .filter(|(_, fold)| fold.closed && fold.first == y)
+            .max_by_key(|(_, fold)| fold.last)
+            .map(|(index, _)| index);
+
+        if let Some(index) = outermost {
+            self.open_fold(index);
+            true
+        } else {
+            false
+        }
+    }
+
+    // Opens the fold shown on row `y`, or closes the innermost open one starting there. Returns
+    // whether there was a fold to toggle.
+    pub fn toggle_fold(&mut self, y: usize) -> bool {
+        if self.unfold(y) {
+            return true;
+        }
+
+        let innermost = self
+            .folds
+            .iter_mut()
+            .filter(|fold| !fold.closed && fold.first == y)
+            .min_by_key(|fold| fold.last);
+        if let Some(fold) = innermost {
+            fold.closed = true;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn open_fold(&mut self, index: usize) {
+        if self.folds.get(index).map_or(false, |fold| fold.manual) {
+            if let Some(fold) = self.folds.get_mut(index) {
+                fold.closed = false;
+            }
+        } else {
+            self.folds.remove(index);
+        }
+    }
+
+    // Opens every fold that hides row `y`
+    pub fn reveal(&mut self, y: usize) {
+        while let Some(index) = self.folds.iter().position(|fold| fold.hides(y)) {
+            self.open_fold(index);
+        }
+    }
+
+    // How many rows the closed fold shown on row `y` hides, if there is one
+    #[must_use]
+    pub fn fold_at(&self, y: usize) -> Option<usize> {
+        self.folds
+            .iter()
+            .filter(|fold| fold.closed && fold.first == y)
+            .map(|fold| fold.last.saturating_sub(fold.first))
+            .max()
+    }
+
+    // Whether an open fold starts on row `y`, ready to be closed again
+    #[must_use]
+    pub fn has_open_fold(&self, y: usize) -> bool {
+        self.folds.iter().any(|fold| !fold.closed && fold.first == y)
+    }
+
+    #[must_use]
+    pub fn is_hidden(&self, y: usize) -> bool {
+        self.folds.iter().any(|fold| fold.hides(y))
+    }
+
+    // The first row at or after `y` that isn't folded away
+    #[must_use]
+    pub fn next_visible(&self, y: usize) -> usize {
+        let mut y = y;
+        while let Some(fold) = self.folds.iter().find(|fold| fold.hides(y)) {
+            y = fold.last.saturating_add(1);
+        }
+        y
+    }
+
+    // The first row at or before `y` that isn't folded away
+    #[must_use]
+    pub fn previous_visible(&self, y: usize) -> usize {
+        let mut y = y;
+        while let Some(fold) = self.folds.iter().find(|fold| fold.hides(y)) {
+            y = fold.first;
+        }
+        y
+    }
+
+    // How many rows from `start` up to, but not including, `end` are on screen
+    #[must_use]
+    pub fn visible_between(&self, start: usize, end: usize) -> usize {
+        (start..end).filter(|&y| !self.is_hidden(y)).count()
+    }
+
+    // Walks up from `y` until `count` visible rows have been passed
+    #[must_use]
+    pub fn visible_before(&self, y: usize, count: usize) -> usize {
+        let mut y = y;
+        for _ in 0..count {
+            if y == 0 {
+                break;
+            }
+            y = self.previous_visible(y.saturating_sub(1));
+        }
+        y
+    }
+
+    // Keeps the folds covering the same rows after a row was added right after `at`, or the one
+    // after `at` was joined into it. A fold whose first row goes away goes with it.
+    fn shift_folds(&mut self, at: usize, inserted: bool) {
+        let next = at.saturating_add(1);
+        if !inserted {
+            self.folds.retain(|fold| fold.first != next);
+        }
+        for fold in &mut self.folds {
+            if fold.first > at {
+                if inserted {
+                    fold.first = fold.first.saturating_add(1);
+                    fold.last = fold.last.saturating_add(1);
+                } else {
+                    fold.first = fold.first.saturating_sub(1);
+                    fold.last = fold.last.saturating_sub(1);
+                }
+            } else if inserted && fold.last >= at {
+                fold.last = fold.last.saturating_add(1);
+            } else if !inserted && fold.last >= next {
+                fold.last = fold.last.saturating_sub(1);
+            }
+        }
+        self.folds.retain(|fold| fold.last > fold.first);
     }
 
     #[must_use]
@@ -159,9 +1520,52 @@ impl Document {
         self.dirty
     }
 
+    // Like `find_from`, but when `bounds` is given only a match lying entirely between the two
+    // positions counts; the bounds may come in either order
+    #[must_use]
+    pub fn find(
+        &self,
+        pattern: &Pattern,
+        at: &Position,
+        direction: SearchDirection,
+        bounds: Option<&(Position, Position)>,
+    ) -> Option<Match> {
+        let (start, end) = match bounds {
+            Some((first, second)) if precedes(second, first) => (second, first),
+            Some((first, second)) => (first, second),
+            None => return self.find_from(pattern, at, direction),
+        };
+
+        // searching forward from the start of the range, or backward from its end, keeps every
+        // result on the right side of one bound, leaving only the other one to check
+        let at = if precedes(at, start) {
+            start
+        } else if precedes(end, at) {
+            end
+        } else {
+            at
+        };
+        let found = self.find_from(pattern, at, direction)?;
+        let within = if direction == SearchDirection::Forward {
+            let found_end = Position {
+                x: found.position.x.saturating_add(found.len),
+                y: found.position.y,
+            };
+            !precedes(end, &found_end)
+        } else {
+            !precedes(&found.position, start)
+        };
+
+        if within {
+            Some(found)
+        } else {
+            None
+        }
+    }
+
     #[must_use]
     #[allow(clippy::indexing_slicing)]
-    pub fn find(&self, query: &str, at: &Position, direction: SearchDirection) -> Option<Position> {
+    pub fn find_from(&self, pattern: &Pattern, at: &Position, direction: SearchDirection) -> Option<Match> {
         if at.y >= self.len() {
             return None;
         }
@@ -180,9 +1584,9 @@ impl Document {
         };
         for _ in start..end {
             if let Some(row) = self.rows.get(position.y) {
-                if let Some(x) = row.find(&query, position.x, direction) {
+                if let Some((x, len)) = row.find_pattern(pattern, position.x, direction) {
                     position.x = x;
-                    return Some(position);
+                    return Some(Match { position, len });
                 }
                 if direction == SearchDirection::Forward {
                     position.y = position.y.saturating_add(1);
@@ -199,3 +1603,242 @@ impl Document {
         None
     }
 }
+
+// whether `contents` ends in a line that has no line ending; nothing at all doesn't
+fn lacks_final_newline(contents: &str) -> bool {
+    !contents.is_empty() && !contents.ends_with('\n')
+}
+
+fn precedes(a: &Position, b: &Position) -> bool {
+    (a.y, a.x) < (b.y, b.x)
+}
+
+// The definition `line` makes when, past any of the qualifiers, it opens with one of the keywords:
+// the line from the keyword up to where the body or a `where` clause starts
+fn definition(line: &str, keywords: &[&str], qualifiers: &[&str]) -> Option<String> {
+    let mut rest = line;
+    loop {
+        let word = rest.split_whitespace().next()?;
+        // `impl<T>` and `pub(crate)` run straight into what follows them
+        let is = |names: &[&str]| {
+            names.iter().any(|name| {
+                word.strip_prefix(name)
+                    .map_or(false, |tail| tail.is_empty() || tail.starts_with('<') || tail.starts_with('('))
+            })
+        };
+        if is(keywords) {
+            break;
+        }
+        // the ABI of an `extern "C"` is passed over along with the qualifier
+        if !is(qualifiers) && !word.starts_with('"') {
+            return None;
+        }
+        rest = rest.get(word.len()..)?.trim_start();
+    }
+
+    let end = rest.find('{').into_iter().chain(rest.find(" where")).min().unwrap_or_else(|| rest.len());
+    Some(rest.get(..end)?.trim_end().trim_end_matches(|c| c == ';' || c == ':').to_string())
+}
+
+// A NUL byte never shows up in text; failing that, text is mostly printable, so a sample where
+// more than 30% of the bytes are control characters is binary as well
+fn looks_binary(sample: &[u8]) -> bool {
+    if sample.contains(&0) {
+        return true;
+    }
+
+    let control_bytes = sample
+        .iter()
+        .filter(|byte| byte.is_ascii_control() && !b"\t\n\r\x0c\x1b".contains(byte))
+        .count();
+
+    control_bytes.saturating_mul(10) > sample.len().saturating_mul(3)
+}
+
+// Writes to a temporary file next to `path` and renames it over the original once everything
+// made it to disk, so a failed save never leaves a truncated file behind. The permissions (and on
+// Unix the owner) of the file being replaced are carried over, keeping e.g. scripts executable.
+pub(crate) fn write_atomically<F>(path: &str, write: F) -> Result<(), Error>
+where
+    F: FnOnce(&mut fs::File) -> Result<(), Error>,
+{
+    // write through symlinks instead of replacing them with a regular file
+    let path = fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path));
+    let original = fs::metadata(&path).ok();
+    let temp_path = temp_path_for(&path);
+
+    let result = fs::File::create(&temp_path).and_then(|mut file| {
+        write(&mut file)?;
+        file.sync_all()?;
+        if let Some(metadata) = &original {
+            copy_permissions(metadata, &temp_path)?;
+        }
+        fs::rename(&temp_path, &path)
+    });
+    if result.is_err() {
+        fs::remove_file(&temp_path).ok();
+    }
+
+    result
+}
+
+// Copies the file at `path` aside before it gets overwritten. With `keep` at 1 the copy is
+// `name~`; with more, the copies are numbered `name.~1~` (the newest) up to `name.~keep~`, and the
+// oldest one is dropped to make room. A file that doesn't exist yet has nothing to back up.
+pub(crate) fn back_up(path: &str, keep: usize) -> Result<(), Error> {
+    let path = fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path));
+    if keep == 0 || !path.exists() {
+        return Ok(());
+    }
+    if keep == 1 {
+        fs::copy(&path, backup_path_for(&path, None))?;
+        return Ok(());
+    }
+
+    let oldest = backup_path_for(&path, Some(keep));
+    if oldest.exists() {
+        fs::remove_file(oldest)?;
+    }
+    for number in (1..keep).rev() {
+        let backup = backup_path_for(&path, Some(number));
+        if backup.exists() {
+            fs::rename(backup, backup_path_for(&path, Some(number.saturating_add(1))))?;
+        }
+    }
+    fs::copy(&path, backup_path_for(&path, Some(1)))?;
+
+    Ok(())
+}
+
+fn backup_path_for(path: &Path, number: Option<usize>) -> PathBuf {
+    let file_name = path
+        .file_name()
+        .map_or_else(String::new, |name| name.to_string_lossy().into_owned());
+
+    match number {
+        Some(number) => path.with_file_name(format!("{}.~{}~", file_name, number)),
+        None => path.with_file_name(format!("{}~", file_name)),
+    }
+}
+
+fn temp_path_for(path: &Path) -> PathBuf {
+    let file_name = path
+        .file_name()
+        .map_or_else(String::new, |name| name.to_string_lossy().into_owned());
+
+    path.with_file_name(format!(".{}.sim-tmp", file_name))
+}
+
+fn modified_time(file_name: &str) -> Option<SystemTime> {
+    fs::metadata(file_name).and_then(|metadata| metadata.modified()).ok()
+}
+
+#[cfg(unix)]
+fn copy_permissions(metadata: &fs::Metadata, to: &Path) -> Result<(), Error> {
+    use std::os::unix::fs::{chown, MetadataExt, PermissionsExt};
+
+    fs::set_permissions(to, fs::Permissions::from_mode(metadata.mode()))?;
+    // only root (or the owner, for the group) may hand a file over, so this is best effort
+    chown(to, Some(metadata.uid()), Some(metadata.gid())).ok();
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn copy_permissions(metadata: &fs::Metadata, to: &Path) -> Result<(), Error> {
+    fs::set_permissions(to, metadata.permissions())
+}
+
+// Dumps the document the same way `save` writes it: every row followed by a line ending, except
+// the last one when the file had none there
+impl fmt::Display for Document {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let last = self.rows.len().saturating_sub(1);
+        for (y, row) in self.rows.iter().enumerate() {
+            let ending = if y < last || !self.missing_final_newline { self.line_ending.as_str() } else { "" };
+            write!(f, "{}{}", row, ending)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_final_newline_as_it_was() {
+        for contents in &["a\nb", "a\nb\n", "a\r\nb", "a\r\nb\r\n"] {
+            assert_eq!(Document::from_str(contents).to_string(), *contents);
+        }
+    }
+
+    #[test]
+    fn inserts_at_the_end_without_adding_a_newline() {
+        let mut document = Document::from_str("a\nb");
+        document.insert(&Position { x: 1, y: 1 }, 'c');
+        assert_eq!(document.to_string(), "a\nbc");
+
+        let mut document = Document::from_str("a\nb\n");
+        document.insert(&Position { x: 1, y: 1 }, 'c');
+        assert_eq!(document.to_string(), "a\nbc\n");
+    }
+
+    #[test]
+    fn deletes_at_the_end_without_touching_the_newline() {
+        let mut document = Document::from_str("a\nbc");
+        document.delete(&Position { x: 1, y: 1 });
+        assert_eq!(document.to_string(), "a\nb");
+        // past the end of the last row there is nothing left to delete
+        document.delete(&Position { x: 1, y: 1 });
+        assert_eq!(document.to_string(), "a\nb");
+
+        let mut document = Document::from_str("a\r\nbc\r\n");
+        document.delete(&Position { x: 1, y: 1 });
+        assert_eq!(document.to_string(), "a\r\nb\r\n");
+    }
+
+    fn rust(contents: &str) -> Document {
+        let mut document = Document::from_str(contents);
+        document.file_type = FileType::from("main.rs");
+        document
+    }
+
+    #[test]
+    fn reindents_with_tabs_or_spaces() {
+        let contents = "fn main() {\nif x {\n  y();\n}\n}\n";
+        let mut document = rust(contents);
+        assert_eq!(document.reindent(4, false), Some(3));
+        assert_eq!(document.to_string(), "fn main() {\n\tif x {\n\t\ty();\n\t}\n}\n");
+        assert_eq!(document.indents_with_tabs(), Some(true));
+
+        let mut document = rust(contents);
+        assert_eq!(document.reindent(4, true), Some(3));
+        assert_eq!(document.to_string(), "fn main() {\n    if x {\n        y();\n    }\n}\n");
+        assert_eq!(document.indents_with_tabs(), Some(false));
+    }
+}
+
+#[cfg(all(test, unix))]
+mod unix_tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn saving_keeps_the_mode() -> Result<(), Error> {
+        let path = std::env::temp_dir().join(format!("sim-mode-{}.sh", std::process::id()));
+        fs::write(&path, "echo hi\n")?;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755))?;
+
+        let mut document = Document::open(&path.to_string_lossy())?;
+        document.insert(&Position { x: 0, y: 0 }, '#');
+        let saved = document.save();
+        let mode = fs::metadata(&path).map(|metadata| metadata.permissions().mode() & 0o777);
+        fs::remove_file(&path)?;
+
+        saved?;
+        assert_eq!(mode?, 0o755);
+        Ok(())
+    }
+}
@@ -1,16 +1,61 @@
+use crate::Backend;
+use crate::Bell;
+use crate::Config;
+use crate::LineNumbers;
+use crate::MissingDirectories;
+use crate::Pattern;
+use crate::keymap::{self, Action, Lookup};
+use crate::SearchMode;
 use crate::Document;
+use crate::EditorConfig;
+use crate::HexDocument;
 use crate::Row;
+use crate::Selection;
+use crate::Severity;
+use crate::StreamDocument;
 use crate::Terminal;
+use crate::Theme;
+use crate::diagnostics;
+use crate::grep;
+use crate::diff::{self, DiffKind, DiffLine};
+use crate::document;
+use crate::hex::{self, HexPane};
+use crate::terminal::{FOCUS_OUT, MOUSE_OFF, MOUSE_ON, PASTE_END, PASTE_START, SHIFT_LEFT, SHIFT_RIGHT};
+use crate::theme::THEME_NAMES;
 
-use termion::event::Key;
+use termion::event::{Event, Key, MouseButton, MouseEvent};
 use termion::color;
+use termion::style;
+use std::collections::HashMap;
 use std::env;
+use std::fs;
+use std::io::{self, ErrorKind, Read};
+use std::iter;
+use std::mem;
+use std::path::{Path, PathBuf};
 use std::time::{Duration, Instant};
+use unicode_segmentation::UnicodeSegmentation;
 
-const VERSION: &str = env!("CARGO_PKG_VERSION");
-const STATUS_BG_COLOR: color::Rgb = color::Rgb(239, 239, 239);
-const STATUS_FG_COLOR: color::Rgb = color::Rgb(63, 63, 63);
-const QUIT_TIMES: u8 = 3;
+const UNNAMED_REGISTER: char = '"';
+// how long to wait for input before checking on the background highlighter again
+const HIGHLIGHT_POLL: Duration = Duration::from_millis(50);
+// how long to wait for input the rest of the time before checking whether the terminal was resized
+const RESIZE_POLL: Duration = Duration::from_millis(100);
+// how often to look at whether the open file was changed on disk by something else
+const DISK_CHECK: Duration = Duration::from_secs(1);
+// how big a document has to be for its save to show how far along it is
+const SAVE_PROGRESS_BYTES: u64 = 1024 * 1024;
+// how many rows a turn of the mouse wheel scrolls
+const WHEEL_LINES: usize = 3;
+// the commands whose argument is a file name, which Tab completes
+const PATH_COMMANDS: &[&str] = &["open", "save-copy"];
+const VIEW_HELP: &str = "VIEW: j/k = scroll | Space/b = page | g/G = top/bottom | / = search | q = quit";
+const FOLD_FG_COLOR: color::Rgb = color::Rgb(147, 161, 161);
+const DIFF_REMOVED_COLOR: color::Rgb = color::Rgb(220, 50, 47);
+const DIFF_ADDED_COLOR: color::Rgb = color::Rgb(133, 153, 0);
+const DIFF_CHANGED_COLOR: color::Rgb = color::Rgb(181, 137, 0);
+const ERROR_COLOR: color::Rgb = color::Rgb(220, 50, 47);
+const WARNING_COLOR: color::Rgb = color::Rgb(181, 137, 0);
 
 #[derive(PartialEq, Copy, Clone)]
 pub enum SearchDirection {
@@ -18,12 +63,101 @@ pub enum SearchDirection {
     Backward,
 }
 
-#[derive(Default, Clone)]
+#[derive(Default, Clone, PartialEq)]
 pub struct Position {
     pub x: usize,
     pub y: usize,
 }
 
+// The last edit made, kept so that it can be repeated at another spot. Consecutive keystrokes of
+// the same kind make up one change, so typing a word and repeating it inserts the whole word.
+#[derive(Clone)]
+enum Change {
+    Insert(String),
+    Delete(usize),
+    Backspace(usize),
+}
+
+// What Tab completes in a prompt: nothing, the whole input as a file name, or the file name
+// given to one of the `PATH_COMMANDS`
+#[derive(PartialEq, Copy, Clone)]
+enum Completion {
+    Nothing,
+    Path,
+    Command,
+}
+
+// How yanked text goes back in: at the cursor, as whole lines below the cursor's line, or as a
+// column of pieces starting at the cursor and going down
+#[derive(PartialEq, Copy, Clone)]
+enum RegisterKind {
+    Characterwise,
+    Linewise,
+    Blockwise,
+}
+
+// A document that is open but not being edited right now, along with where it was left
+#[derive(Default)]
+struct Buffer {
+    document: Document,
+    cursor_position: Position,
+    offset: Position,
+}
+
+// A read-only, side by side comparison of two buffers
+struct DiffView {
+    lines: Vec<DiffLine>,
+    // the first diff line on screen
+    top: usize,
+}
+
+// A list drawn over the text to pick from, every entry going to a line of the document
+struct Picker {
+    entries: Vec<(String, usize)>,
+    // the entries whose labels hold the typed text
+    shown: Vec<usize>,
+    // where in `shown` the picked entry is
+    selected: usize,
+}
+
+impl Picker {
+    fn new(entries: Vec<(String, usize)>, selected: usize) -> Self {
+        Self {
+            shown: (0..entries.len()).collect(),
+            entries,
+            selected,
+        }
+    }
+
+    // Keeps only the entries holding `query`, without regard to case, and picks the first of them
+    fn narrow(&mut self, query: &str) {
+        let query = query.to_lowercase();
+        let entries = &self.entries;
+        self.shown = (0..entries.len())
+            .filter(|&index| entries.get(index).map_or(false, |(label, _)| label.to_lowercase().contains(&query)))
+            .collect();
+        self.selected = 0;
+    }
+
+    fn step(&mut self, down: bool) {
+        self.selected = if down {
+            self.selected.saturating_add(1).min(self.shown.len().saturating_sub(1))
+        } else {
+            self.selected.saturating_sub(1)
+        };
+    }
+
+    fn line(&self) -> Option<usize> {
+        let index = self.shown.get(self.selected)?;
+        self.entries.get(*index).map(|(_, line)| *line)
+    }
+
+    // the first entry on screen, which scrolls just far enough to keep the picked one in view
+    fn top(&self, height: usize) -> usize {
+        self.selected.saturating_sub(height.saturating_sub(1))
+    }
+}
+
 struct StatusMessage {
     text: String,
     time: Instant,
@@ -38,54 +172,260 @@ impl StatusMessage {
     }
 }
 
-pub struct Editor {
+#[derive(PartialEq, Clone, Copy)]
+enum StatusSide {
+    Left,
+    Right,
+}
+
+// One indicator on the status bar. Left segments are joined with spaces, right ones with " | ".
+struct StatusSegment {
+    side: StatusSide,
+    text: String,
+}
+
+impl StatusSegment {
+    fn left(text: String) -> Self {
+        Self {
+            side: StatusSide::Left,
+            text,
+        }
+    }
+
+    fn right(text: String) -> Self {
+        Self {
+            side: StatusSide::Right,
+            text,
+        }
+    }
+}
+
+pub struct Editor<B: Backend = Terminal> {
     should_quit: bool,
-    terminal: Terminal,
+    terminal: B,
     document: Document,
     cursor_position: Position,
     // will keep track of what row of the file the user is currently scrolled to
     offset: Position,
     status_message: StatusMessage,
     quit_times: u8,
+    config: Config,
+    // set when a file is opened in hex mode; `document` stays empty meanwhile
+    hex: Option<HexDocument>,
+    hex_pane: HexPane,
+    // the high half of a byte being typed in the hex pane
+    hex_nibble: Option<u8>,
+    // set when a file is streamed instead of loaded; `document` stays empty meanwhile, and
+    // `offset` is the only position there is
+    stream: Option<StreamDocument>,
+    // set in view mode, where the document is paged through like with `less` and never changed
+    viewing: bool,
+    last_change: Option<Change>,
+    // whether the next edit still belongs to `last_change`
+    change_open: bool,
+    // typed characters replace the one under the cursor instead of pushing it right
+    overwrite: bool,
+    // every open buffer; the one at `active_buffer` is only a placeholder while its contents
+    // live in `document`, `cursor_position`, and `offset`
+    buffers: Vec<Buffer>,
+    active_buffer: usize,
+    // the buffer that was active before the current one
+    previous_buffer: usize,
+    diff: Option<DiffView>,
+    // search without regard to case, unless smart case overrides it
+    ignore_case: bool,
+    // where the selection was started and where it extends to; the two ends can be in either
+    // order
+    selection: Option<(Position, Position)>,
+    // the selection is the rectangle between its two ends rather than the text from one to the
+    // other
+    block_selection: bool,
+    // where a block insert started, and the last row it goes on to; what gets typed on the first
+    // row is copied to the others on Esc
+    block_insert: Option<(Position, usize)>,
+    // yanked text by register: `a` to `z`, and `UNNAMED_REGISTER` for yanks that don't name one
+    registers: HashMap<char, (String, RegisterKind)>,
+    show_line_numbers: bool,
+    show_whitespace: bool,
+    // the view was scrolled sideways on its own, and stays put until the cursor moves
+    free_scroll: bool,
+    // where Tab goes next within the snippet expanded last, in order
+    snippet_stops: Vec<Position>,
+    // shown instead of the text while something is being picked from it
+    picker: Option<Picker>,
+    // a row shown in place of the one at its index, with a replacement that is still being typed
+    replace_preview: Option<(usize, Row)>,
+    // how search queries are matched, switched with Ctrl-R while searching
+    search_mode: SearchMode,
+    // shown after what is being typed into the prompt, for the prompt's callback to say something
+    prompt_note: String,
+    // the row whose diagnostic the message bar shows, so it is only shown on getting there
+    shown_diagnostic: Option<usize>,
+    // what the last `grep` found, and the one of them gone to last
+    grep_matches: Vec<grep::Match>,
+    grep_index: usize,
+    // the keys of a chord pressed so far, while the rest of it is still to come
+    pending_keys: Vec<Key>,
+    // when the open file was last looked at for changes made behind our back
+    disk_checked: Instant,
 }
 
-impl Editor {
+impl Editor<Terminal> {
     pub fn default() -> Self {
-        let args: Vec<String> = env::args().collect();
-        let mut initial_status = String::from("HELP: Ctrl-Q = quit | Ctrl-Q = quit | Ctrl-F = find");
-        let document = if let Some(file_name) = args.get(1) {
-            let doc = Document::open(&file_name);
-            // if doc.is_ok() {
-            //     doc.unwrap()
-            // } else {
-            //     initial_status = format!("ERR: Could not open file: {}", file_name);
-            //     Document::default()
-            // }
-            if let Ok(unwrapped_doc) = doc {
-                unwrapped_doc
+        let args: Vec<String> = env::args().skip(1).collect();
+        let hex_mode = args.iter().any(|arg| arg == "--hex");
+        let stream_mode = args.iter().any(|arg| arg == "--stream");
+        let view_mode = args.iter().any(|arg| arg == "--view");
+        let (config, problems) = Config::load();
+        let mut editor = Self::with_config(
+            Terminal::default().expect("Failed to initialize terminal"),
+            Document::default(),
+            config,
+        );
+
+        let mut initial_status = editor.help(
+            "HELP",
+            &[],
+            &[(Action::Save, "save"), (Action::Quit, "quit"), (Action::Find, "find"), (Action::Command, "command")],
+        );
+        let mut file_names = args.iter().filter(|arg| !arg.starts_with("--")).peekable();
+        let mut streamed = false;
+        editor.viewing = view_mode && !hex_mode;
+        if editor.viewing {
+            initial_status = String::from(VIEW_HELP);
+        }
+        // with nothing to open, a pager shows what is piped into it
+        if view_mode && file_names.peek().is_none() && !termion::is_tty(&io::stdin()) {
+            let mut text = String::new();
+            if io::stdin().read_to_string(&mut text).is_ok() {
+                editor.document = Document::from_str(&text);
+            }
+        }
+        if let Some(file_name) = file_names.next() {
+            let too_big = editor.config.stream_above.map_or(false, |limit| {
+                fs::metadata(file_name).map_or(false, |metadata| metadata.len() > limit)
+            });
+            streamed = !hex_mode && (stream_mode || too_big);
+            let result = if hex_mode {
+                editor.open_hex(&file_name)
+            } else if streamed {
+                editor.open_stream(&file_name)
             } else {
-                initial_status = format!("ERR: Could not open file: {}", file_name);
-                Document::default()
+                editor.open(&file_name)
+            };
+
+            match result {
+                Ok(()) if hex_mode => initial_status = editor.hex_help(),
+                Ok(()) if streamed => {
+                    let actions = [(Action::Find, "find"), (Action::Quit, "quit")];
+                    initial_status = editor.help("READ-ONLY", &["Arrows = scroll"], &actions);
+                },
+                Ok(()) if editor.viewing => initial_status = String::from(VIEW_HELP),
+                Ok(()) => initial_status = opened_message(&editor.document),
+                // binary files can't be edited as text, so we show them as hex instead
+                Err(error) if error.kind() == ErrorKind::InvalidData => {
+                    initial_status = if editor.open_hex(&file_name).is_ok() {
+                        format!("Binary file, opened in hex mode. {}", editor.hex_help())
+                    } else {
+                        format!("ERR: Could not open file: {}", file_name)
+                    };
+                },
+                // a file that isn't there yet gets written, directories and all, on the first save
+                Err(error) if error.kind() == ErrorKind::NotFound && !hex_mode && !streamed => {
+                    editor.document.file_name = Some(file_name.to_string());
+                    initial_status = format!("New file: {}", file_name);
+                },
+                Err(_) => initial_status = format!("ERR: Could not open file: {}", file_name),
             }
-        } else {
-            Document::default()
-        };
+        }
+        // any further files wait in the background, hex mode and streaming only deal with a single one
+        if !hex_mode && !streamed {
+            for file_name in file_names {
+                if editor.open_buffer(file_name).is_err() {
+                    initial_status = format!("ERR: Could not open file: {}", file_name);
+                }
+            }
+            editor.switch_buffer(0);
+        }
+        // a broken setting matters more than the help, but not more than a file that didn't open
+        if let Some(problem) = problems.first().filter(|_| !initial_status.starts_with("ERR:")) {
+            initial_status = format!("ERR: {}", problem);
+        }
+        editor.status_message = StatusMessage::from(initial_status);
+
+        editor
+    }
+}
 
+impl<B: Backend> Editor<B> {
+    pub fn new(terminal: B, document: Document) -> Self {
+        Self::with_config(terminal, document, Config::default())
+    }
+
+    pub fn with_config(terminal: B, mut document: Document, config: Config) -> Self {
+        if config.warn_mixed_indentation {
+            document.set_warn_mixed_indentation(true);
+        }
+        if config.warn_suspicious_characters {
+            document.set_suspicious_characters(&config.suspicious_characters);
+        }
+        if !config.line_length_limits.is_empty() {
+            document.set_line_length_limits(&config.line_length_limits);
+        }
+        let show_line_numbers = config.line_numbers;
+        let show_whitespace = config.show_whitespace;
+        if config.mouse {
+            terminal.write(MOUSE_ON);
+        }
         Self {
             should_quit: false,
-            terminal: Terminal::default().expect("Failed to initialize terminal"),
+            terminal,
             document,
             cursor_position: Position::default(),
             offset: Position::default(),
-            status_message: StatusMessage::from(initial_status),
-            quit_times: QUIT_TIMES,
+            status_message: StatusMessage::from(String::new()),
+            quit_times: config.quit_times,
+            config,
+            hex: None,
+            hex_pane: HexPane::Bytes,
+            hex_nibble: None,
+            stream: None,
+            viewing: false,
+            last_change: None,
+            change_open: false,
+            overwrite: false,
+            buffers: vec![Buffer::default()],
+            active_buffer: 0,
+            previous_buffer: 0,
+            diff: None,
+            ignore_case: false,
+            selection: None,
+            block_selection: false,
+            block_insert: None,
+            registers: HashMap::new(),
+            show_line_numbers,
+            show_whitespace,
+            free_scroll: false,
+            snippet_stops: Vec::new(),
+            picker: None,
+            replace_preview: None,
+            search_mode: SearchMode::default(),
+            prompt_note: String::new(),
+            shown_diagnostic: None,
+            grep_matches: Vec::new(),
+            grep_index: 0,
+            pending_keys: Vec::new(),
+            disk_checked: Instant::now(),
         }
     }
 
     pub fn run(&mut self) {
         loop {
+            self.document.apply_highlights();
+            self.update_size();
+            self.check_disk();
             if let Err(error) = self.refresh_screen() {
-                die(error);
+                die(&self.terminal, error);
             }
 
             if self.should_quit {
@@ -93,42 +433,418 @@ impl Editor {
             }
 
             if let Err(error) = self.process_keypress() {
-                die(error);
+                die(&self.terminal, error);
+            }
+        }
+    }
+
+    #[must_use]
+    pub fn document(&self) -> &Document {
+        &self.document
+    }
+
+    #[must_use]
+    pub fn cursor_position(&self) -> &Position {
+        &self.cursor_position
+    }
+
+    #[must_use]
+    pub fn should_quit(&self) -> bool {
+        self.should_quit
+    }
+
+    #[must_use]
+    pub fn terminal(&self) -> &B {
+        &self.terminal
+    }
+
+    fn open(&mut self, file_name: &str) -> Result<(), std::io::Error> {
+        self.document = self.load(file_name)?;
+
+        Ok(())
+    }
+
+    // Opens `file_name` in a buffer of its own and switches to it
+    fn open_buffer(&mut self, file_name: &str) -> Result<(), std::io::Error> {
+        let document = self.load(file_name)?;
+        self.buffers.push(Buffer {
+            document,
+            ..Buffer::default()
+        });
+        self.switch_buffer(self.buffers.len().saturating_sub(1));
+
+        Ok(())
+    }
+
+    // Reads `file_name` in, redrawing a "Loading... N%" message as the file streams in
+    fn load(&mut self, file_name: &str) -> Result<Document, std::io::Error> {
+        let mut last_percent = None;
+        let mut document = Document::open_with_progress(file_name, |read, total| {
+            let percent = read.saturating_mul(100).checked_div(total).unwrap_or(100);
+            if last_percent != Some(percent) {
+                last_percent = Some(percent);
+                self.status_message = StatusMessage::from(format!("Loading... {}%", percent));
+                // a failed redraw only costs us the progress message, the load itself goes on
+                self.refresh_screen().ok();
+            }
+        })?;
+        if self.config.warn_mixed_indentation {
+            document.set_warn_mixed_indentation(true);
+        }
+        if self.config.warn_suspicious_characters {
+            document.set_suspicious_characters(&self.config.suspicious_characters);
+        }
+        if !self.config.line_length_limits.is_empty() {
+            document.set_line_length_limits(&self.config.line_length_limits);
+        }
+        if self.config.editorconfig {
+            document.set_editorconfig(EditorConfig::find(file_name));
+        }
+
+        Ok(document)
+    }
+
+    // Parks the active buffer and brings in the one at `index`
+    fn switch_buffer(&mut self, index: usize) {
+        if index == self.active_buffer || index >= self.buffers.len() {
+            return;
+        }
+
+        let current = Buffer {
+            document: mem::take(&mut self.document),
+            cursor_position: mem::take(&mut self.cursor_position),
+            offset: mem::take(&mut self.offset),
+        };
+        if let Some(slot) = self.buffers.get_mut(self.active_buffer) {
+            *slot = current;
+        }
+        if let Some(slot) = self.buffers.get_mut(index) {
+            let next = mem::take(slot);
+            self.document = next.document;
+            self.cursor_position = next.cursor_position;
+            self.offset = next.offset;
+        }
+
+        self.previous_buffer = self.active_buffer;
+        self.active_buffer = index;
+    }
+
+    // The buffer holding `file_name`, the active one included
+    fn buffer_for(&self, file_name: &str) -> Option<usize> {
+        let holds = |document: &Document| {
+            document.file_name.as_deref().map_or(false, |name| diagnostics::same_file(name, file_name))
+        };
+        if holds(&self.document) {
+            return Some(self.active_buffer);
+        }
+        self.buffers.iter().position(|buffer| holds(&buffer.document))
+    }
+
+    // Moves one buffer forward or backward, wrapping around at either end
+    fn cycle_buffer(&mut self, forward: bool) {
+        let count = self.buffers.len();
+        let index = if forward {
+            self.active_buffer.saturating_add(1) % count
+        } else {
+            self.active_buffer.checked_sub(1).unwrap_or_else(|| count.saturating_sub(1))
+        };
+        self.switch_buffer(index);
+    }
+
+    fn any_dirty(&self) -> bool {
+        self.is_dirty()
+            || self
+                .buffers
+                .iter()
+                .any(|buffer| buffer.document.is_dirty())
+    }
+
+    // Compares the buffer that was active before with the current one
+    fn show_diff(&mut self) {
+        let previous = match self.buffers.get(self.previous_buffer) {
+            Some(buffer) if self.previous_buffer != self.active_buffer => &buffer.document,
+            _ => {
+                self.status_message = StatusMessage::from("Need two buffers to diff.".to_string());
+                return;
+            },
+        };
+
+        self.diff = Some(DiffView {
+            lines: diff::diff(&lines_of(previous), &lines_of(&self.document)),
+            top: 0,
+        });
+        self.status_message = StatusMessage::from("DIFF: arrows/PageUp/PageDown = scroll | Esc = close".to_string());
+    }
+
+    fn process_diff_key(&mut self, key: Key) {
+        let height = self.text_height();
+        if let Some(view) = &mut self.diff {
+            let last = view.lines.len().saturating_sub(1);
+            match key {
+                Key::Esc | Key::Char('q') => self.diff = None,
+                Key::Up => view.top = view.top.saturating_sub(1),
+                Key::Down => view.top = view.top.saturating_add(1).min(last),
+                Key::PageUp => view.top = view.top.saturating_sub(height),
+                Key::PageDown => view.top = view.top.saturating_add(height).min(last),
+                Key::Home => view.top = 0,
+                Key::End => view.top = last,
+                _ => (),
+            }
+        }
+    }
+
+    fn open_stream(&mut self, file_name: &str) -> Result<(), std::io::Error> {
+        self.stream = Some(StreamDocument::open(file_name)?);
+        self.document = Document::default();
+
+        Ok(())
+    }
+
+    // A streamed file can only be scrolled through and searched
+    fn process_stream_key(&mut self, key: Key) {
+        match key {
+            Key::Ctrl('q') => self.should_quit = true,
+            Key::Ctrl('f') => self.search_stream(),
+            _ => (),
+        }
+
+        let height = self.text_height();
+        let top = self.offset.y;
+        if let Some(stream) = &self.stream {
+            match key {
+                Key::Up => self.offset.y = top.saturating_sub(1),
+                Key::PageUp => self.offset.y = top.saturating_sub(height),
+                Key::Down | Key::PageDown => {
+                    let step = if key == Key::Down { 1 } else { height };
+                    // the rows below have to be read to know whether they are there
+                    let _ = stream.row(top.saturating_add(step));
+                    self.offset.y = top.saturating_add(step).min(stream.len().saturating_sub(1));
+                },
+                Key::Home => self.offset.y = 0,
+                Key::End => self.offset.y = stream.count_rows().saturating_sub(height),
+                Key::Left => self.offset.x = self.offset.x.saturating_sub(1),
+                Key::Right => self.offset.x = self.offset.x.saturating_add(1),
+                Key::Char(_) | Key::Delete | Key::Backspace => {
+                    self.status_message = StatusMessage::from("The file is read-only.".to_string());
+                    self.alert();
+                },
+                _ => (),
             }
         }
     }
 
+    // In view mode the keys `less` uses page through the document, with the cursor kept on the top
+    // row, and nothing can change it
+    fn process_view_key(&mut self, key: Key) {
+        let height = self.text_height();
+        let top = self.offset.y;
+        let top = match key {
+            Key::Char('q') | Key::Ctrl('q') => {
+                self.should_quit = true;
+                return;
+            },
+            Key::Char('/') | Key::Ctrl('f') => {
+                self.search();
+                return;
+            },
+            Key::Left | Key::Right => {
+                self.scroll_sideways(key == Key::Right);
+                return;
+            },
+            Key::Char('j') | Key::Char('\n') | Key::Down => top.saturating_add(1),
+            Key::Char('k') | Key::Up => top.saturating_sub(1),
+            Key::Char(' ') | Key::PageDown => top.saturating_add(height),
+            Key::Char('b') | Key::PageUp => top.saturating_sub(height),
+            Key::Char('g') | Key::Home => 0,
+            Key::Char('G') | Key::End => usize::MAX,
+            _ => {
+                self.status_message = StatusMessage::from(VIEW_HELP.to_string());
+                self.alert();
+                return;
+            },
+        };
+
+        // like `less`, paging stops once the last row is at the bottom
+        let top = top.min(self.document.len().saturating_sub(height));
+        self.offset.y = top;
+        self.cursor_position = Position { x: self.offset.x, y: top };
+    }
+
+    // Looks for a query below the top row, which is taken as already seen, and scrolls the match
+    // to the top
+    fn search_stream(&mut self) {
+        let query = match self.prompt("Search (ESC to cancel): ", |_, _, _| {}).unwrap_or(None) {
+            Some(query) => query,
+            None => return,
+        };
+        // reading through a big file takes a while, and nothing else gets drawn meanwhile
+        self.status_message = StatusMessage::from(format!("Searching for {}...", query));
+        let _ = self.refresh_screen();
+
+        let at = Position { x: 0, y: self.offset.y.saturating_add(1) };
+        let width = self.terminal.size().width as usize;
+        match self.stream.as_ref().and_then(|stream| stream.find(&query, &at)) {
+            Some(found) => {
+                self.offset.y = found.y;
+                if found.x < self.offset.x || found.x >= self.offset.x.saturating_add(width) {
+                    self.offset.x = found.x.saturating_sub(width.saturating_sub(1));
+                }
+                self.status_message = StatusMessage::from(String::new());
+            },
+            None => {
+                self.status_message = StatusMessage::from(format!("No more matches for {}", query));
+                self.alert();
+            },
+        }
+    }
+
+    fn open_hex(&mut self, file_name: &str) -> Result<(), std::io::Error> {
+        self.hex = Some(HexDocument::open(file_name)?);
+        self.document = Document::default();
+
+        Ok(())
+    }
+
+    // The tab line only shows up once there is more than one buffer to tell apart
+    fn shows_tabline(&self) -> bool {
+        self.hex.is_none() && self.buffers.len() > 1
+    }
+
+    // How many rows of the screen the text gets, after the tab line if there is one
+    fn text_height(&self) -> usize {
+        let height = self.terminal.size().height as usize;
+        if self.shows_tabline() {
+            height.saturating_sub(1)
+        } else {
+            height
+        }
+    }
+
+    // Columns taken by the line numbers and the space after them, if they are shown
+    fn gutter_width(&self) -> usize {
+        if self.hex.is_some() || self.diff.is_some() {
+            return 0;
+        }
+        let numbers = if self.show_line_numbers {
+            let separator = self.config.gutter_separator.chars().count();
+            self.document.len().max(1).to_string().len().saturating_add(separator)
+        } else {
+            0
+        };
+        // a column for the sign of the worst diagnostic on the row, and a space after it
+        let signs = if self.document.has_diagnostics() { 2 } else { 0 };
+
+        numbers.saturating_add(signs)
+    }
+
+    // How many columns of the screen the text gets, after the gutter
+    fn text_width(&self) -> usize {
+        (self.terminal.size().width as usize).saturating_sub(self.gutter_width())
+    }
+
+    fn is_dirty(&self) -> bool {
+        self.hex.as_ref().map_or_else(|| self.document.is_dirty(), HexDocument::is_dirty)
+    }
+
+    // The furthest down the cursor may go. In text this is one past the last row, where new rows
+    // get appended, unless the config keeps it on the last row; in hex the cursor stays on the
+    // bytes that exist.
+    fn cursor_height(&self) -> usize {
+        self.hex.as_ref().map_or_else(
+            || {
+                if self.config.allow_cursor_past_eof {
+                    self.document.len()
+                } else {
+                    self.document.len().saturating_sub(1)
+                }
+            },
+            |hex| hex.len().saturating_sub(1),
+        )
+    }
+
+    // The furthest right the cursor may go on row `y`, with the same split as `cursor_height`
+    fn cursor_width(&self, y: usize) -> usize {
+        self.hex.as_ref().map_or_else(
+            || self.document.row(y).map_or(0, Row::len),
+            |hex| hex.row_len(y).saturating_sub(1),
+        )
+    }
+
+    // Pulls the cursor back inside the document after an edit that may have left it past the end
+    // of its row or of the document, then scrolls to it
+    fn clamp_cursor(&mut self) {
+        let y = self.cursor_position.y.min(self.cursor_height());
+        let x = self.cursor_position.x.min(self.cursor_width(y));
+        self.cursor_position = Position { x, y };
+        self.scroll();
+    }
+
     fn refresh_screen(&self) -> Result<(), std::io::Error> {
-        Terminal::cursor_hide();
-        Terminal::cursor_position(&Position::default());
+        self.terminal.cursor_hide();
+        self.terminal.cursor_position(&Position::default());
 
         if self.should_quit {
-            Terminal::clear_screen();
-            println!("Goodbye\r");
+            self.terminal.clear_screen();
+            self.terminal.write("Goodbye\r\n");
         } else {
+            if self.shows_tabline() {
+                self.draw_tabline();
+            }
             self.draw_rows();
 
             self.draw_status_bar();
             self.draw_message_bar();
 
             // Terminal::cursor_position(&self.cursor_position);
-            Terminal::cursor_position(&Position {
-                x: self.cursor_position.x.saturating_sub(self.offset.x),
-                y: self.cursor_position.y.saturating_sub(self.offset.y),
+            let x = if self.picker.is_some() {
+                0
+            } else if self.hex.is_some() {
+                // a half typed byte puts the cursor on its second digit
+                hex::screen_column(self.cursor_position.x, self.hex_pane)
+                    .saturating_add(self.hex_nibble.map_or(0, |_| 1))
+            } else {
+                // the cursor is kept as an index into the row's characters, but a tab takes up
+                // more than one column on screen
+                self.document
+                    .row(self.cursor_position.y)
+                    .map_or(0, |row| row.render_width(self.offset.x, self.cursor_position.x, self.tab_width()))
+                    .saturating_add(self.gutter_width())
+            };
+            let y = if let Some(picker) = &self.picker {
+                picker.selected.saturating_sub(picker.top(self.text_height()))
+            } else {
+                self.document.visible_between(self.offset.y, self.cursor_position.y)
+            };
+            self.terminal.cursor_position(&Position {
+                x,
+                y: if self.shows_tabline() { y.saturating_add(1) } else { y },
             });
         }
 
-        Terminal::cursor_show();
-        Terminal::flush()
+        self.terminal.cursor_show();
+        self.terminal.flush()
     }
 
     fn search(&mut self) {
         let old_position = self.cursor_position.clone();
+        let old_offset = self.offset.clone();
         let mut direction = SearchDirection::Forward;
+        // whether case was toggled by hand, which goes over smart case for the rest of the search
+        let mut case: Option<bool> = None;
+        self.prompt_note = search_note(self.search_mode, self.ignores_case(""), None);
         let query = self
-            .prompt("Search (ESC to cancel, Arrows to navigate): ", |editor, key, query| {
+            .prompt("Search (ESC to cancel, Arrows to navigate, Ctrl-R regex, Ctrl-I case): ", |editor, key, query| {
                 let mut moved =false;
                 match key {
+                    Key::Ctrl('r') => {
+                        editor.search_mode = editor.search_mode.toggled();
+                        direction = SearchDirection::Forward;
+                    },
+                    // a terminal sends Ctrl-I as a Tab
+                    Key::Ctrl('i') | Key::Char('\t') => {
+                        case = Some(!case.unwrap_or_else(|| editor.ignores_case(query)));
+                        direction = SearchDirection::Forward;
+                    },
                     Key::Right | Key::Down => {
                         direction = SearchDirection::Forward;
                         editor.move_cursor(Key::Right);
@@ -138,105 +854,1779 @@ impl Editor {
                     _ => direction = SearchDirection::Forward,
                 }
 
-                if let Some(position) = editor.document.find(&query, &editor.cursor_position, direction) {
-                    editor.cursor_position = position;
+                let ignore_case = case.unwrap_or_else(|| editor.ignores_case(query));
+                // a regular expression that doesn't compile yet, as it often won't halfway through
+                // typing it, is searched for as it is
+                let pattern = match Pattern::new(query, editor.search_mode, ignore_case) {
+                    Ok(pattern) => {
+                        editor.prompt_note = search_note(editor.search_mode, ignore_case, None);
+                        pattern
+                    },
+                    Err(error) => {
+                        editor.prompt_note = search_note(editor.search_mode, ignore_case, Some(&error));
+                        Pattern::plain(query, ignore_case)
+                    },
+                };
+                if let Some(found) = editor.document.find(&pattern, &editor.cursor_position, direction, None) {
+                    editor.cursor_position = found.position;
                     editor.scroll();
-                } else if moved {
-                    editor.move_cursor(Key::Left);
+                    editor.scroll_with_context(editor.config.search_context);
+                } else {
+                    if moved {
+                        editor.move_cursor(Key::Left);
+                    }
+                    if query.is_empty() {
+                        // with the query gone there is nothing left to have moved to
+                        editor.cursor_position = old_position.clone();
+                        editor.offset = old_offset.clone();
+                    } else {
+                        editor.alert();
+                    }
                 }
-                editor.document.highlight(Some(query));
+                editor.document.highlight_pattern(Some(&pattern));
             }).unwrap_or(None);
 
+        if let Some(case) = case {
+            self.ignore_case = case;
+        }
+        // however far the search went, cancelling it puts the cursor and the view back as they were
         if query.is_none() {
             self.cursor_position = old_position;
+            self.offset = old_offset;
             self.scroll();
         }
-        self.document.highlight(None);
+        self.document.highlight(None, false);
     }
 
-    #[allow(clippy::integer_arithmetic)]
-    fn process_keypress(&mut self) -> Result<(), std::io::Error> {
-        let pressed_key = Terminal::read_key()?;
-        match pressed_key {
-            Key::Ctrl('q') => {
-                if self.quit_times > 0 && self.document.is_dirty() {
-                    self.status_message = StatusMessage::from(format!(
-                            "WARNING! File has unsaved changes. Press Ctrl-Q {} more times to quit.",
-                            self.quit_times,
-                    ));
-
-                    self.quit_times -= 1;
-                    return Ok(());
+    // Replaces every match of a query, or every match within the selection if there is one. The
+    // matches light up as the query is typed, and the first one shows what it would become as the
+    // replacement is typed, but nothing changes until the replacement is confirmed.
+    fn replace(&mut self) {
+        let old_position = self.cursor_position.clone();
+        let old_offset = self.offset.clone();
+        let bounds = self.selection_bounds();
+        let start = bounds.as_ref().map_or_else(|| old_position.clone(), |(start, _)| start.clone());
+        let mut first = None;
+        let query = self
+            .prompt("Replace (ESC to cancel): ", |editor, _, query| {
+                let pattern = Pattern::plain(query, editor.ignores_case(query));
+                // the first match on from the cursor, or failing that from the top
+                first = editor
+                    .document
+                    .find(&pattern, &start, SearchDirection::Forward, bounds.as_ref())
+                    .or_else(|| {
+                        let top = Position::default();
+                        editor.document.find(&pattern, &top, SearchDirection::Forward, bounds.as_ref())
+                    });
+                if let Some(found) = &first {
+                    editor.cursor_position = found.position.clone();
+                    editor.scroll();
+                    editor.scroll_with_context(editor.config.search_context);
+                } else {
+                    editor.cursor_position = old_position.clone();
+                    editor.offset = old_offset.clone();
+                    if !query.is_empty() {
+                        editor.alert();
+                    }
                 }
+                editor.document.highlight_pattern(Some(&pattern));
+            }).unwrap_or(None);
 
-                self.should_quit = true;
-            },
-            Key::Ctrl('s') => self.save(),
-            Key::Ctrl('f') => self.search(),
-            Key::Char(c) => {
-                self.document.insert(&self.cursor_position, c);
-                self.move_cursor(Key::Right);
-            },
-            Key::Delete => self.document.delete(&self.cursor_position),
-            Key::Backspace => {
-                if self.cursor_position.x > 0 || self.cursor_position.y > 0 {
-                    self.move_cursor(Key::Left);
-                    self.document.delete(&self.cursor_position);
+        let (query, first) = match (query, first) {
+            (Some(query), Some(first)) => (query, first),
+            (query, _) => {
+                if let Some(query) = query {
+                    self.status_message = StatusMessage::from(format!("No matches for {}", query));
                 }
+                self.cursor_position = old_position;
+                self.offset = old_offset;
+                self.scroll();
+                self.document.highlight(None, false);
+                return;
             },
-            Key::Up
-            | Key::Down
-            | Key::Left
-            | Key::Right
-            | Key::PageUp
-            | Key::PageDown
-            | Key::End
-            | Key::Home => {
-                self.move_cursor(pressed_key);
-            },
-            _ => (),
-        }
+        };
 
-        self.scroll();
+        let pattern = Pattern::plain(&query, self.ignores_case(&query));
+        let at = &first.position;
+        self.replace_preview = self.document.preview_replace(at, first.len, "").map(|row| (at.y, row));
+        let with = self
+            .read_prompt(&format!("Replace {} with: ", query), Completion::Nothing, |editor, _, with| {
+                editor.replace_preview = editor.document.preview_replace(at, first.len, with).map(|row| (at.y, row));
+            }).unwrap_or(None);
+        self.replace_preview = None;
 
-        if self.quit_times < QUIT_TIMES {
-            self.quit_times = QUIT_TIMES;
-            self.status_message = StatusMessage::from(String::new());
+        if let Some(with) = with {
+            let with_len = with[..].graphemes(true).count();
+            let mut bounds = bounds;
+            let mut at = bounds.as_ref().map_or_else(Position::default, |(start, _)| start.clone());
+            let mut count: usize = 0;
+            // every match is asked about in turn, until "all" says to stop asking
+            let mut all = false;
+            while let Some(found) =
+                self.document.find(&pattern, &at, SearchDirection::Forward, bounds.as_ref())
+            {
+                let found_at = found.position;
+                if !all {
+                    match self.confirm_replace(&found_at, found.len, &with) {
+                        Some(Key::Char('y')) => (),
+                        Some(Key::Char('a')) => all = true,
+                        Some(Key::Char('n')) => {
+                            at = Position {
+                                x: found_at.x.saturating_add(found.len.max(1)),
+                                y: found_at.y,
+                            };
+                            continue;
+                        },
+                        _ => break,
+                    }
+                }
+                self.document.replace_at(&found_at, found.len, &with);
+                // the rest of the row shifts along with the new text, and the end of the range
+                // with it
+                if let Some((_, end)) = bounds.as_mut() {
+                    if end.y == found_at.y {
+                        end.x = end.x.saturating_add(with_len).saturating_sub(found.len);
+                    }
+                }
+                at = Position {
+                    x: found_at.x.saturating_add(with_len),
+                    y: found_at.y,
+                };
+                count = count.saturating_add(1);
+            }
+            self.cursor_position = first.position;
+            let plural = if count == 1 { "" } else { "es" };
+            self.status_message = StatusMessage::from(format!("Replaced {} match{}", count, plural));
+        } else {
+            self.cursor_position = old_position;
+            self.offset = old_offset;
         }
-
-        Ok(())
+        self.scroll();
+        self.document.highlight(None, false);
     }
 
-    fn save(&mut self) {
-        if self.document.file_name.is_none() {
-            let new_name = self.prompt("Save as: ", |_, _, _| {}).unwrap_or(None);
-            if new_name.is_none() {
-                self.status_message = StatusMessage::from("Save aborted.".to_string());
-                return;
-            }
+    // Shows what replacing the match at `at` would look like and asks about it, giving the key
+    // that was answered with, or `None` when the terminal couldn't be read
+    fn confirm_replace(&mut self, at: &Position, find_len: usize, with: &str) -> Option<Key> {
+        self.cursor_position = at.clone();
+        self.scroll();
+        self.scroll_with_context(self.config.search_context);
+        self.replace_preview = self.document.preview_replace(at, find_len, with).map(|row| (at.y, row));
+        self.status_message = StatusMessage::from("Replace this one? (y)es (n)o (a)ll (q)uit".to_string());
+        let key = self.refresh_screen().and_then(|_| self.terminal.read_key()).ok();
+        self.replace_preview = None;
+        key
+    }
 
-            self.document.file_name = new_name;
+    // With smart case on, the query itself decides: all lowercase ignores case, any uppercase
+    // letter respects it. Otherwise the ignore-case toggle does.
+    fn ignores_case(&self, query: &str) -> bool {
+        if self.config.smart_case {
+            !query.chars().any(char::is_uppercase)
+        } else {
+            self.ignore_case
         }
+    }
 
-        if self.document.save().is_ok() {
-            self.status_message = StatusMessage::from("File saved successfully.".to_string());
-        } else {
-            self.status_message = StatusMessage::from("Error writing to file!".to_string());
+    // Puts the cursor on a line, or with `line:col` on a column of it, both counted from 1 as
+    // compilers give them; past the end goes to the last one
+    fn goto_line(&mut self) {
+        let input = match self.prompt("Go to line: ", |_, _, _| {}).unwrap_or(None) {
+            Some(input) if !input.trim().is_empty() => input,
+            _ => return,
+        };
+
+        let mut parts = input.trim().splitn(2, ':');
+        let line = parts.next().map(str::parse::<usize>);
+        let column = parts.next().map(str::parse::<usize>);
+        match (line, column) {
+            (Some(Ok(line)), None) => self.cursor_position = Position { x: 0, y: line.saturating_sub(1) },
+            (Some(Ok(line)), Some(Ok(column))) => {
+                self.cursor_position = Position { x: column.saturating_sub(1), y: line.saturating_sub(1) };
+            },
+            _ => {
+                self.status_message = StatusMessage::from(format!("Not a line number: {}", input));
+                return;
+            },
         }
+        self.clamp_cursor();
     }
 
-    fn prompt<C>(&mut self, prompt: &str, mut callback: C) -> Result<Option<String>, std::io::Error>
-    where
-        C: FnMut(&mut Self, Key, &String),
-        {
-            let mut result = String::new();
-            loop {
-                self.status_message = StatusMessage::from(format!("{}{}", prompt, result));
+    /// # Errors
+    ///
+    #[allow(clippy::integer_arithmetic)]
+    pub fn process_keypress(&mut self) -> Result<(), std::io::Error> {
+        let pressed_key = match self.next_event()? {
+            Event::Key(key) => key,
+            // the read-only views have nowhere to put it, and none of it is meant as keypresses
+            Event::Unsupported(sequence) if sequence == PASTE_START && (self.stream.is_some() || self.diff.is_some()) => {
+                self.read_paste()?;
+                return Ok(());
+            },
+            Event::Unsupported(sequence) if sequence == PASTE_START && self.hex.is_none() && !self.viewing => {
+                let text = self.read_paste()?;
+                self.selection = None;
+                self.document.break_undo_group();
+                self.handle_paste(&text);
+                self.finish_keypress();
+                return Ok(());
+            },
+            Event::Unsupported(sequence)
+                if (sequence == SHIFT_LEFT || sequence == SHIFT_RIGHT) && self.hex.is_none() =>
+            {
+                self.scroll_sideways(sequence == SHIFT_RIGHT);
+                return Ok(());
+            },
+            Event::Mouse(event) if self.takes_mouse() => {
+                // a click moves the cursor, so typing after it starts a change of its own
+                self.change_open = false;
+                self.handle_mouse(event);
+                self.free_scroll = false;
+                self.finish_keypress();
+                return Ok(());
+            },
+            Event::Unsupported(sequence) if sequence == FOCUS_OUT => {
+                if self.config.autosave_on_focus_loss {
+                    self.autosave();
+                }
+                return Ok(());
+            },
+            _ => return Ok(()),
+        };
+        self.free_scroll = false;
+        if self.diff.is_some() {
+            self.process_diff_key(pressed_key);
+            return Ok(());
+        }
+        if self.stream.is_some() {
+            self.process_stream_key(pressed_key);
+            return Ok(());
+        }
+        if self.viewing {
+            self.process_view_key(pressed_key);
+            return Ok(());
+        }
+        if self.config.message_timeout == 0 {
+            self.status_message = StatusMessage::from(String::new());
+        }
+
+        if matches!(pressed_key, Key::Char(_) | Key::Delete | Key::Backspace) {
+            self.selection = None;
+        } else {
+            self.change_open = false;
+        }
+        // every keypress undoes on its own, but for the letters of a word typed one after the
+        // other, which undo with the whitespace that came before them
+        let typing_on = match (&pressed_key, &self.last_change) {
+            (Key::Char(c), Some(Change::Insert(text))) => {
+                self.change_open && !c.is_whitespace() && !text.ends_with('\n')
+            },
+            _ => false,
+        };
+        if !typing_on {
+            self.document.break_undo_group();
+        }
+
+        match self.lookup_key(pressed_key) {
+            Lookup::Action(Action::Quit) if self.warn_before_quit() => return Ok(()),
+            Lookup::Action(action) => self.perform(action),
+            Lookup::Prefix | Lookup::Swallowed => return Ok(()),
+            Lookup::Unbound => self.process_unbound_key(pressed_key),
+        }
+
+        self.finish_keypress();
+
+        Ok(())
+    }
+
+    // What the keys pressed so far are bound to, holding on to them while they are the start of
+    // a chord. A chord that turns out not to be bound is dropped as a whole.
+    fn lookup_key(&mut self, key: Key) -> Lookup {
+        self.pending_keys.push(key);
+        let lookup = self.config.keymap.lookup(&self.pending_keys);
+        if lookup == Lookup::Prefix {
+            self.status_message = StatusMessage::from(format!("{} -", keymap::describe(&self.pending_keys)));
+            return lookup;
+        }
+
+        let keys = mem::take(&mut self.pending_keys);
+        if lookup == Lookup::Unbound && keys.len() > 1 {
+            self.status_message = StatusMessage::from(format!("{} is not bound", keymap::describe(&keys)));
+            self.alert();
+            return Lookup::Swallowed;
+        }
+
+        lookup
+    }
+
+    // what to press for `action`, if anything is bound to it
+    fn keys_for(&self, action: Action) -> Option<String> {
+        self.config.keymap.keys_for(action).map(keymap::describe)
+    }
+
+    // A help line for the message bar: `mode`, what the keys outside the keymap in `fixed` do,
+    // and then the keys bound to `actions` now, leaving out the ones bound to nothing
+    fn help(&self, mode: &str, fixed: &[&str], actions: &[(Action, &str)]) -> String {
+        let bound = actions
+            .iter()
+            .filter_map(|(action, what)| self.keys_for(*action).map(|keys| format!("{} = {}", keys, what)));
+        let entries: Vec<String> = fixed.iter().copied().map(String::from).chain(bound).collect();
+
+        format!("{}: {}", mode, entries.join(" | "))
+    }
+
+    fn hex_help(&self) -> String {
+        self.help("HEX", &["Tab = switch pane"], &[(Action::Save, "save"), (Action::Quit, "quit")])
+    }
+
+    // Counts down the presses it takes to quit with unsaved changes, saying whether that still
+    // has a way to go
+    fn warn_before_quit(&mut self) -> bool {
+        if self.quit_times == 0 || !self.any_dirty() {
+            return false;
+        }
+        let keys = self.keys_for(Action::Quit).unwrap_or_else(|| "the quit key".to_string());
+        self.status_message = StatusMessage::from(format!(
+                "WARNING! File has unsaved changes. Press {} {} more times to quit.",
+                keys,
+                self.quit_times,
+        ));
+        self.quit_times -= 1;
+
+        true
+    }
+
+    #[allow(clippy::too_many_lines)]
+    fn perform(&mut self, action: Action) {
+        let text = self.hex.is_none();
+        match action {
+            Action::Quit => {
+                if self.config.remember_folds {
+                    self.save_folds();
+                }
+                self.should_quit = true;
+            },
+            Action::Save => self.save(),
+            Action::Command => self.command(),
+            Action::Find if text => self.search(),
+            Action::Replace if text => self.replace(),
+            Action::GotoLine if text => self.goto_line(),
+            Action::Undo if text => {
+                let restored = self.document.undo();
+                self.restore_cursor(restored, "Nothing to undo.");
+            },
+            Action::Redo if text => {
+                let restored = self.document.redo();
+                self.restore_cursor(restored, "Nothing to redo.");
+            },
+            Action::Copy if text => self.yank(UNNAMED_REGISTER),
+            Action::Cut if text => self.cut(),
+            Action::Paste if text => self.put(UNNAMED_REGISTER),
+            Action::RepeatChange if text => self.repeat_last_change(),
+            Action::Reload if text => self.reload(),
+            Action::ToggleOverwrite => self.overwrite = !self.overwrite,
+            Action::ToggleSelection if text => self.toggle_selection(),
+            Action::NextGrepMatch if text => self.step_grep_match(true),
+            Action::PreviousGrepMatch if text => self.step_grep_match(false),
+            Action::NextDiagnostic if text => self.jump_to_diagnostic(SearchDirection::Forward),
+            Action::PreviousDiagnostic if text => self.jump_to_diagnostic(SearchDirection::Backward),
+            Action::Increment if text => self.add_to_number(1),
+            Action::Decrement if text => self.add_to_number(-1),
+            Action::ToggleLineNumbers => self.toggle_line_numbers(),
+            Action::ToggleAutoIndent => self.toggle_auto_indent(),
+            Action::ToggleFold if text => {
+                if !self.document.toggle_fold(self.cursor_position.y) {
+                    self.status_message = StatusMessage::from("No fold here.".to_string());
+                }
+            },
+            Action::MoveUp => self.move_with_selection(Key::Up),
+            Action::MoveDown => self.move_with_selection(Key::Down),
+            Action::MoveLeft => self.move_with_selection(Key::Left),
+            Action::MoveRight => self.move_with_selection(Key::Right),
+            Action::PageUp => self.move_with_selection(Key::PageUp),
+            Action::PageDown => self.move_with_selection(Key::PageDown),
+            Action::Home => self.move_with_selection(Key::Home),
+            Action::End => self.move_with_selection(Key::End),
+            _ => (),
+        }
+    }
+
+    // the keys that do what they do wherever they are, like typing, rather than through the keymap
+    #[allow(clippy::integer_arithmetic)]
+    fn process_unbound_key(&mut self, pressed_key: Key) {
+        match pressed_key {
+            Key::Char(c) if self.hex.is_some() => self.hex_insert(c),
+            // bytes can only be overwritten in hex mode, never inserted or removed
+            Key::Delete | Key::Backspace if self.hex.is_some() => self.alert(),
+            Key::Esc if self.block_insert.is_some() => self.finish_block_insert(),
+            Key::Esc => self.selection = None,
+            Key::Alt(c @ '1'..='9') if self.shows_tabline() => {
+                let index = c.to_digit(10).map_or(0, |digit| digit as usize).saturating_sub(1);
+                self.switch_buffer(index);
+            },
+            Key::Char('\t') if !self.snippet_stops.is_empty() => {
+                self.cursor_position = self.snippet_stops.remove(0);
+            },
+            Key::Char('\t') if self.expand_snippet() => (),
+            Key::Char('\t') if self.config.tab_indents && self.in_indentation() => self.indent_row(true),
+            Key::BackTab if self.config.tab_indents && self.hex.is_none() => self.indent_row(false),
+            Key::Char(c) => {
+                let before = self.cursor_position.clone();
+                let Position { x, y } = self.cursor_position;
+                let at_end = x >= self.document.row(y).map_or(0, Row::len);
+                if self.overwrite && c != '\n' && !at_end {
+                    self.document.delete(&self.cursor_position);
+                    self.snippet_stops.clear();
+                }
+                let auto_indent = self.config.auto_indent && self.block_insert.is_none();
+                if auto_indent && "})]".contains(c) && self.document.bracket_blocks() && self.in_indentation() {
+                    self.indent_row(false);
+                }
+                let text = if c == '\t' && self.soft_tabs() {
+                    let width = self.tab_width().max(1);
+                    " ".repeat(width.saturating_sub(x % width))
+                } else {
+                    c.to_string()
+                };
+                for c in text.chars() {
+                    self.insert_char(c);
+                }
+                if auto_indent && c == '\n' {
+                    self.carry_indentation(y, x);
+                }
+                self.record_change(Change::Insert(text));
+                self.move_snippet_stops(&before);
+            },
+            Key::Delete => {
+                self.document.delete(&self.cursor_position);
+                self.record_change(Change::Delete(1));
+                self.snippet_stops.clear();
+            },
+            Key::Backspace => {
+                let before = self.cursor_position.clone();
+                self.backspace();
+                self.record_change(Change::Backspace(1));
+                self.move_snippet_stops(&before);
+            },
+            _ => (),
+        }
+    }
+
+    // Moves the cursor with one of the movement keys, taking the head of the selection with it
+    fn move_with_selection(&mut self, key: Key) {
+        self.hex_nibble = None;
+        self.move_cursor(key);
+        if let Some((_, end)) = &mut self.selection {
+            *end = self.cursor_position.clone();
+        }
+    }
+
+    // Waits for input, redrawing whenever the background highlighter finishes more rows, the
+    // terminal is resized or the file changes on disk meanwhile
+    fn next_event(&mut self) -> Result<Event, std::io::Error> {
+        loop {
+            let timeout = if self.document.is_highlighting() { HIGHLIGHT_POLL } else { RESIZE_POLL };
+            if let Some(event) = self.terminal.poll_event(timeout)? {
+                return Ok(event);
+            }
+            let highlighted = self.document.apply_highlights();
+            let changed = self.check_disk();
+            if self.update_size() || highlighted || changed {
+                self.refresh_screen()?;
+            }
+        }
+    }
+
+    // Picks up a new terminal size, scrolling so the cursor is still on the screen after it
+    // shrank. Says whether the size changed.
+    fn update_size(&mut self) -> bool {
+        if !self.terminal.update_size() {
+            return false;
+        }
+        self.scroll();
+        true
+    }
+
+    // Says so in the status bar when something else wrote to the open file, once per change and
+    // no more often than `DISK_CHECK`. Says whether it did.
+    fn check_disk(&mut self) -> bool {
+        if self.disk_checked.elapsed() < DISK_CHECK {
+            return false;
+        }
+        self.disk_checked = Instant::now();
+        if !self.document.has_changed_on_disk() {
+            return false;
+        }
+
+        self.document.mark_disk_change_seen();
+        let file_name = self.document.file_name.clone().unwrap_or_default();
+        let how = match self.config.keymap.keys_for(Action::Reload) {
+            Some(keys) => format!("{} reloads it", keymap::describe(keys)),
+            None => "the reload command reloads it".to_string(),
+        };
+        self.status_message = StatusMessage::from(format!("{} changed on disk. {}.", file_name, how));
+        true
+    }
+
+    fn finish_keypress(&mut self) {
+        // whatever the key did to the document, the cursor has to end up somewhere in it
+        self.clamp_cursor();
+        self.highlight_word_under_cursor();
+        self.show_diagnostic();
+        let selection = self.selection.as_ref().map(|(anchor, head)| Selection {
+            anchor: anchor.clone(),
+            head: head.clone(),
+            block: self.block_selection,
+        });
+        self.document.note_selection(selection);
+
+        if self.quit_times < self.config.quit_times {
+            self.quit_times = self.config.quit_times;
+            self.status_message = StatusMessage::from(String::new());
+        }
+    }
+
+    // Marks the other occurrences of the word under the cursor. The document is only highlighted
+    // again when the cursor reaches a different word, so moving within a word doesn't flicker.
+    fn highlight_word_under_cursor(&mut self) {
+        if self.hex.is_some() {
+            return;
+        }
+
+        let Position { x, y } = self.cursor_position;
+        let word = self.document.row(y).and_then(|row| row.word_at(x));
+        if word.as_deref() != self.document.occurrence() {
+            self.document.highlight_occurrences(word.as_deref());
+        }
+    }
+
+    // Collects everything up to the end of a bracketed paste
+    fn read_paste(&mut self) -> Result<String, std::io::Error> {
+        let mut text = String::new();
+        loop {
+            match self.terminal.read_event()? {
+                Event::Key(Key::Char(c)) => text.push(c),
+                Event::Unsupported(sequence) if sequence == PASTE_END => break,
+                _ => (),
+            }
+        }
+
+        Ok(text)
+    }
+
+    // Inserts pasted text at the cursor as it came, without treating any of it as a keypress
+    pub fn handle_paste(&mut self, text: &str) {
+        let text = if self.config.reindent_paste {
+            let indentation = self
+                .document
+                .row(self.cursor_position.y)
+                .map_or("", Row::indentation);
+            reindent(text, indentation)
+        } else {
+            text.to_string()
+        };
+
+        for c in text.chars() {
+            self.insert_char(c);
+        }
+        // a paste is a change of its own, neither joining the typing before it nor the one after
+        self.change_open = false;
+        self.record_change(Change::Insert(text));
+        self.change_open = false;
+        // the cursor may have moved many lines away in one go
+        self.scroll();
+    }
+
+    // Replaces the word before the cursor with the snippet it triggers, if it is a trigger. The
+    // cursor goes to the first tab stop, and Tab takes it on to the others.
+    fn expand_snippet(&mut self) -> bool {
+        let Position { x, y } = self.cursor_position;
+        let before: String = self.document.row(y).map_or_else(String::new, |row| row.slice(0, x));
+        let trigger: String = before
+            .chars()
+            .rev()
+            .take_while(|c| c.is_alphanumeric() || *c == '_')
+            .collect::<Vec<char>>()
+            .into_iter()
+            .rev()
+            .collect();
+        if trigger.is_empty() {
+            return false;
+        }
+        let snippets = &self.config.snippets;
+        let expansion = match [self.document.file_type(), "*".to_string()]
+            .iter()
+            .find_map(|file_type| snippets.get(file_type)?.get(&trigger))
+        {
+            Some(expansion) => expansion.clone(),
+            None => return false,
+        };
+
+        let indentation = self.document.row(y).map_or_else(String::new, |row| row.indentation().to_string());
+        for _ in trigger.chars() {
+            self.move_cursor(Key::Left);
+            self.document.delete(&self.cursor_position);
+        }
+        // each stop is kept with its number, `$0` sorting after the rest
+        let mut stops: Vec<(u32, Position)> = Vec::new();
+        let mut chars = expansion.chars().peekable();
+        while let Some(c) = chars.next() {
+            if c == '$' {
+                if let Some(number) = chars.peek().and_then(|next| next.to_digit(10)) {
+                    chars.next();
+                    let order = if number == 0 { u32::MAX } else { number };
+                    stops.push((order, self.cursor_position.clone()));
+                    continue;
+                }
+            }
+            self.insert_char(c);
+            // the lines after the first line up with it
+            if c == '\n' {
+                for c in indentation.chars() {
+                    self.insert_char(c);
+                }
+            }
+        }
+
+        stops.sort_by_key(|(order, _)| *order);
+        self.snippet_stops = stops.into_iter().map(|(_, position)| position).collect();
+        if !self.snippet_stops.is_empty() {
+            self.cursor_position = self.snippet_stops.remove(0);
+        }
+        true
+    }
+
+    // Keeps the remaining snippet stops on the cursor's row in place as text goes in or out
+    // before them. An edit that leaves the row gives up on them.
+    fn move_snippet_stops(&mut self, before: &Position) {
+        let after = self.cursor_position.clone();
+        if after.y != before.y {
+            self.snippet_stops.clear();
+            return;
+        }
+        for stop in self.snippet_stops.iter_mut().filter(|stop| stop.y == after.y) {
+            if stop.x >= before.x {
+                stop.x = stop.x.saturating_add(after.x).saturating_sub(before.x);
+            } else if stop.x > after.x {
+                stop.x = after.x;
+            }
+        }
+    }
+
+    fn insert_char(&mut self, c: char) {
+        self.document.insert(&self.cursor_position, c);
+        self.move_cursor(Key::Right);
+    }
+
+    // Indents the row just split off of row `y` at `x` as deep as row `y`, as far as its
+    // indentation went before `x`
+    fn carry_indentation(&mut self, y: usize, x: usize) {
+        let indentation: String = self
+            .document
+            .row(y)
+            .map_or_else(String::new, |row| row.indentation().chars().take(x).collect());
+        for c in indentation.chars() {
+            self.insert_char(c);
+        }
+    }
+
+    fn backspace(&mut self) {
+        // with soft tabs, spaces that are all there is before the cursor go back to the previous
+        // tab stop; a space after anything else is only one
+        let Position { x, y } = self.cursor_position;
+        let spaces_before = self
+            .document
+            .row(y)
+            .map_or(false, |row| row.indentation().chars().take(x).filter(|&c| c == ' ').count() == x);
+        if self.soft_tabs() && x > 0 && spaces_before {
+            let width = self.tab_width().max(1);
+            self.cursor_position.x = x.saturating_sub(x.saturating_sub(1) % width).saturating_sub(1);
+            for _ in self.cursor_position.x..x {
+                self.document.delete(&self.cursor_position);
+            }
+            return;
+        }
+
+        // on a line holding nothing but indentation, the whole indentation before the cursor goes
+        // at once
+        let only_indentation = self
+            .document
+            .row(y)
+            .map_or(false, |row| row.indentation().len() == row.len());
+        if x > 0 && only_indentation {
+            self.cursor_position.x = 0;
+            for _ in 0..x {
+                self.document.delete(&self.cursor_position);
+            }
+            return;
+        }
+
+        if self.cursor_position.x > 0 || self.cursor_position.y > 0 {
+            self.move_cursor(Key::Left);
+            self.document.delete(&self.cursor_position);
+        } else {
+            self.alert();
+        }
+    }
+
+    // Puts the cursor where an undo or redo left off, kept within the document, and brings back
+    // the selection the edits were made with, the cursor going to its head
+    fn restore_cursor(&mut self, restored: Option<(Position, Option<Selection>)>, nothing: &str) {
+        self.selection = None;
+        match restored {
+            Some((_, Some(selection))) => {
+                self.cursor_position = selection.head.clone();
+                self.block_selection = selection.block;
+                self.selection = Some((selection.anchor, selection.head));
+                self.clamp_cursor();
+            },
+            Some((position, None)) => {
+                self.cursor_position = position;
+                self.clamp_cursor();
+            },
+            None => self.status_message = StatusMessage::from(nothing.to_string()),
+        }
+    }
+
+    // spaces per indentation level, as the `.editorconfig` or else the config has it
+    fn tab_width(&self) -> usize {
+        self.document.editorconfig().tab_width.unwrap_or(self.config.tab_width)
+    }
+
+    fn soft_tabs(&self) -> bool {
+        self.document.editorconfig().soft_tabs.unwrap_or(self.config.soft_tabs)
+    }
+
+    // whether the cursor is in its row's leading whitespace, or right after it
+    fn in_indentation(&self) -> bool {
+        let Position { x, y } = self.cursor_position;
+        self.document.row(y).map_or(false, |row| x <= row.indentation().len())
+    }
+
+    // Adds a level of indentation to the cursor's row, or takes one off, with the cursor staying
+    // on the same character. A level is a tab in rows indented with tabs, and `tab_width` spaces
+    // in any other row.
+    fn indent_row(&mut self, indent: bool) {
+        let y = self.cursor_position.y;
+        let indentation = match self.document.row(y) {
+            Some(row) => row.indentation().to_string(),
+            None => return,
+        };
+        let level = if indentation.starts_with('\t') {
+            "\t".to_string()
+        } else {
+            " ".repeat(self.tab_width())
+        };
+
+        let at = Position { x: 0, y };
+        if indent {
+            self.document.replace_at(&at, 0, &level);
+            self.cursor_position.x = self.cursor_position.x.saturating_add(level.len());
+        } else {
+            let removed = if indentation.starts_with('\t') {
+                1
+            } else {
+                indentation.len().min(self.tab_width())
+            };
+            if removed == 0 {
+                return;
+            }
+            self.document.replace_at(&at, removed, "");
+            self.cursor_position.x = self.cursor_position.x.saturating_sub(removed);
+        }
+    }
+
+    // Tells the user that an action had no effect, the way the config asks for
+    fn alert(&self) {
+        match self.config.bell {
+            Bell::Audible => self.terminal.bell(),
+            Bell::Visual => self.terminal.flash(),
+            Bell::Off => (),
+        }
+    }
+
+    fn record_change(&mut self, change: Change) {
+        match (&mut self.last_change, change) {
+            (Some(Change::Insert(text)), Change::Insert(more)) if self.change_open => {
+                text.push_str(&more);
+            },
+            (Some(Change::Delete(count)), Change::Delete(more))
+            | (Some(Change::Backspace(count)), Change::Backspace(more))
+                if self.change_open =>
+            {
+                *count = count.saturating_add(more);
+            },
+            (_, change) => self.last_change = Some(change),
+        }
+        self.change_open = true;
+    }
+
+    // Applies `last_change` again at the cursor, like Vim's `.`
+    fn repeat_last_change(&mut self) {
+        match self.last_change.clone() {
+            Some(Change::Insert(text)) => {
+                for c in text.chars() {
+                    self.insert_char(c);
+                }
+            },
+            Some(Change::Delete(count)) => {
+                for _ in 0..count {
+                    self.document.delete(&self.cursor_position);
+                }
+            },
+            Some(Change::Backspace(count)) => {
+                for _ in 0..count {
+                    self.backspace();
+                }
+            },
+            None => (),
+        }
+        self.scroll();
+    }
+
+    // Tab switches between the hex and the ASCII pane; anything else overwrites the byte under the
+    // cursor, either one hex digit at a time or as an ASCII character
+    fn hex_insert(&mut self, c: char) {
+        if c == '\t' {
+            self.hex_pane = match self.hex_pane {
+                HexPane::Bytes => HexPane::Ascii,
+                HexPane::Ascii => HexPane::Bytes,
+            };
+            self.hex_nibble = None;
+            return;
+        }
+
+        let index = self
+            .cursor_position
+            .y
+            .saturating_mul(hex::BYTES_PER_ROW)
+            .saturating_add(self.cursor_position.x);
+        let mut written = false;
+        if let Some(hex) = &mut self.hex {
+            match self.hex_pane {
+                HexPane::Bytes => {
+                    #[allow(clippy::cast_possible_truncation)]
+                    if let Some(digit) = c.to_digit(16).map(|digit| digit as u8) {
+                        if let Some(high) = self.hex_nibble.take() {
+                            hex.set_byte(index, high << 4_u8 | digit);
+                            written = true;
+                        } else {
+                            self.hex_nibble = Some(digit);
+                        }
+                    }
+                },
+                HexPane::Ascii => {
+                    if c.is_ascii() && !c.is_ascii_control() {
+                        #[allow(clippy::cast_possible_truncation)]
+                        hex.set_byte(index, c as u8);
+                        written = true;
+                    }
+                },
+            }
+        }
+
+        if written {
+            self.move_cursor(Key::Right);
+        }
+    }
+
+    // Reads a command name (and its arguments) on the message bar and runs it
+    fn command(&mut self) {
+        let input = self.prompt_completing(":", Completion::Command).unwrap_or(None);
+        let input = if let Some(input) = input {
+            input
+        } else {
+            return;
+        };
+
+        let mut words = input.split_whitespace();
+        match words.next() {
+            Some("info") => self.info(),
+            Some("open") if self.hex.is_none() => {
+                let file_name = words.collect::<Vec<&str>>().join(" ");
+                let file_name = self.document.resolve(&file_name);
+                self.status_message = if self.open_buffer(&file_name).is_ok() {
+                    StatusMessage::from(opened_message(&self.document))
+                } else {
+                    StatusMessage::from(format!("ERR: Could not open file: {}", file_name))
+                };
+            },
+            Some("next") => self.cycle_buffer(true),
+            Some("prev") => self.cycle_buffer(false),
+            Some("diff") if self.hex.is_none() => self.show_diff(),
+            Some("reload") if self.hex.is_none() => self.reload(),
+            Some("number") => self.toggle_line_numbers(),
+            Some("whitespace") => {
+                self.show_whitespace = !self.show_whitespace;
+                let state = if self.show_whitespace { "on" } else { "off" };
+                self.status_message = StatusMessage::from(format!("Whitespace: {}", state));
+            },
+            Some("cursorline") => {
+                self.config.highlight_cursor_line = !self.config.highlight_cursor_line;
+                let state = if self.config.highlight_cursor_line { "on" } else { "off" };
+                self.status_message = StatusMessage::from(format!("Cursor line: {}", state));
+            },
+            Some("cursorcolumn") => {
+                self.config.highlight_cursor_column = !self.config.highlight_cursor_column;
+                let state = if self.config.highlight_cursor_column { "on" } else { "off" };
+                self.status_message = StatusMessage::from(format!("Cursor column: {}", state));
+            },
+            Some("save-copy") if self.hex.is_none() => {
+                let path = words.collect::<Vec<&str>>().join(" ");
+                self.save_copy(path);
+            },
+            Some("insert") if self.hex.is_none() => self.start_block_insert(),
+            Some("block") if self.hex.is_none() => {
+                self.selection = Some((self.cursor_position.clone(), self.cursor_position.clone()));
+                self.block_selection = true;
+            },
+            Some(command @ ("yank" | "put")) if self.hex.is_none() => {
+                let name = match words.next() {
+                    None => UNNAMED_REGISTER,
+                    Some(name) => match name.chars().next() {
+                        Some(c @ 'a'..='z') if name.len() == 1 => c,
+                        _ => {
+                            self.status_message = StatusMessage::from(format!("No register {}, use a to z", name));
+                            return;
+                        },
+                    },
+                };
+                if command == "yank" {
+                    self.yank(name);
+                } else {
+                    self.put(name);
+                }
+            },
+            Some("reindent") if self.hex.is_none() => self.reindent(),
+            Some("add") if self.hex.is_none() => match words.next().map(str::parse) {
+                Some(Ok(amount)) => self.add_to_number(amount),
+                _ => self.status_message = StatusMessage::from("Add how much?".to_string()),
+            },
+            Some("reverse") if self.hex.is_none() => self.reverse_lines(),
+            Some("unique") if self.hex.is_none() => match words.next() {
+                None => self.unique_lines(false),
+                Some("adjacent") => self.unique_lines(true),
+                Some(word) => self.status_message = StatusMessage::from(format!("Unknown unique mode: {}", word)),
+            },
+            Some("grep") if self.hex.is_none() => {
+                let pattern = words.collect::<Vec<&str>>().join(" ");
+                if pattern.is_empty() {
+                    self.status_message = StatusMessage::from("Grep for what?".to_string());
+                } else {
+                    self.grep(&pattern);
+                }
+            },
+            Some("lint") if self.hex.is_none() => {
+                let output_file = words.collect::<Vec<&str>>().join(" ");
+                let message = self.lint(Some(output_file.as_str()).filter(|name| !name.is_empty()));
+                self.status_message = StatusMessage::from(message);
+            },
+            Some("outline") if self.hex.is_none() => self.outline(),
+            Some("replace") if self.hex.is_none() => self.replace(),
+            Some("comment") if self.hex.is_none() => self.toggle_block_comment(),
+            Some("fold") if self.hex.is_none() => self.fold(),
+            Some("unfold") if self.hex.is_none() => {
+                if !self.document.unfold(self.cursor_position.y) {
+                    self.status_message = StatusMessage::from("No fold here.".to_string());
+                }
+            },
+            Some("theme") => self.switch_theme(words.next()),
+            Some("ignorecase") => {
+                self.ignore_case = !self.ignore_case;
+                let state = if self.ignore_case { "on" } else { "off" };
+                self.status_message = StatusMessage::from(format!("Ignore case: {}", state));
+            },
+            Some("smartcase") => {
+                self.config.smart_case = !self.config.smart_case;
+                let state = if self.config.smart_case { "on" } else { "off" };
+                self.status_message = StatusMessage::from(format!("Smart case: {}", state));
+            },
+            Some("auto-indent") => self.toggle_auto_indent(),
+            Some("soft-tabs") => {
+                // over whatever the `.editorconfig` said too
+                self.config.soft_tabs = !self.soft_tabs();
+                let mut settings = self.document.editorconfig().clone();
+                settings.soft_tabs = None;
+                self.document.set_editorconfig(settings);
+                let state = if self.config.soft_tabs { "on" } else { "off" };
+                self.status_message = StatusMessage::from(format!("Soft tabs: {}", state));
+            },
+            Some("mouse") => {
+                self.config.mouse = !self.config.mouse;
+                self.terminal.write(if self.config.mouse { MOUSE_ON } else { MOUSE_OFF });
+                let state = if self.config.mouse { "on" } else { "off" };
+                self.status_message = StatusMessage::from(format!("Mouse: {}", state));
+            },
+            Some("reindent-paste") => {
+                self.config.reindent_paste = !self.config.reindent_paste;
+                let state = if self.config.reindent_paste { "on" } else { "off" };
+                self.status_message = StatusMessage::from(format!("Reindent on paste: {}", state));
+            },
+            Some("final-newline") if self.hex.is_none() => {
+                let final_newline = !self.document.has_final_newline();
+                self.document.set_final_newline(final_newline);
+                let state = if final_newline { "on" } else { "off" };
+                self.status_message = StatusMessage::from(format!("Final newline: {}", state));
+            },
+            Some(name) => {
+                self.status_message = StatusMessage::from(format!("Unknown command: {}", name));
+            },
+            None => (),
+        }
+    }
+
+    // Lists the definitions or headings of the document in place of the text. Typing narrows the
+    // list down, Up and Down go through it, and Enter moves to the line of the picked entry.
+    fn outline(&mut self) {
+        let entries = self.document.outline();
+        if entries.is_empty() {
+            self.status_message = StatusMessage::from("Nothing to outline.".to_string());
+            return;
+        }
+
+        // the list starts out on the entry the cursor is under
+        let y = self.cursor_position.y;
+        let selected = entries.iter().rposition(|(_, line)| *line <= y).unwrap_or(0);
+        self.picker = Some(Picker::new(entries, selected));
+        let input = self
+            .read_prompt("Outline (ESC to cancel, Up/Down to pick): ", Completion::Nothing, |editor, key, query| {
+                if let Some(picker) = editor.picker.as_mut() {
+                    match key {
+                        Key::Up | Key::Down => picker.step(key == Key::Down),
+                        Key::Char(_) | Key::Backspace => picker.narrow(query),
+                        _ => (),
+                    }
+                }
+            }).unwrap_or(None);
+
+        let picked = self.picker.take().and_then(|picker| picker.line());
+        if let (Some(_), Some(y)) = (input, picked) {
+            self.cursor_position = Position { x: 0, y };
+            self.scroll();
+        }
+    }
+
+    // Searches with the configured grep and goes to the first match
+    fn grep(&mut self, pattern: &str) {
+        match grep::run(&self.config.grep_command, pattern) {
+            Ok(matches) if matches.is_empty() => {
+                self.status_message = StatusMessage::from(format!("No matches for {}.", pattern));
+            },
+            Ok(matches) => {
+                self.grep_matches = matches;
+                self.grep_index = 0;
+                self.go_to_grep_match();
+            },
+            Err(error) => self.status_message = StatusMessage::from(error),
+        }
+    }
+
+    fn step_grep_match(&mut self, forward: bool) {
+        if self.grep_matches.is_empty() {
+            self.status_message = StatusMessage::from("No grep matches, run `grep` first.".to_string());
+            return;
+        }
+        let last = self.grep_matches.len().saturating_sub(1);
+        if (forward && self.grep_index >= last) || (!forward && self.grep_index == 0) {
+            self.status_message = StatusMessage::from(format!(
+                "Already at the {} match.",
+                if forward { "last" } else { "first" }
+            ));
+            return;
+        }
+        self.grep_index = if forward {
+            self.grep_index.saturating_add(1)
+        } else {
+            self.grep_index.saturating_sub(1)
+        };
+        self.go_to_grep_match();
+    }
+
+    // Brings up the file of the current match, opening it if no buffer has it yet, and puts the
+    // cursor on the match
+    fn go_to_grep_match(&mut self) {
+        let found = match self.grep_matches.get(self.grep_index) {
+            Some(found) => found.clone(),
+            None => return,
+        };
+        match self.buffer_for(&found.file_name) {
+            Some(index) => self.switch_buffer(index),
+            None => {
+                if self.open_buffer(&found.file_name).is_err() {
+                    self.status_message =
+                        StatusMessage::from(format!("ERR: Could not open file: {}", found.file_name));
+                    return;
+                }
+            },
+        }
+
+        self.cursor_position = Position { x: found.column.saturating_sub(1), y: found.row };
+        self.clamp_cursor();
+        self.scroll();
+        self.status_message = StatusMessage::from(format!(
+            "({}/{}) {}:{}: {}",
+            self.grep_index.saturating_add(1),
+            self.grep_matches.len(),
+            found.file_name,
+            found.row.saturating_add(1),
+            found.text
+        ));
+    }
+
+    // Comments out the selection with a block comment, or uncomments it
+    fn toggle_block_comment(&mut self) {
+        let (start, end) = match self.selection_bounds() {
+            Some(bounds) => bounds,
+            None => {
+                self.status_message = StatusMessage::from("Nothing selected.".to_string());
+                return;
+            },
+        };
+
+        match self.document.toggle_block_comment(&start, &end) {
+            Ok((new_start, new_end)) => {
+                // keep the same text selected, so the command can be undone by running it again
+                self.cursor_position = new_end.clone();
+                self.selection = Some((new_start, new_end));
+            },
+            Err(message) => self.status_message = StatusMessage::from(message.to_string()),
+        }
+    }
+
+    // Reindents with what the `.editorconfig` says, or else with what the file is indented with
+    // already, or else with what the config says
+    fn reindent(&mut self) {
+        let soft_tabs = self
+            .document
+            .editorconfig()
+            .soft_tabs
+            .or_else(|| self.document.indents_with_tabs().map(|tabs| !tabs))
+            .unwrap_or(self.config.soft_tabs);
+        self.status_message = match self.document.reindent(self.tab_width(), soft_tabs) {
+            Some(changed) => {
+                // the cursor's row may have gotten shorter
+                let width = self.cursor_width(self.cursor_position.y);
+                self.cursor_position.x = self.cursor_position.x.min(width);
+                StatusMessage::from(format!("Reindented {} lines", changed))
+            },
+            None => StatusMessage::from("Reindent isn't supported for this file type.".to_string()),
+        };
+    }
+
+    fn toggle_line_numbers(&mut self) {
+        self.show_line_numbers = !self.show_line_numbers;
+        let state = if self.show_line_numbers { "on" } else { "off" };
+        self.status_message = StatusMessage::from(format!("Line numbers: {}", state));
+    }
+
+    fn toggle_auto_indent(&mut self) {
+        self.config.auto_indent = !self.config.auto_indent;
+        let state = if self.config.auto_indent { "on" } else { "off" };
+        self.status_message = StatusMessage::from(format!("Auto-indent: {}", state));
+    }
+
+    // Adds `amount` to the number under or after the cursor, which ends up on its last digit
+    fn add_to_number(&mut self, amount: i64) {
+        match self.document.add_to_number(&self.cursor_position, amount) {
+            Some(end) => self.cursor_position = end,
+            None => self.alert(),
+        }
+    }
+
+    // Reverses the order of the selected rows, or of every row without a selection
+    fn reverse_lines(&mut self) {
+        let (start, end) = match self.selection_bounds() {
+            Some((start, end)) => (start.y, end.y),
+            None => (0, self.document.len().saturating_sub(1)),
+        };
+        self.selection = None;
+        let changed = self.document.reverse_lines(start, end);
+        self.cursor_position = Position { x: 0, y: start };
+        self.status_message = StatusMessage::from(format!("Reversed {} lines", changed));
+    }
+
+    // Drops repeated rows from the selection, or from every row without one; `adjacent` only
+    // drops a row repeating the one right above it, like `uniq`
+    fn unique_lines(&mut self, adjacent: bool) {
+        let (start, end) = match self.selection_bounds() {
+            Some((start, end)) => (start.y, end.y),
+            None => (0, self.document.len().saturating_sub(1)),
+        };
+        self.selection = None;
+        let removed = self.document.unique_lines(start, end, adjacent);
+        self.cursor_position = Position { x: 0, y: start };
+        self.status_message = StatusMessage::from(format!("Removed {} duplicate lines", removed));
+    }
+
+    // Folds the selected rows, or else the block indented under the cursor's line, or opens the
+    // fold already there
+    fn fold(&mut self) {
+        let y = self.cursor_position.y;
+        let hidden = if let Some((start, end)) = self.selection_bounds() {
+            self.selection = None;
+            self.cursor_position = start.clone();
+            self.scroll();
+            self.document.fold_range(start.y, end.y)
+        } else if self.document.unfold(y) {
+            return;
+        } else {
+            self.document.fold_indented(y)
+        };
+        self.status_message = if hidden > 0 {
+            StatusMessage::from(format!("Folded {} lines", hidden))
+        } else {
+            StatusMessage::from("Nothing to fold.".to_string())
+        };
+    }
+
+    // Copies the selection into register `name`, or the cursor's line without one
+    fn yank(&mut self, name: char) {
+        let register = match (self.selection_bounds(), self.block_bounds()) {
+            (_, Some((top, bottom, left, right))) => {
+                let pieces: Vec<String> = (top..=bottom)
+                    .map(|y| self.document.row(y).map_or_else(String::new, |row| row.slice(left, right)))
+                    .collect();
+                (pieces.join("\n"), RegisterKind::Blockwise)
+            },
+            (Some((start, end)), None) => (self.document.extract(&start, &end), RegisterKind::Characterwise),
+            (None, None) => {
+                let y = self.cursor_position.y;
+                let line = self.document.row(y).map_or_else(String::new, Row::to_string);
+                (format!("{}\n", line), RegisterKind::Linewise)
+            },
+        };
+
+        let lines = register.0.lines().count().max(1);
+        self.status_message = StatusMessage::from(format!("Yanked {} lines", lines));
+        // like in Vim, the unnamed register always has the latest yank as well
+        self.registers.insert(UNNAMED_REGISTER, register.clone());
+        self.registers.insert(name, register);
+        self.selection = None;
+    }
+
+    // Yanks the selection into the unnamed register and takes it out of the document; without a
+    // selection it is the cursor's whole line
+    fn cut(&mut self) {
+        let selection = self.selection_bounds();
+        let block = self.block_bounds();
+        self.yank(UNNAMED_REGISTER);
+        let row_len = |document: &Document, y: usize| document.row(y).map_or(0, Row::len);
+
+        match (selection, block) {
+            (_, Some((top, bottom, left, right))) => {
+                for y in top..=bottom {
+                    let len = row_len(&self.document, y);
+                    if left < len {
+                        self.document.replace_at(&Position { x: left, y }, right.min(len).saturating_sub(left), "");
+                    }
+                }
+                self.cursor_position = Position { x: left, y: top };
+            },
+            (Some((start, end)), None) => {
+                self.document.delete_range(&start, &end);
+                self.cursor_position = start;
+            },
+            (None, None) => {
+                let y = self.cursor_position.y;
+                self.document.replace_at(&Position { x: 0, y }, row_len(&self.document, y), "");
+                // the line's own line ending goes with it, or on the last line the one before it
+                if y.saturating_add(1) < self.document.len() {
+                    self.document.delete(&Position { x: 0, y });
+                } else if y > 0 {
+                    let above = y.saturating_sub(1);
+                    self.document.delete(&Position { x: row_len(&self.document, above), y: above });
+                }
+                self.cursor_position = Position { x: 0, y: y.min(self.document.len().saturating_sub(1)) };
+            },
+        }
+        if let Some((text, _)) = self.registers.get(&UNNAMED_REGISTER) {
+            let lines = text.lines().count().max(1);
+            self.status_message = StatusMessage::from(format!("Cut {} lines", lines));
+        }
+        self.block_selection = false;
+        self.scroll();
+    }
+
+    // Puts register `name` back the way it was yanked
+    fn put(&mut self, name: char) {
+        let (text, kind) = match self.registers.get(&name) {
+            Some(register) => register.clone(),
+            None => {
+                self.status_message = StatusMessage::from("Nothing yanked.".to_string());
+                return;
+            },
+        };
+
+        match kind {
+            RegisterKind::Characterwise => {
+                for c in text.chars() {
+                    self.insert_char(c);
+                }
+            },
+            RegisterKind::Linewise => {
+                // going in after the end of the cursor's line never leaves an extra blank line at
+                // the end of the document
+                let y = self.cursor_position.y.min(self.document.len().saturating_sub(1));
+                let mut at = Position {
+                    x: self.document.row(y).map_or(0, Row::len),
+                    y,
+                };
+                for c in iter::once('\n').chain(text.strip_suffix('\n').unwrap_or(&text).chars()) {
+                    self.document.insert(&at, c);
+                    at = if c == '\n' {
+                        Position { x: 0, y: at.y.saturating_add(1) }
+                    } else {
+                        Position { x: at.x.saturating_add(1), y: at.y }
+                    };
+                }
+                self.cursor_position = Position { x: 0, y: y.saturating_add(1) };
+            },
+            RegisterKind::Blockwise => {
+                let Position { x, y: top } = self.cursor_position;
+                for (index, piece) in text.split('\n').enumerate() {
+                    let y = top.saturating_add(index);
+                    if y >= self.document.len() && y > 0 {
+                        let last = y.saturating_sub(1);
+                        let end = self.document.row(last).map_or(0, Row::len);
+                        self.document.insert(&Position { x: end, y: last }, '\n');
+                    }
+                    // rows too short to reach the column are padded out to it
+                    let len = self.document.row(y).map_or(0, Row::len);
+                    for column in len..x {
+                        self.document.insert(&Position { x: column, y }, ' ');
+                    }
+                    for (offset, c) in piece.chars().enumerate() {
+                        self.document.insert(&Position { x: x.saturating_add(offset), y }, c);
+                    }
+                }
+            },
+        }
+        self.selection = None;
+        // a put can open or close a multiline comment anywhere below
+        self.document.highlight(None, false);
+        self.scroll();
+    }
+
+    // The rows and the columns a block selection covers, the columns up to but not including
+    // `right`
+    fn block_bounds(&self) -> Option<(usize, usize, usize, usize)> {
+        if !self.block_selection {
+            return None;
+        }
+        let (anchor, head) = self.selection.as_ref()?;
+
+        Some((
+            anchor.y.min(head.y),
+            anchor.y.max(head.y),
+            anchor.x.min(head.x),
+            anchor.x.max(head.x),
+        ))
+    }
+
+    // Starts typing at the left column of the block selection, on its first row
+    fn start_block_insert(&mut self) {
+        if let Some((top, bottom, left, _)) = self.block_bounds() {
+            self.cursor_position = Position { x: left, y: top };
+            self.block_insert = Some((self.cursor_position.clone(), bottom));
+            self.selection = None;
+            self.block_selection = false;
+            self.scroll();
+        } else {
+            self.status_message = StatusMessage::from("No block selected.".to_string());
+        }
+    }
+
+    // Copies what was typed on the first row of a block insert to the same column of the rest of
+    // its rows. Nothing is copied once the cursor has left the text that was typed, e.g. for a
+    // line break.
+    fn finish_block_insert(&mut self) {
+        let (start, last) = match self.block_insert.take() {
+            Some(block_insert) => block_insert,
+            None => return,
+        };
+        if self.cursor_position.y != start.y || self.cursor_position.x <= start.x {
+            return;
+        }
+        let text = self
+            .document
+            .row(start.y)
+            .map_or_else(String::new, |row| row.slice(start.x, self.cursor_position.x));
+
+        for y in start.y.saturating_add(1)..=last {
+            let len = match self.document.row(y) {
+                Some(row) => row.len(),
+                None => break,
+            };
+            if len < start.x {
+                if !self.config.pad_block_insert {
+                    continue;
+                }
+                for x in len..start.x {
+                    self.document.insert(&Position { x, y }, ' ');
+                }
+            }
+            for (offset, c) in text.chars().enumerate() {
+                self.document.insert(&Position { x: start.x.saturating_add(offset), y }, c);
+            }
+        }
+    }
+
+    fn toggle_selection(&mut self) {
+        self.block_selection = false;
+        self.selection = if self.selection.is_some() {
+            None
+        } else {
+            Some((self.cursor_position.clone(), self.cursor_position.clone()))
+        };
+    }
+
+    // The selection with its ends in document order
+    fn selection_bounds(&self) -> Option<(Position, Position)> {
+        let (anchor, head) = self.selection.as_ref()?;
+        if (head.y, head.x) < (anchor.y, anchor.x) {
+            Some((head.clone(), anchor.clone()))
+        } else {
+            Some((anchor.clone(), head.clone()))
+        }
+    }
+
+    // Switches to the preset called `name`, or without one to the preset after the current one
+    fn switch_theme(&mut self, name: Option<&str>) {
+        let name = match name {
+            Some(name) => name,
+            None => {
+                let current = THEME_NAMES.iter().position(|&name| name == self.config.theme.name);
+                let next = current.map_or(0, |index| index.saturating_add(1) % THEME_NAMES.len());
+                THEME_NAMES.get(next).copied().unwrap_or("dark")
+            },
+        };
+
+        if let Some(theme) = Theme::named(name) {
+            self.config.theme = theme;
+            self.status_message = StatusMessage::from(format!("Theme: {}", name));
+        } else {
+            self.status_message = StatusMessage::from(format!("Unknown theme: {}", name));
+        }
+    }
+
+    // Sums up the current buffer on the message bar
+    fn info(&mut self) {
+        let state = if self.is_dirty() { "modified" } else { "saved" };
+        let summary = if let Some(hex) = &self.hex {
+            format!("Hex | {} bytes | {}", hex.byte_count(), state)
+        } else {
+            let encoding = if self.document.has_bom() {
+                "UTF-8 with BOM"
+            } else {
+                "UTF-8"
+            };
+            let final_newline = if self.document.has_final_newline() { "" } else { ", no final newline" };
+            // as typing gets them, after the `.editorconfig` has had its say
+            let tabs = if self.soft_tabs() { "soft" } else { "hard" };
+            format!(
+                "{} | {} lines | {}{} | {} | {} tabs, width {} | {}",
+                self.document.file_type(),
+                self.document.len(),
+                self.document.line_ending().name(),
+                final_newline,
+                encoding,
+                tabs,
+                self.tab_width(),
+                state,
+            )
+        };
+
+        self.status_message = StatusMessage::from(summary);
+    }
+
+    fn save(&mut self) {
+        let backups = self.config.backups;
+        if let Some(hex) = &mut self.hex {
+            let backed_up = hex
+                .file_name
+                .as_deref()
+                .map_or(Ok(()), |file_name| document::back_up(file_name, backups));
+            if backed_up.is_err() {
+                self.status_message = StatusMessage::from("Error writing backup, file not saved!".to_string());
+                return;
+            }
+            self.status_message = if hex.save().is_ok() {
+                StatusMessage::from("File saved successfully.".to_string())
+            } else {
+                StatusMessage::from("Error writing to file!".to_string())
+            };
+            return;
+        }
+
+        if self.document.file_name.is_none() {
+            let new_name = self.prompt_completing("Save as: ", Completion::Path).unwrap_or(None);
+            if new_name.is_none() {
+                self.status_message = StatusMessage::from("Save aborted.".to_string());
+                return;
+            }
+
+            self.document.file_name = new_name.map(|name| self.document.resolve(&name));
+        }
+
+        if !self.make_directory() {
+            return;
+        }
+
+        let formatted = match self.config.formatters.get(&self.document.file_type()) {
+            Some(command) => self.document.format(command),
+            None => Ok(()),
+        };
+        // the cursor stays on the same line number, which the formatter may have made shorter or
+        // taken away
+        self.clamp_cursor();
+
+        // a big file takes a while to write, so the message bar keeps up with it meanwhile
+        let terminal = &self.terminal;
+        let mut last_percent = None;
+        let mut size = 0;
+        let started = Instant::now();
+        let saved = save_document(&mut self.document, &self.config, |written, total| {
+            size = total;
+            let percent = written.saturating_mul(100).checked_div(total).unwrap_or(100);
+            if total >= SAVE_PROGRESS_BYTES && last_percent != Some(percent) {
+                last_percent = Some(percent);
+                show_progress(terminal, &format!("Saving... {}%", percent));
+            }
+        });
+        let took = format!("{} in {} ms", human_size(size), started.elapsed().as_millis());
+        let mut message = match (saved, formatted) {
+            (Ok(()), Ok(())) => format!("File saved successfully, {}.", took),
+            (Ok(()), Err(error)) => format!("Saved unformatted, {}. {}", took, error),
+            (Err(message), _) => message.to_string(),
+        };
+        if saved.is_ok() && self.config.linters.contains_key(&self.document.file_type()) {
+            message = format!("{} {}", message, self.lint(None));
+        }
+        self.status_message = StatusMessage::from(message);
+    }
+
+    // Creates the directory the document is saved to when it doesn't exist yet, asking first if
+    // the config says so; whether the document can be saved there now
+    fn make_directory(&mut self) -> bool {
+        let directory = match self.document.directory() {
+            Some(directory) if !directory.as_os_str().is_empty() && !directory.exists() => directory.to_path_buf(),
+            _ => return true,
+        };
+        let create = match self.config.missing_directories {
+            MissingDirectories::Create => true,
+            MissingDirectories::Refuse => false,
+            MissingDirectories::Ask => {
+                let question = format!("Directory {} doesn't exist. Create it? (y/n) ", directory.display());
+                self.prompt(&question, |_, _, _| {})
+                    .unwrap_or(None)
+                    .map_or(false, |answer| answer.to_lowercase().starts_with('y'))
+            },
+        };
+
+        let message = if !create {
+            format!("Directory {} doesn't exist, file not saved!", directory.display())
+        } else if let Err(error) = fs::create_dir_all(&directory) {
+            format!("Couldn't create {}: {}, file not saved!", directory.display(), error)
+        } else {
+            return true;
+        };
+        self.status_message = StatusMessage::from(message);
+        false
+    }
+
+    // Reads the file in again, asking first when that would throw changes away. The cursor stays
+    // on its line, or the last one if the file got shorter.
+    fn reload(&mut self) {
+        let file_name = match &self.document.file_name {
+            Some(file_name) => file_name.clone(),
+            None => {
+                self.status_message = StatusMessage::from("No file to reload.".to_string());
+                return;
+            },
+        };
+        if self.document.is_dirty() {
+            let question = format!("Discard unsaved changes and reload {}? (y/n) ", file_name);
+            let discard = self
+                .prompt(&question, |_, _, _| {})
+                .unwrap_or(None)
+                .map_or(false, |answer| answer.to_lowercase().starts_with('y'));
+            if !discard {
+                return;
+            }
+        }
+
+        self.status_message = match self.document.reload() {
+            Ok(()) => {
+                self.selection = None;
+                self.clamp_cursor();
+                StatusMessage::from(format!("Reloaded {}", file_name))
+            },
+            Err(error) => StatusMessage::from(format!("ERR: Could not reload {}: {}", file_name, error)),
+        };
+    }
+
+    // Marks the rows a linter complains about, running the linter configured for the file type
+    // on the saved file, or reading what one printed to `output_file`. Says how it went.
+    fn lint(&mut self, output_file: Option<&str>) -> String {
+        let file_name = match &self.document.file_name {
+            Some(file_name) => file_name.clone(),
+            None => return "Save the file to lint it.".to_string(),
+        };
+        let diagnostics = if let Some(output_file) = output_file {
+            fs::read_to_string(self.document.resolve(output_file))
+                .map(|output| diagnostics::parse(&output, &file_name))
+                .map_err(|error| format!("Couldn't read {}: {}", output_file, error))
+        } else if let Some(command) = self.config.linters.get(&self.document.file_type()) {
+            diagnostics::run(command, &file_name)
+        } else {
+            Err(format!("No linter for {}.", self.document.file_type()))
+        };
+
+        match diagnostics {
+            Ok(diagnostics) => {
+                let count: usize = diagnostics.values().map(Vec::len).sum();
+                self.document.set_diagnostics(diagnostics);
+                self.shown_diagnostic = None;
+                match count {
+                    0 => "No problems found.".to_string(),
+                    1 => "1 problem.".to_string(),
+                    _ => format!("{} problems.", count),
+                }
+            },
+            Err(error) => error,
+        }
+    }
+
+    // Puts the cursor on the next row with diagnostics, or the previous one
+    fn jump_to_diagnostic(&mut self, direction: SearchDirection) {
+        match self.document.next_diagnostic(self.cursor_position.y, direction) {
+            Some(y) => {
+                let column = self.document.diagnostics_at(y).first().map_or(1, |diagnostic| diagnostic.column);
+                self.cursor_position = Position { x: column.saturating_sub(1), y };
+                // landing on it shows its message even when it is the one shown already
+                self.shown_diagnostic = None;
+            },
+            None => self.status_message = StatusMessage::from("No problems to go to.".to_string()),
+        }
+    }
+
+    // Shows what the linter said about the cursor's row, once on getting there
+    fn show_diagnostic(&mut self) {
+        let y = self.cursor_position.y;
+        let first = self.document.diagnostics_at(y).first();
+        if first.is_none() {
+            self.shown_diagnostic = None;
+        } else if self.shown_diagnostic != Some(y) {
+            let count = self.document.diagnostics_at(y).len();
+            let more = if count > 1 { format!(" (+{} more)", count.saturating_sub(1)) } else { String::new() };
+            if let Some(diagnostic) = first {
+                self.status_message = StatusMessage::from(format!("{}{}", diagnostic.message, more));
+            }
+            self.shown_diagnostic = Some(y);
+        }
+    }
+
+    // Leaves the folds of every buffer behind for next time; they are only a convenience, so a
+    // failure to write them doesn't hold up quitting
+    fn save_folds(&self) {
+        for document in iter::once(&self.document).chain(self.buffers.iter().map(|buffer| &buffer.document)) {
+            let _ = document.save_folds();
+        }
+    }
+
+    // Saves every named buffer with unsaved changes without a word, unless one of them fails
+    fn autosave(&mut self) {
+        let config = &self.config;
+        let failed = iter::once(&mut self.document)
+            .chain(self.buffers.iter_mut().map(|buffer| &mut buffer.document))
+            .filter(|document| document.file_name.is_some() && document.is_dirty())
+            .map(|document| save_document(document, config, |_, _| {}))
+            .filter(Result::is_err)
+            .count();
+        if failed > 0 {
+            self.status_message = StatusMessage::from(format!("Autosave failed for {} file(s)!", failed));
+        }
+    }
+
+    // Writes the buffer to `path`, asking for one if it's empty, without saving the buffer itself
+    fn save_copy(&mut self, path: String) {
+        let path = if path.is_empty() {
+            match self.prompt_completing("Save a copy as: ", Completion::Path).unwrap_or(None) {
+                Some(path) => path,
+                None => {
+                    self.status_message = StatusMessage::from("Save aborted.".to_string());
+                    return;
+                },
+            }
+        } else {
+            path
+        };
+        let path = self.document.resolve(&path);
+
+        self.status_message = if self.document.save_copy(&path).is_ok() {
+            StatusMessage::from(format!("Copy saved to {}", path))
+        } else {
+            StatusMessage::from("Error writing to file!".to_string())
+        };
+    }
+
+    fn prompt<C>(&mut self, prompt: &str, callback: C) -> Result<Option<String>, std::io::Error>
+    where
+        C: FnMut(&mut Self, Key, &String),
+        {
+            Ok(self.read_prompt(prompt, Completion::Nothing, callback)?.filter(|input| !input.is_empty()))
+        }
+
+    fn prompt_completing(&mut self, prompt: &str, completion: Completion) -> Result<Option<String>, std::io::Error> {
+        Ok(self.read_prompt(prompt, completion, |_, _, _| {})?.filter(|input| !input.is_empty()))
+    }
+
+    // Like `prompt`, but tells pressing Enter on an empty input, which gives an empty string, from
+    // cancelling with Esc, which gives nothing. Tab completes file names as `completion` says:
+    // as far as all the candidates agree at first, then through each of them in turn.
+    fn read_prompt<C>(
+        &mut self,
+        prompt: &str,
+        completion: Completion,
+        mut callback: C,
+    ) -> Result<Option<String>, std::io::Error>
+    where
+        C: FnMut(&mut Self, Key, &String),
+        {
+            let mut result = String::new();
+            let mut cancelled = false;
+            // what the candidates complete, the candidates, and the one Tab is on, if any yet
+            let mut cycle: Option<(String, Vec<String>, Option<usize>)> = None;
+            let mut hint = String::new();
+            loop {
+                self.status_message = StatusMessage::from(format!("{}{}{}{}", prompt, result, hint, self.prompt_note));
                 self.refresh_screen()?;
-                let key = Terminal::read_key()?;
+                let key = self.terminal.read_key()?;
+                if key != Key::Char('\t') {
+                    cycle = None;
+                    hint.clear();
+                }
                 match key {
+                    Key::Char('\t') if completion != Completion::Nothing && self.config.complete_paths => {
+                        if let Some((base, candidates, index)) = &mut cycle {
+                            let next = index.map_or(0, |index| index.saturating_add(1));
+                            let next = if next < candidates.len() { next } else { 0 };
+                            *index = Some(next);
+                            result = format!("{}{}", base, candidates.get(next).map_or("", String::as_str));
+                        } else if let Some((base, candidates)) =
+                            path_candidates(&result, completion, self.document.directory())
+                        {
+                            result = format!("{}{}", base, common_prefix(&candidates));
+                            if candidates.len() > 1 {
+                                hint = format!("  ({})", candidates.join(" "));
+                                cycle = Some((base, candidates, None));
+                            }
+                        } else {
+                            self.alert();
+                        }
+                    },
                     Key::Backspace => {
-                        result.truncate(result.len().saturating_sub(1));
+                        result.pop();
                     },
                     Key::Char('\n') => break,
                     Key::Char(c) => {
@@ -245,7 +2635,7 @@ impl Editor {
                         }
                     }
                     Key::Esc => {
-                        result.truncate(0);
+                        cancelled = true;
                         break;
                     }
                     _ => (),
@@ -254,41 +2644,220 @@ impl Editor {
                 callback(self, key, &result);
             }
 
-            self.status_message = StatusMessage::from(String::new());
-            if result.is_empty() {
-                return Ok(None);
+            self.prompt_note.clear();
+            self.status_message = StatusMessage::from(String::new());
+            if cancelled {
+                return Ok(None);
+            }
+
+            Ok(Some(result))
+        }
+
+    fn scroll(&mut self) {
+        let Position { x, y } = self.cursor_position;
+        let width = self.text_width();
+        let height = self.text_height();
+        let row_len = self.cursor_width(y);
+        let tab_width = self.tab_width();
+        let in_hex = self.hex.is_some();
+        // wherever the cursor ended up, it has to be on screen
+        self.document.reveal(y);
+        // the margin can't take up more than half the screen, or the cursor would have nowhere to be
+        #[allow(clippy::integer_division)]
+        let margin = self.config.side_scroll_off.min(width.saturating_sub(1) / 2);
+        let offset = &mut self.offset;
+
+        if y < offset.y {
+            offset.y = y;
+        } else if self.document.visible_between(offset.y, y) >= height {
+            // check if the cursor has moved outside of the visible window, and if so, adjust
+            // offset so that the cursor is just inside the visible window
+            offset.y = self.document.visible_before(y, height.saturating_sub(1));
+        }
+
+        if self.free_scroll {
+            return;
+        }
+        if x < offset.x.saturating_add(margin) {
+            offset.x = x.saturating_sub(margin);
+        } else if x.saturating_add(margin) >= offset.x.saturating_add(width) {
+            // check if the cursor has moved outside of the visible window, and if so, adjust
+            // offset so that the cursor is just inside the visible window, keeping the margin
+            // only as long as there is more of the line to show
+            offset.x = x
+                .saturating_add(margin)
+                .min(row_len)
+                .saturating_sub(width)
+                .saturating_add(1)
+                .max(offset.x);
+        }
+        // tabs before the cursor can still push it past the right edge
+        if let Some(row) = self.document.row(y).filter(|_| !in_hex) {
+            while offset.x < x && row.render_width(offset.x, x, tab_width).saturating_add(margin) >= width {
+                offset.x = offset.x.saturating_add(1);
+            }
+        }
+    }
+
+    // whether the mouse can point into the text right now; the other views don't take it
+    fn takes_mouse(&self) -> bool {
+        self.config.mouse
+            && self.hex.is_none()
+            && self.stream.is_none()
+            && self.diff.is_none()
+            && self.picker.is_none()
+            && self.block_insert.is_none()
+    }
+
+    // A click puts the cursor where it points and starts a selection there, dragging extends the
+    // selection and scrolls once it goes past the top or the bottom of the text, and letting go
+    // keeps the selection unless nothing was dragged over
+    fn handle_mouse(&mut self, event: MouseEvent) {
+        match event {
+            // a click on the tab line goes to the buffer of the tab under it
+            MouseEvent::Press(MouseButton::Left, column, 1) if self.shows_tabline() => {
+                if let Some(index) = self.tab_at(column) {
+                    self.switch_buffer(index);
+                }
+            },
+            MouseEvent::Press(MouseButton::Left, column, line) => {
+                let position = self.position_at(column, line);
+                self.cursor_position = position.clone();
+                self.block_selection = false;
+                self.selection = Some((position.clone(), position));
+            },
+            MouseEvent::Hold(column, line) => {
+                let position = self.position_at(column, line);
+                if let Some((_, head)) = &mut self.selection {
+                    *head = position.clone();
+                    self.cursor_position = position;
+                }
+            },
+            MouseEvent::Release(_, _) => {
+                if matches!(&self.selection, Some((anchor, head)) if anchor == head) {
+                    self.selection = None;
+                }
+            },
+            MouseEvent::Press(MouseButton::WheelUp, _, _) => self.scroll_wheel(false),
+            MouseEvent::Press(MouseButton::WheelDown, _, _) => self.scroll_wheel(true),
+            _ => (),
+        }
+    }
+
+    // The buffer whose tab is under `column` of the tab line, counted from 1 the way the terminal
+    // reports it
+    fn tab_at(&self, column: u16) -> Option<usize> {
+        let column = usize::from(column).saturating_sub(1);
+        let mut end: usize = 0;
+        self.tab_labels().0.iter().position(|label| {
+            end = end.saturating_add(label.chars().count());
+            column < end
+        })
+    }
+
+    // Scrolls the view a few rows, the cursor coming along only as far as it has to to stay on
+    // screen
+    fn scroll_wheel(&mut self, down: bool) {
+        let last = self.document.len().saturating_sub(1);
+        for _ in 0..WHEEL_LINES {
+            if down {
+                let next = self.document.next_visible(self.offset.y.saturating_add(1));
+                if next > last {
+                    break;
+                }
+                self.offset.y = next;
+            } else if self.offset.y > 0 {
+                self.offset.y = self.document.previous_visible(self.offset.y.saturating_sub(1));
+            }
+        }
+
+        let height = self.text_height();
+        let y = self.cursor_position.y;
+        if y < self.offset.y {
+            self.cursor_position.y = self.offset.y;
+        } else if self.document.visible_between(self.offset.y, y) >= height {
+            let mut bottom = self.offset.y;
+            for _ in 1..height {
+                bottom = self.document.next_visible(bottom.saturating_add(1));
+            }
+            self.cursor_position.y = bottom.min(last);
+        }
+        self.clamp_cursor();
+    }
+
+    // The document position under the terminal cell at `column` and `line`, counted from 1 the
+    // way the terminal reports them. Pointing above or below the text gives the row just outside
+    // the screen, so a drag there scrolls on.
+    fn position_at(&self, column: u16, line: u16) -> Position {
+        let top = if self.shows_tabline() { 1 } else { 0 };
+        let line = usize::from(line).saturating_sub(1);
+        let y = if line < top {
+            self.document.previous_visible(self.offset.y.saturating_sub(1))
+        } else {
+            let mut y = self.offset.y;
+            for _ in 0..line.saturating_sub(top).min(self.text_height()) {
+                y = self.document.next_visible(y).saturating_add(1);
+            }
+            self.document.next_visible(y)
+        };
+        let y = y.min(self.cursor_height());
+
+        let column = usize::from(column).saturating_sub(1).saturating_sub(self.gutter_width());
+        let x = self.document.row(y).map_or(0, |row| {
+            (self.offset.x..row.len())
+                .find(|&x| row.render_width(self.offset.x, x.saturating_add(1), self.tab_width()) > column)
+                .unwrap_or_else(|| row.len())
+        });
+
+        Position { x, y }
+    }
+
+    // Shifts the view a column without moving the cursor, but no further right than it takes to
+    // show the end of the longest row on screen
+    fn scroll_sideways(&mut self, right: bool) {
+        let mut longest = 0;
+        let mut y = self.offset.y;
+        for _ in 0..self.text_height() {
+            y = self.document.next_visible(y);
+            match self.document.row(y) {
+                Some(row) => longest = longest.max(row.len()),
+                None => break,
             }
-
-            Ok(Some(result))
+            y = y.saturating_add(1);
         }
 
-    fn scroll(&mut self) {
-        let Position { x, y } = self.cursor_position;
-        let width = self.terminal.size().width as usize;
-        let height = self.terminal.size().height as usize;
-        let mut offset = &mut self.offset;
+        let limit = longest.saturating_sub(self.text_width());
+        self.offset.x = if right {
+            self.offset.x.saturating_add(1).min(limit.max(self.offset.x))
+        } else {
+            self.offset.x.saturating_sub(1)
+        };
+        self.free_scroll = true;
+    }
 
-        if y < offset.y {
-            offset.y = y;
-        } else if y >= offset.y.saturating_add(height) {
-            // check if the cursor has moved outside of the visible window, and if so, adjust
-            // offset so that the cursor is just inside the visible window
-            offset.y = y.saturating_sub(height).saturating_add(1);
-        }
+    // Scrolls vertically so that at least `lines` rows stay visible above and below the cursor,
+    // as far as the screen height allows
+    fn scroll_with_context(&mut self, lines: usize) {
+        let y = self.cursor_position.y;
+        let height = self.text_height();
+        #[allow(clippy::integer_division)]
+        let lines = lines.min(height.saturating_sub(1) / 2);
 
-        if x < offset.x {
-            offset.x = x;
-        } else if x >= offset.x.saturating_add(width) {
-            // check if the cursor has moved outside of the visible window, and if so, adjust
-            // offset so that the cursor is just inside the visible window
-            offset.x = x.saturating_sub(width).saturating_add(1);
+        if self.document.visible_between(self.offset.y, y) < lines || y < self.offset.y {
+            self.offset.y = self.document.visible_before(y, lines);
+        } else if self.document.visible_between(self.offset.y, y).saturating_add(lines) >= height {
+            self.offset.y = self.document.visible_before(y, height.saturating_sub(1).saturating_sub(lines));
         }
     }
 
     fn move_cursor(&mut self, key: Key) {
-        let terminal_height = self.terminal.size().height as usize;
+        let terminal_height = self.text_height();
+        // overlapping by the whole screen or more would leave paging stuck in place
+        let page = terminal_height
+            .saturating_sub(self.config.scroll_overlap)
+            .max(1);
         let Position { mut y, mut x } = self.cursor_position;
-        let height = self.document.len();
+        let height = self.cursor_height();
         // Now let’s fix the horizontal scrolling. The missing piece here is that we are not yet
         // allowing the cursor to scroll past the right of the screen
         // let width = if let Some(row) = self.document.row(y) {
@@ -297,13 +2866,13 @@ impl Editor {
         //     0
         // };
         // let width = self.document.row(y).map_or(0, |row| row.len());
-        let mut width = self.document.row(y).map_or(0, Row::len);
+        let mut width = self.cursor_width(y);
 
         match key {
-            Key::Up => y = y.saturating_sub(1),
+            Key::Up => y = self.document.previous_visible(y.saturating_sub(1)),
             Key::Down => {
                 if y < height {
-                    y = y.saturating_add(1)
+                    y = self.document.next_visible(y.saturating_add(1))
                 }
             }
             Key::Left => {
@@ -312,8 +2881,8 @@ impl Editor {
                 } else if y > 0 {
                     // We want to allow the user to press at the beginning of the line to move to
                     // the end of the previous line.
-                    y -= 1;
-                    x = self.document.row(y).map_or(0, Row::len);
+                    y = self.document.previous_visible(y - 1);
+                    x = self.cursor_width(y);
                 }
             },
             Key::Right => {
@@ -322,15 +2891,15 @@ impl Editor {
                 } else if y < height {
                     // Similarly, let’s allow the user to press at the end of a line to go to the
                     // beginning of the next line.
-                    y += 1;
+                    y = self.document.next_visible(y + 1);
                     x = 0;
                 }
             }
             // let’s make the and keys scroll up or down an entire page instead of the full
             // document.
             Key::PageUp => {
-                y = if y > terminal_height {
-                    y.saturating_sub(terminal_height)
+                y = if y > page {
+                    y.saturating_sub(page)
                 } else {
                     0
                 }
@@ -341,8 +2910,8 @@ impl Editor {
                 // We were able to get rid of unnecessary saturating arithmetics. Why? For example, y and height
                 // have the same type. If y.saturating_add(terminal_height) is less than height, then y +
                 // terminal_height is also less than height.
-                y = if y.saturating_add(terminal_height) < height {
-                    y.saturating_add(terminal_height)
+                y = if y.saturating_add(page) < height {
+                    y.saturating_add(page)
                 } else {
                     height
                 }
@@ -351,9 +2920,11 @@ impl Editor {
             Key::End => x = width,
             _ => (),
         }
+        // paging may well land inside a fold
+        y = self.document.previous_visible(y);
 
         // We have to set width again, since row can have changed during the key processing
-        width = self.document.row(y).map_or(0, Row::len);
+        width = self.cursor_width(y);
         // We then set the new value of x, making sure that x does not exceed the current row’s
         // width
         if x > width {
@@ -363,60 +2934,446 @@ impl Editor {
         self.cursor_position = Position { x, y };
     }
 
-    pub fn draw_row(&self, row: &Row) {
+    fn draw_row(&self, row: &Row, y: usize) {
+        self.draw_gutter(y);
+        let width = self.text_width();
+        let tab_width = self.tab_width();
+        let mut start = self.offset.x;
+        // tabs take up more than one column, so the window ends wherever the screen fills up
+        let mut end = row.fit(start, width, tab_width);
+        // a row cut off at either edge gives up that edge's column to a marker saying so
+        let markers = self.config.overflow_markers;
+        let left = markers.filter(|_| start > 0).map(|(left, _)| left);
+        let right = markers.filter(|_| row.len() > end).map(|(_, right)| right);
+        if left.is_some() {
+            start = start.saturating_add(1);
+        }
+        let width = width
+            .saturating_sub(usize::from(left.is_some()))
+            .saturating_sub(usize::from(right.is_some()));
+        if left.is_some() || right.is_some() {
+            end = row.fit(start, width, tab_width);
+        }
+        let selected = self.selected_columns(y);
+        // the first row of a fold tells what the fold holds
+        let marker = if let Some(hidden) = self.document.fold_at(y) {
+            format!("  \u{2026} {} lines", hidden)
+        } else if self.document.has_open_fold(y) {
+            "  \u{25be}".to_string()
+        } else {
+            String::new()
+        };
+        let room = width.saturating_sub(row.render_width(start, end, tab_width));
+        let marker: String = marker.chars().take(room).collect();
+
+        if y == self.cursor_position.y && self.config.highlight_cursor_line {
+            // the column tint would reset the line's background, and the cursor marks it anyway
+            let padding = room.saturating_sub(marker.chars().count());
+            self.terminal.set_bg_color(self.config.theme.palette.crosshair);
+            self.draw_marker(&left.map(String::from).unwrap_or_default());
+            self.terminal.write(&row.render_with(start, end, None, selected, self.show_whitespace, tab_width, &self.config.theme.palette));
+            self.draw_marker(&right.map(String::from).unwrap_or_default());
+            self.draw_marker(&marker);
+            self.terminal.write(&" ".repeat(padding));
+            self.terminal.reset_bg_color();
+            self.terminal.write("\r\n");
+            return;
+        }
+
+        let column = if self.config.highlight_cursor_column && marker.is_empty() {
+            Some(self.cursor_position.x)
+        } else {
+            None
+        };
+        self.draw_marker(&left.map(String::from).unwrap_or_default());
+        self.terminal.write(&row.render_with(start, end, column, selected, self.show_whitespace, tab_width, &self.config.theme.palette));
+        self.draw_marker(&right.map(String::from).unwrap_or_default());
+        self.draw_marker(&marker);
+        self.terminal.write("\r\n");
+    }
+
+    // The line number of row `y`, in the search match color while a search marks it
+    fn draw_gutter(&self, y: usize) {
+        let mut width = self.gutter_width();
+        if self.document.has_diagnostics() {
+            let worst = self.document.diagnostics_at(y).iter().map(|diagnostic| diagnostic.severity).max();
+            match worst {
+                Some(severity) => {
+                    let (color, sign) = match severity {
+                        Severity::Error => (ERROR_COLOR, "●"),
+                        Severity::Warning => (WARNING_COLOR, "▲"),
+                    };
+                    self.terminal.set_fg_color(color);
+                    self.terminal.write(&format!("{} ", sign));
+                    self.terminal.reset_fg_color();
+                },
+                None => self.terminal.write("  "),
+            }
+            width = width.saturating_sub(2);
+        }
+        if width == 0 {
+            return;
+        }
+
+        let color = if self.document.has_match(y) {
+            self.config.theme.gutter_match_fg
+        } else {
+            self.config.theme.gutter_fg
+        };
+        let cursor_y = self.cursor_position.y;
+        let distance = if y < cursor_y {
+            self.document.visible_between(y, cursor_y)
+        } else {
+            self.document.visible_between(cursor_y, y)
+        };
+        let number = match self.config.line_number_style {
+            LineNumbers::Relative => distance,
+            LineNumbers::Hybrid if y != cursor_y => distance,
+            _ => y.saturating_add(1),
+        };
+        let separator = &self.config.gutter_separator;
+        let digits = width.saturating_sub(separator.chars().count());
+        self.terminal.set_fg_color(color);
+        self.terminal.write(&format!("{:>2$}{}", number, separator, digits));
+        self.terminal.reset_fg_color();
+    }
+
+    // Fold and overflow markers are drawn dimmer than the text around them
+    fn draw_marker(&self, marker: &str) {
+        if !marker.is_empty() {
+            self.terminal.set_fg_color(FOLD_FG_COLOR);
+            self.terminal.write(marker);
+            self.terminal.reset_fg_color();
+        }
+    }
+
+    // The range of graphemes on row `y` that the selection covers
+    fn selected_columns(&self, y: usize) -> Option<(usize, usize)> {
+        if let Some((top, bottom, left, right)) = self.block_bounds() {
+            return if y >= top && y <= bottom { Some((left, right)) } else { None };
+        }
+        let (start, end) = self.selection_bounds()?;
+        if y < start.y || y > end.y {
+            return None;
+        }
+
+        let from = if y == start.y { start.x } else { 0 };
+        let to = if y == end.y { end.x } else { usize::MAX };
+        Some((from, to))
+    }
+
+    // The tab of every buffer that fits on the tab line, in order, each with a space on either
+    // side and squeezed into an equal share of the width, but no less than 4 columns. Says as
+    // well whether buffers past the last one had to be left off.
+    fn tab_labels(&self) -> (Vec<String>, bool) {
         let width = self.terminal.size().width as usize;
-        let start = self.offset.x;
-        let end = self.offset.x.saturating_add(width);
-        let row = row.render(start, end);
+        let share = width.checked_div(self.buffers.len()).unwrap_or(width).max(4);
+        let mut labels = Vec::new();
+        let mut used: usize = 0;
+        for (index, buffer) in self.buffers.iter().enumerate() {
+            let document = if index == self.active_buffer {
+                &self.document
+            } else {
+                &buffer.document
+            };
+            let name = document
+                .file_name
+                .as_deref()
+                .map_or("[No Name]", |name| name.rsplit('/').next().unwrap_or(name));
+            let dirty = if document.is_dirty() { "*" } else { "" };
+            let mut label = format!("{}:{}{}", index.saturating_add(1), name, dirty);
+            // room for a space on either side
+            let room = share.saturating_sub(2);
+            if label.chars().count() > room {
+                label = label.chars().take(room.saturating_sub(1)).collect();
+                label.push('…');
+            }
+            let label = format!(" {} ", label);
+            used = used.saturating_add(label.chars().count());
+            if used > width {
+                return (labels, true);
+            }
+            labels.push(label);
+        }
+
+        (labels, false)
+    }
+
+    // One tab per buffer, numbered for Alt-1 to Alt-9, with the active one in reverse video. When
+    // they don't all fit, a `…` in the last column stands for the rest.
+    fn draw_tabline(&self) {
+        let width = self.terminal.size().width as usize;
+        let (mut labels, cut) = self.tab_labels();
+        let mut used: usize = labels.iter().map(|label| label.chars().count()).sum();
+        let shown = labels.len();
+        if cut {
+            if used < width {
+                labels.push("…".to_string());
+                used = used.saturating_add(1);
+            } else if let Some(last) = labels.last_mut() {
+                last.pop();
+                last.push('…');
+            }
+        }
+        let mut line = String::new();
+        for (index, label) in labels.iter().enumerate() {
+            if index == self.active_buffer && index < shown {
+                line.push_str(&format!("{}{}{}", style::Invert, label, style::NoInvert));
+            } else {
+                line.push_str(label);
+            }
+        }
+        line.push_str(&" ".repeat(width.saturating_sub(used)));
 
-        println!("{}\r", row);
+        self.terminal.set_fg_color(self.config.theme.status_fg);
+        self.terminal.set_bg_color(self.config.theme.status_bg);
+        self.terminal.write(&format!("{}\r\n", line));
+        self.terminal.reset_fg_color();
+        self.terminal.reset_bg_color();
     }
 
     #[allow(clippy::integer_arithmetic, clippy::integer_division)]
     fn draw_rows(&self) {
-        let height = self.terminal.size().height;
+        if let Some(hex) = &self.hex {
+            self.draw_hex_rows(hex);
+            return;
+        }
+        if let Some(stream) = &self.stream {
+            self.draw_stream_rows(stream);
+            return;
+        }
+        if let Some(view) = &self.diff {
+            self.draw_diff_rows(view);
+            return;
+        }
+        if let Some(picker) = &self.picker {
+            self.draw_picker(picker);
+            return;
+        }
+
+        let height = self.text_height();
+        let welcome_lines: Vec<&str> = match &self.config.welcome_message {
+            Some(message) if self.document.is_empty() => message.lines().collect(),
+            _ => Vec::new(),
+        };
+        // the banner is centered vertically around the upper third of the screen
+        let welcome_top = (height / 3).saturating_sub(welcome_lines.len() / 2);
+
+        let mut y = self.offset.y;
         for terminal_row in 0..height {
-            Terminal::clear_current_line();
+            self.terminal.clear_current_line();
 
-            if let Some(row) = self.document.row(self.offset.y.saturating_add(terminal_row as usize)) {
-                self.draw_row(row);
-            } else if self.document.is_empty() && terminal_row == height / 3 {
-                self.draw_welcome_message();
+            y = self.document.next_visible(y);
+            if let Some(row) = self.document.row(y) {
+                let row = match &self.replace_preview {
+                    Some((preview_y, preview)) if *preview_y == y => preview,
+                    _ => row,
+                };
+                self.draw_row(row, y);
+                y = y.saturating_add(1);
+            } else if let Some(line) = terminal_row
+                .checked_sub(welcome_top)
+                .and_then(|index| welcome_lines.get(index))
+            {
+                self.draw_welcome_message(line);
             } else {
-                println!("~\r");
+                self.terminal.write("~\r\n");
             }
         }
     }
 
+    fn draw_stream_rows(&self, stream: &StreamDocument) {
+        let width = self.terminal.size().width as usize;
+        for terminal_row in 0..self.text_height() {
+            self.terminal.clear_current_line();
 
-    fn draw_status_bar(&self) {
-        let mut status;
+            if let Some(text) = stream.row(self.offset.y.saturating_add(terminal_row)) {
+                let text: String = text.replace('\t', "  ").chars().skip(self.offset.x).take(width).collect();
+                self.terminal.write(&format!("{}\r\n", text));
+            } else {
+                self.terminal.write("~\r\n");
+            }
+        }
+    }
+
+    fn draw_hex_rows(&self, hex: &HexDocument) {
+        let width = self.terminal.size().width as usize;
+        for terminal_row in 0..self.terminal.size().height {
+            self.terminal.clear_current_line();
+
+            if let Some(mut row) = hex.render_row(self.offset.y.saturating_add(terminal_row as usize)) {
+                row.truncate(width);
+                self.terminal.write(&format!("{}\r\n", row));
+            } else {
+                self.terminal.write("~\r\n");
+            }
+        }
+    }
+
+    // Draws the shown entries numbered by their line, with the picked one inverted
+    fn draw_picker(&self, picker: &Picker) {
+        let width = self.terminal.size().width as usize;
+        let height = self.text_height();
+        let digits = self.document.len().to_string().len();
+        let top = picker.top(height);
+        for terminal_row in 0..height {
+            self.terminal.clear_current_line();
+
+            let index = top.saturating_add(terminal_row);
+            if let Some((label, line)) = picker.shown.get(index).and_then(|entry| picker.entries.get(*entry)) {
+                let text: String = format!("{:>digits$} {}", line.saturating_add(1), label, digits = digits)
+                    .chars()
+                    .take(width)
+                    .collect();
+                if index == picker.selected {
+                    self.terminal.write(&format!("{}{}{}\r\n", style::Invert, text, style::NoInvert));
+                } else {
+                    self.terminal.write(&format!("{}\r\n", text));
+                }
+            } else {
+                self.terminal.write("~\r\n");
+            }
+        }
+    }
+
+    // Draws the previous buffer on the left and the active one on the right. Removed lines are
+    // colored on the left, added ones on the right, and changed ones on both sides.
+    fn draw_diff_rows(&self, view: &DiffView) {
         let width = self.terminal.size().width as usize;
+        #[allow(clippy::integer_division)]
+        let half = width.saturating_sub(3) / 2;
+        for terminal_row in 0..self.text_height() {
+            self.terminal.clear_current_line();
+
+            if let Some(line) = view.lines.get(view.top.saturating_add(terminal_row as usize)) {
+                let left_color = match line.kind {
+                    DiffKind::Removed => Some(DIFF_REMOVED_COLOR),
+                    DiffKind::Changed => Some(DIFF_CHANGED_COLOR),
+                    _ => None,
+                };
+                let right_color = match line.kind {
+                    DiffKind::Added => Some(DIFF_ADDED_COLOR),
+                    DiffKind::Changed => Some(DIFF_CHANGED_COLOR),
+                    _ => None,
+                };
+                self.draw_diff_side(line.left.as_deref(), half, left_color);
+                self.terminal.write(" | ");
+                self.draw_diff_side(line.right.as_deref(), half, right_color);
+                self.terminal.write("\r\n");
+            } else {
+                self.terminal.write("~\r\n");
+            }
+        }
+    }
+
+    fn draw_diff_side(&self, text: Option<&str>, width: usize, color: Option<color::Rgb>) {
+        let text: String = text
+            .unwrap_or("")
+            .replace('\t', "  ")
+            .chars()
+            .take(width)
+            .collect();
+        if let Some(color) = color {
+            self.terminal.set_fg_color(color);
+        }
+        self.terminal.write(&format!("{:width$}", text, width = width));
+        if color.is_some() {
+            self.terminal.reset_fg_color();
+        }
+    }
+
+    // Everything the status bar shows, piece by piece; a new indicator only needs to add its own
+    // segment here
+    fn status_segments(&self) -> Vec<StatusSegment> {
         let mut file_name = "[No Name]".to_string();
-        if let Some(name) = &self.document.file_name {
+        let name = match (&self.stream, &self.hex) {
+            (Some(stream), _) => &stream.file_name,
+            (_, Some(hex)) => &hex.file_name,
+            _ => &self.document.file_name,
+        };
+        if let Some(name) = name {
             file_name = name.clone();
             file_name.truncate(20);
         }
-
-        let modified_indicator = if self.document.is_dirty() {
-            " [+]"
+        if let Some(stream) = &self.stream {
+            // rows past the ones read so far aren't counted yet
+            let more = if stream.is_complete() { "" } else { "+" };
+            return vec![
+                StatusSegment::left(format!("{} - {} bytes", file_name, stream.size())),
+                StatusSegment::right("Read-only".to_string()),
+                StatusSegment::right(format!("{}/{}{}", self.offset.y.saturating_add(1), stream.len(), more)),
+            ];
+        }
+        let (size, len) = if let Some(hex) = &self.hex {
+            (format!("{} bytes", hex.byte_count()), hex.len())
         } else {
-            ""
+            (format!("{} lines", self.document.len()), self.document.len())
         };
 
-        status = format!(
-            "{} - {} lines{}",
-            file_name,
-            self.document.len(),
-            modified_indicator
-        );
+        let mut segments = vec![StatusSegment::left(format!("{} - {}", file_name, size))];
+        if self.is_dirty() {
+            segments.push(StatusSegment::left("[+]".to_string()));
+        }
 
-        let line_indicator = format!(
-            "{} | {}/{}",
-            self.document.file_type(),
-            self.cursor_position.y.saturating_add(1),
-            self.document.len(),
-        );
+        if self.hex.is_some() {
+            segments.push(StatusSegment::right("Hex".to_string()));
+        } else {
+            let mode = if self.viewing {
+                "VIEW"
+            } else if self.overwrite {
+                "OVR"
+            } else {
+                "INS"
+            };
+            segments.push(StatusSegment::right(mode.to_string()));
+            if self.selection.is_some() {
+                let mode = if self.block_selection { "BLOCK" } else { "SEL" };
+                segments.push(StatusSegment::right(mode.to_string()));
+            } else if self.block_insert.is_some() {
+                segments.push(StatusSegment::right("BLOCK".to_string()));
+            }
+            if self.document.is_prose() {
+                segments.push(StatusSegment::right(format!("{} words", self.document.word_count())));
+            }
+            segments.push(StatusSegment::right(self.document.file_type()));
+        }
+
+        let line = self.cursor_position.y.saturating_add(1);
+        let percent = line.min(len).saturating_mul(100).checked_div(len).unwrap_or(100);
+        if self.hex.is_some() {
+            segments.push(StatusSegment::right(format!("{}/{}", line, len)));
+        } else {
+            // the column counts graphemes, with the one on screen after it when tabs make them differ
+            let x = self.cursor_position.x;
+            let column = x.saturating_add(1);
+            let visual = self
+                .document
+                .row(self.cursor_position.y)
+                .map_or(column, |row| row.render_width(0, x, self.tab_width()).saturating_add(1));
+            let position = if visual == column {
+                format!("Ln {}, Col {}", line, column)
+            } else {
+                format!("Ln {}, Col {} ({})", line, column, visual)
+            };
+            segments.push(StatusSegment::right(position));
+        }
+        segments.push(StatusSegment::right(format!("{}%", percent)));
+
+        segments
+    }
+
+    fn draw_status_bar(&self) {
+        let width = self.terminal.size().width as usize;
+        let segments = self.status_segments();
+        let side = |side: StatusSide| {
+            segments
+                .iter()
+                .filter(|segment| segment.side == side)
+                .map(|segment| segment.text.as_str())
+                .collect::<Vec<&str>>()
+        };
+        let mut status = side(StatusSide::Left).join(" ");
+        let line_indicator = side(StatusSide::Right).join(" | ");
 
         #[allow(clippy::integer_arithmetic)]
         let len = status.len() + line_indicator.len();
@@ -427,42 +3384,237 @@ impl Editor {
         status = format!("{}{}", status, line_indicator);
         status.truncate(width);
 
+        self.terminal.set_fg_color(self.config.theme.status_fg);
+        self.terminal.set_bg_color(self.config.theme.status_bg);
+        self.terminal.write(&format!("{}\r\n", status));
 
-        Terminal::set_fg_color(STATUS_FG_COLOR);
-        Terminal::set_bg_color(STATUS_BG_COLOR);
-        println!("{}\r", status);
-
-        Terminal::reset_fg_color();
-        Terminal::reset_bg_color();
+        self.terminal.reset_fg_color();
+        self.terminal.reset_bg_color();
     }
 
     fn draw_message_bar(&self) {
-        Terminal::clear_current_line();
+        self.terminal.clear_current_line();
         let message = &self.status_message;
-        if Instant::now() - message.time < Duration::new(5, 0) {
-            let mut text = message.text.clone();
-            text.truncate(self.terminal.size().width as usize);
-            print!("{}", text);
+        let timeout = self.config.message_timeout;
+        if timeout == 0 || Instant::now() - message.time < Duration::new(timeout, 0) {
+            // cut by characters, as a byte cut could land in the middle of one
+            let text: String = message.text.chars().take(self.terminal.size().width as usize).collect();
+            self.terminal.write(&text);
         }
     }
 
-    fn draw_welcome_message(&self) {
+    fn draw_welcome_message(&self, line: &str) {
         // width/2 - welcome_len/2
         // (width - welcome_len) / 2
-        let mut welcome_message = format!("SIM editor -- version {}", VERSION);
+        // measured and cut by characters rather than bytes, as a banner may be drawn with any
         let width = self.terminal.size().width as usize;
-        let len = welcome_message.len();
+        let len = line.chars().count();
         #[allow(clippy::integer_arithmetic, clippy::integer_division)]
         let padding = width.saturating_sub(len) / 2;
         let spaces = " ".repeat(padding.saturating_sub(1));
-        welcome_message = format!("~{}{}", spaces, welcome_message);
-        welcome_message.truncate(width);
-        println!("{}\r", welcome_message);
+        let welcome_message: String = format!("~{}{}", spaces, line).chars().take(width).collect();
+        self.terminal.write(&format!("{}\r\n", welcome_message));
+    }
+}
+
+// Backs up and writes a named document the way the config asks, failing with the message to show
+fn save_document<F>(document: &mut Document, config: &Config, on_progress: F) -> Result<(), &'static str>
+where
+    F: FnMut(u64, u64),
+{
+    if document.directory().map_or(false, |directory| !directory.as_os_str().is_empty() && !directory.exists()) {
+        return Err("Directory doesn't exist, file not saved!");
+    }
+    if let Some(file_name) = &document.file_name {
+        if document::back_up(file_name, config.backups).is_err() {
+            return Err("Error writing backup, file not saved!");
+        }
+    }
+
+    let settings = document.editorconfig().clone();
+    if settings.trim_trailing_whitespace == Some(true) {
+        document.trim_trailing_whitespace();
+    }
+    if let Some(final_newline) = settings.final_newline {
+        document.set_final_newline(final_newline);
+    }
+    if document.save_with_progress(on_progress).is_err() {
+        return Err("Error writing to file!");
+    }
+    if config.persistent_undo {
+        // losing the history is no reason to report the save as failed
+        let _ = document.save_undo_history();
+    }
+
+    Ok(())
+}
+
+// Writes `text` over the message bar and nothing else, for progress on something that keeps the
+// whole screen from being redrawn meanwhile
+fn show_progress<B: Backend>(terminal: &B, text: &str) {
+    terminal.cursor_hide();
+    terminal.cursor_position(&Position {
+        x: 0,
+        y: (terminal.size().height as usize).saturating_add(1),
+    });
+    terminal.clear_current_line();
+    terminal.write(text);
+    terminal.cursor_show();
+    // a failed redraw only costs us the progress message
+    terminal.flush().ok();
+}
+
+// e.g. "opened foo.rs — 1,234 lines, 45 KB"
+fn opened_message(document: &Document) -> String {
+    format!(
+        "opened {} \u{2014} {} lines, {}",
+        document.file_name.as_deref().unwrap_or("[No Name]"),
+        with_separators(document.len() as u64),
+        human_size(document.size())
+    )
+}
+
+// what the search prompt says after the query about how it is being matched, e.g. "  [regex]
+// [ignore case]"
+fn search_note(mode: SearchMode, ignore_case: bool, error: Option<&str>) -> String {
+    let mut note = String::new();
+    match error {
+        Some(error) => note.push_str(&format!("  [regex: {}, searching plainly]", error)),
+        None if mode == SearchMode::Regex => note.push_str("  [regex]"),
+        None => (),
+    }
+    if ignore_case {
+        note.push_str("  [ignore case]");
+    }
+
+    note
+}
+
+// e.g. "512 bytes", "45 KB", "3 MB"
+#[allow(clippy::integer_arithmetic, clippy::integer_division)]
+fn human_size(size: u64) -> String {
+    if size < 1024 {
+        format!("{} bytes", size)
+    } else if size < 1024 * 1024 {
+        format!("{} KB", with_separators(size.saturating_add(512) / 1024))
+    } else {
+        format!("{} MB", with_separators(size.saturating_add(512 * 1024) / (1024 * 1024)))
+    }
+}
+
+// 1234567 as "1,234,567"
+#[allow(clippy::integer_arithmetic)]
+fn with_separators(number: u64) -> String {
+    let digits = number.to_string();
+    let mut result = String::new();
+    for (index, digit) in digits.chars().enumerate() {
+        if index > 0 && (digits.len() - index) % 3 == 0 {
+            result.push(',');
+        }
+        result.push(digit);
+    }
+
+    result
+}
+
+fn lines_of(document: &Document) -> Vec<String> {
+    (0..document.len())
+        .filter_map(|index| document.row(index))
+        .map(Row::to_string)
+        .collect()
+}
+
+// Shifts a block of lines so that its first line sits at `indentation`, keeping how far every other
+// line is indented relative to the first one. The first line goes right at the cursor, so it loses
+// its indentation altogether.
+fn reindent(text: &str, indentation: &str) -> String {
+    let mut lines = text.split('\n');
+    let first = lines.next().unwrap_or("");
+    let first_indentation_len = first.len().saturating_sub(first.trim_start_matches(is_blank).len());
+
+    let mut result = first.trim_start_matches(is_blank).to_string();
+    for line in lines {
+        result.push('\n');
+        let content = line.trim_start_matches(is_blank);
+        if content.is_empty() {
+            continue;
+        }
+
+        // whatever indentation the line has beyond the first line's is kept
+        let line_indentation_len = line.len().saturating_sub(content.len());
+        let extra = line.get(first_indentation_len.min(line_indentation_len)..).unwrap_or(line);
+        result.push_str(indentation);
+        result.push_str(extra);
     }
+
+    result
+}
+
+// Where the file name being typed in `input` starts, and the entries of its directory that it
+// could go on to name, directories ending in `/`. A relative name is looked up from `directory`
+// when there is one. Dotfiles only come up once the name starts with a dot.
+fn path_candidates(
+    input: &str,
+    completion: Completion,
+    directory: Option<&Path>,
+) -> Option<(String, Vec<String>)> {
+    let start = match completion {
+        Completion::Nothing => return None,
+        Completion::Path => 0,
+        Completion::Command => {
+            let command = input.split(' ').next()?;
+            if !PATH_COMMANDS.contains(&command) || command.len() == input.len() {
+                return None;
+            }
+            command.len().saturating_add(1)
+        },
+    };
+    let path = input.get(start..)?;
+    let (dir, name) = path.split_at(path.rfind('/').map_or(0, |index| index.saturating_add(1)));
+
+    let dir = if dir.is_empty() { "." } else { dir };
+    let dir = directory.map_or_else(|| PathBuf::from(dir), |directory| directory.join(dir));
+    let mut candidates: Vec<String> = fs::read_dir(dir)
+        .ok()?
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let mut file_name = entry.file_name().into_string().ok()?;
+            if !file_name.starts_with(name) || (file_name.starts_with('.') && !name.starts_with('.')) {
+                return None;
+            }
+            if entry.path().is_dir() {
+                file_name.push('/');
+            }
+            Some(file_name)
+        })
+        .collect();
+    if candidates.is_empty() {
+        return None;
+    }
+    candidates.sort();
+
+    let base = input.get(..input.len().saturating_sub(name.len()))?;
+    Some((base.to_string(), candidates))
+}
+
+// the longest start that all of `candidates` share
+fn common_prefix(candidates: &[String]) -> String {
+    let first = candidates.first().map_or("", String::as_str);
+    let len = candidates
+        .iter()
+        .map(|candidate| first.chars().zip(candidate.chars()).take_while(|(a, b)| a == b).count())
+        .min()
+        .unwrap_or(0);
+
+    first.chars().take(len).collect()
 }
 
-fn die(e: std::io::Error) {
-    Terminal::clear_screen();
+fn is_blank(c: char) -> bool {
+    c == ' ' || c == '\t'
+}
+
+fn die<B: Backend>(terminal: &B, e: std::io::Error) {
+    terminal.clear_screen();
     panic!(e);
 }
 
@@ -470,3 +3622,333 @@ fn die(e: std::io::Error) {
 //     let byte = c as u8;
 //     byte & 0b0001_1111
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TestBackend;
+
+    // an editor on `contents` that has gone through `keys`, leaving off where they ran out
+    fn run_with(contents: &str, keys: Vec<Key>, config: Config) -> Editor<TestBackend> {
+        run_events(contents, keys.into_iter().map(Event::Key).collect(), config)
+    }
+
+    fn run_events(contents: &str, events: Vec<Event>, config: Config) -> Editor<TestBackend> {
+        let mut editor = Editor::with_config(TestBackend::with_events(40, 10, events), Document::from_str(contents), config);
+        while editor.process_keypress().is_ok() {}
+        editor
+    }
+
+    // `text` pasted the way a terminal with bracketed paste sends it
+    fn paste(text: &str) -> Vec<Event> {
+        let mut events = vec![Event::Unsupported(PASTE_START.to_vec())];
+        events.extend(text.chars().map(|c| Event::Key(Key::Char(c))));
+        events.push(Event::Unsupported(PASTE_END.to_vec()));
+        events
+    }
+
+    fn cursor(editor: &Editor<TestBackend>) -> (usize, usize) {
+        (editor.cursor_position.x, editor.cursor_position.y)
+    }
+
+    fn soft_tabs() -> Config {
+        Config {
+            soft_tabs: true,
+            ..Config::default()
+        }
+    }
+
+    fn rights(count: usize) -> Vec<Key> {
+        vec![Key::Right; count]
+    }
+
+    #[test]
+    fn backspace_takes_indentation_spaces_back_a_tab_stop() {
+        let mut keys = rights(8);
+        keys.push(Key::Backspace);
+        let editor = run_with("        x\n", keys, soft_tabs());
+        assert_eq!(editor.document.to_string(), "    x\n");
+        assert_eq!(cursor(&editor), (4, 0));
+    }
+
+    #[test]
+    fn backspace_takes_a_stray_space_after_text_alone() {
+        let mut keys = rights(5);
+        keys.push(Key::Backspace);
+        let editor = run_with("foo  bar\n", keys, soft_tabs());
+        assert_eq!(editor.document.to_string(), "foo bar\n");
+        assert_eq!(cursor(&editor), (4, 0));
+    }
+
+    #[test]
+    fn backspace_takes_one_space_of_mixed_indentation() {
+        let mut keys = rights(3);
+        keys.push(Key::Backspace);
+        let editor = run_with("\t  x\n", keys, soft_tabs());
+        assert_eq!(editor.document.to_string(), "\t x\n");
+        assert_eq!(cursor(&editor), (2, 0));
+    }
+
+    #[test]
+    fn esc_puts_the_cursor_and_view_back_after_stepping_through_matches() {
+        // a match every 20 rows, each a screen or two further down
+        let contents: String = (0..80_usize)
+            .map(|y| if y % 20 == 19 { "foo\n".to_string() } else { format!("line {}\n", y) })
+            .collect();
+        let mut keys = vec![Key::Down, Key::Down, Key::Right, Key::Ctrl('f'), Key::Char('f'), Key::Char('o'), Key::Char('o')];
+        keys.extend(vec![Key::Down; 3]);
+        keys.push(Key::Esc);
+        let editor = run_with(&contents, keys, Config::default());
+        assert_eq!(cursor(&editor), (1, 2));
+        assert_eq!((editor.offset.x, editor.offset.y), (0, 0));
+    }
+
+    // where the editor put the terminal's cursor last time it drew the screen, 0-based
+    fn screen_cursor(editor: &Editor<TestBackend>) -> Option<(usize, usize)> {
+        let output = editor.terminal.output();
+        let goto = output.rsplit("\x1b[").find(|sequence| sequence.ends_with('H'))?;
+        let mut numbers = goto.trim_end_matches('H').split(';').map(|number| number.parse::<usize>().ok());
+        let (row, column) = (numbers.next()??, numbers.next()??);
+        Some((column.saturating_sub(1), row.saturating_sub(1)))
+    }
+
+    #[test]
+    fn search_puts_the_cursor_past_a_tab_on_screen() -> Result<(), std::io::Error> {
+        let keys = vec![Key::Ctrl('f'), Key::Char('f'), Key::Char('o'), Key::Char('o'), Key::Char('\n')];
+        let editor = run_with("\tfoo\n", keys, Config::default());
+        assert_eq!(cursor(&editor), (1, 0));
+        editor.refresh_screen()?;
+        assert_eq!(screen_cursor(&editor), Some((editor.tab_width(), 0)));
+        Ok(())
+    }
+
+    fn assert_cursor_in_document(editor: &Editor<TestBackend>) {
+        let Position { x, y } = editor.cursor_position;
+        assert!(y <= editor.cursor_height(), "cursor row {} is past the document", y);
+        assert!(x <= editor.cursor_width(y), "cursor column {} is past the end of row {}", x, y);
+    }
+
+    #[test]
+    fn deleting_and_joining_at_the_end_keeps_the_cursor_in_the_document() {
+        // Delete at the very end has nothing to join, and cutting the last row takes away the one
+        // the cursor is on
+        let keys = vec![Key::Down, Key::End, Key::Delete, Key::Ctrl('x')];
+        let editor = run_with("ab\ncd", keys, Config::default());
+        assert_eq!(editor.document.to_string(), "ab");
+        assert_cursor_in_document(&editor);
+
+        // from the empty line past the end, the first Backspace has no row after the last to join
+        let keys = vec![Key::Down, Key::Down, Key::Delete, Key::Backspace, Key::Backspace];
+        let editor = run_with("ab\ncd\n", keys, Config::default());
+        assert_eq!(editor.document.to_string(), "ab\nc\n");
+        assert_cursor_in_document(&editor);
+    }
+
+    #[test]
+    fn undoing_rows_away_keeps_the_cursor_in_the_document() {
+        let mut keys = vec![Key::End, Key::Char('\n'), Key::Char('b'), Key::Char('c'), Key::Char('\n'), Key::Char('d')];
+        keys.extend(vec![Key::Ctrl('z'); 5]);
+        let editor = run_with("a\n", keys, Config::default());
+        assert_eq!(editor.document.to_string(), "a\n");
+        assert_cursor_in_document(&editor);
+    }
+
+    #[test]
+    fn formatting_that_shortens_the_file_keeps_the_cursor_in_the_document() -> Result<(), std::io::Error> {
+        let path = std::env::temp_dir().join(format!("sim-format-{}.rs", std::process::id()));
+        fs::write(&path, "one\ntwo\nthree\n")?;
+        let mut config = Config::default();
+        config.formatters.insert("Rust".to_string(), "head -n 1".to_string());
+
+        let keys = vec![Key::Down, Key::Down, Key::End, Key::Ctrl('s')];
+        let document = Document::open(&path.to_string_lossy())?;
+        let mut editor = Editor::with_config(TestBackend::new(40, 10, keys), document, config);
+        while editor.process_keypress().is_ok() {}
+        let saved = fs::read_to_string(&path);
+        fs::remove_file(&path)?;
+
+        assert_eq!(saved?, "one\n");
+        assert_eq!(editor.document.to_string(), "one\n");
+        assert_cursor_in_document(&editor);
+        Ok(())
+    }
+
+    #[test]
+    fn message_bar_cuts_a_message_between_characters() {
+        let mut editor = run_with("", Vec::new(), Config::default());
+        // the width ends right after the first byte of the em dash
+        editor.terminal = TestBackend::new(18, 10, Vec::new());
+        editor.status_message = StatusMessage::from(opened_message(&editor.document));
+        editor.draw_message_bar();
+        assert!(editor.terminal.output().ends_with("opened [No Name] \u{2014}"));
+    }
+
+    #[test]
+    fn welcome_message_is_measured_in_characters() {
+        let editor = run_with("", Vec::new(), Config::default());
+        editor.draw_welcome_message("\u{2554}\u{2550}\u{2557}");
+        // (40 - 3) / 2 columns to the middle, the `~` taking the first of them
+        let centered = format!("~{}\u{2554}\u{2550}\u{2557}\r\n", " ".repeat(17));
+        assert!(editor.terminal.output().ends_with(&centered));
+
+        let mut editor = editor;
+        editor.terminal = TestBackend::new(3, 10, Vec::new());
+        editor.draw_welcome_message("\u{2554}\u{2550}\u{2557}");
+        assert!(editor.terminal.output().ends_with("~\u{2554}\u{2550}\r\n"));
+    }
+
+    #[test]
+    fn reindent_keeps_the_file_on_tabs() -> Result<(), std::io::Error> {
+        let path = std::env::temp_dir().join(format!("sim-reindent-{}.rs", std::process::id()));
+        fs::write(&path, "fn main() {\n\tif x {\n  y();\n\t}\n}\n")?;
+        let document = Document::open(&path.to_string_lossy());
+        fs::remove_file(&path)?;
+
+        let mut keys = vec![Key::Ctrl('e')];
+        keys.extend("reindent\n".chars().map(Key::Char));
+        let mut editor = Editor::with_config(TestBackend::new(40, 10, keys), document?, soft_tabs());
+        while editor.process_keypress().is_ok() {}
+        assert_eq!(editor.document.to_string(), "fn main() {\n\tif x {\n\t\ty();\n\t}\n}\n");
+        Ok(())
+    }
+
+    #[test]
+    fn info_tells_the_indentation_settings() {
+        let mut keys = vec![Key::Ctrl('e')];
+        keys.extend("info\n".chars().map(Key::Char));
+        let editor = run_with("a\n", keys, soft_tabs());
+        assert_eq!(editor.status_message.text, "No filetype | 1 lines | LF | UTF-8 | soft tabs, width 4 | saved");
+    }
+
+    fn rebound() -> Config {
+        let mut config = Config::default();
+        config.keymap.unbind(&[Key::Ctrl('q')]);
+        assert_eq!(config.keymap.bind(vec![Key::Ctrl('k'), Key::Char('q')], Action::Quit), Ok(()));
+        config
+    }
+
+    #[test]
+    fn help_names_the_keys_bound_now() {
+        let editor = run_with("", Vec::new(), rebound());
+        assert_eq!(
+            editor.help("HELP", &[], &[(Action::Save, "save"), (Action::Quit, "quit")]),
+            "HELP: Ctrl-S = save | Ctrl-K q = quit"
+        );
+        assert_eq!(editor.hex_help(), "HEX: Tab = switch pane | Ctrl-S = save | Ctrl-K q = quit");
+    }
+
+    #[test]
+    fn quit_warning_names_the_keys_bound_now() {
+        let keys = vec![Key::Char('a'), Key::Ctrl('k'), Key::Char('q')];
+        let editor = run_with("", keys, rebound());
+        assert!(!editor.should_quit);
+        assert_eq!(
+            editor.status_message.text,
+            "WARNING! File has unsaved changes. Press Ctrl-K q 3 more times to quit."
+        );
+    }
+
+    #[test]
+    fn an_unbound_chord_is_dropped_whole() {
+        let keys = vec![Key::Ctrl('k'), Key::Char('x')];
+        let editor = run_with("", keys, rebound());
+        assert_eq!(editor.document.to_string(), "");
+        assert_eq!(editor.status_message.text, "Ctrl-K x is not bound");
+    }
+
+    #[test]
+    fn prompt_backspace_takes_a_whole_character() {
+        let keys = vec![Key::Ctrl('f'), Key::Char('\u{e9}'), Key::Backspace, Key::Char('b'), Key::Char('\n')];
+        let editor = run_with("ab\n", keys, Config::default());
+        assert_eq!(cursor(&editor), (1, 0));
+    }
+
+    // what `text` shows on the terminal, without the escape codes that color it
+    fn visible(text: &str) -> String {
+        let mut shown = String::new();
+        let mut in_escape = false;
+        for c in text.chars() {
+            match c {
+                '\x1b' => in_escape = true,
+                _ if in_escape => in_escape = !c.is_ascii_alphabetic(),
+                _ => shown.push(c),
+            }
+        }
+        shown
+    }
+
+    // an editor on `buffers` unnamed buffers, the first of them active
+    fn with_buffers(width: u16, buffers: usize, events: Vec<Event>) -> Editor<TestBackend> {
+        let config = Config {
+            mouse: true,
+            ..Config::default()
+        };
+        let mut editor = Editor::with_config(TestBackend::with_events(width, 10, events), Document::from_str("a\n"), config);
+        for number in 1..buffers {
+            editor.buffers.push(Buffer {
+                document: Document::from_str(&format!("buffer {}\n", number)),
+                ..Buffer::default()
+            });
+        }
+        editor
+    }
+
+    #[test]
+    fn tabline_stops_at_the_width() {
+        let editor = with_buffers(20, 8, Vec::new());
+        editor.draw_tabline();
+        let line = visible(&editor.terminal.output());
+        let line = line.trim_end_matches("\r\n");
+        assert_eq!(line.chars().count(), 20);
+        assert!(line.ends_with('…'), "{:?}", line);
+
+        let editor = with_buffers(40, 3, Vec::new());
+        editor.draw_tabline();
+        let line = visible(&editor.terminal.output());
+        assert_eq!(line.trim_end_matches("\r\n").chars().count(), 40);
+        assert!(!line.contains('…'));
+    }
+
+    #[test]
+    fn clicking_a_tab_goes_to_its_buffer() {
+        // three tabs of 13 columns each, and an unused one at the end
+        let click = |column| Event::Mouse(MouseEvent::Press(MouseButton::Left, column, 1));
+        let mut editor = with_buffers(40, 3, vec![click(15)]);
+        while editor.process_keypress().is_ok() {}
+        assert_eq!(editor.active_buffer, 1);
+        assert_eq!(editor.document.to_string(), "buffer 1\n");
+        assert!(editor.selection.is_none());
+        assert_eq!(cursor(&editor), (0, 0));
+
+        let mut editor = with_buffers(40, 3, vec![click(40)]);
+        while editor.process_keypress().is_ok() {}
+        assert_eq!(editor.active_buffer, 0);
+        assert!(editor.selection.is_none());
+        assert_eq!(cursor(&editor), (0, 0));
+    }
+
+    #[test]
+    fn pasting_into_a_read_only_view_does_nothing() -> Result<(), std::io::Error> {
+        // Esc would close the diff if the paste got through as keys
+        let mut events: Vec<Event> = "\x05next\n\x05diff\n".chars()
+            .map(|c| Event::Key(if c == '\x05' { Key::Ctrl('e') } else { Key::Char(c) }))
+            .collect();
+        events.extend(paste("\x1bpasted"));
+        let mut editor = with_buffers(40, 2, events);
+        while editor.process_keypress().is_ok() {}
+        assert!(editor.diff.is_some());
+        assert_eq!(editor.document.to_string(), "buffer 1\n");
+
+        let path = std::env::temp_dir().join(format!("sim-stream-paste-{}.txt", std::process::id()));
+        fs::write(&path, "streamed\n")?;
+        let mut editor = run_events("", Vec::new(), Config::default());
+        let opened = editor.open_stream(&path.to_string_lossy());
+        fs::remove_file(&path)?;
+        opened?;
+        editor.terminal = TestBackend::with_events(40, 10, paste("pasted"));
+        while editor.process_keypress().is_ok() {}
+        assert_eq!(editor.document.to_string(), "");
+        assert!(editor.status_message.text != "The file is read-only.");
+        Ok(())
+    }
+}
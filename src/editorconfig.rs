@@ -0,0 +1,240 @@
+use crate::LineEnding;
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// What a project's `.editorconfig` files say about one file; anything they leave out is up to
+// the config
+#[derive(Default, Clone)]
+pub struct EditorConfig {
+    // `indent_style`, spaces (soft tabs) or tabs
+    pub soft_tabs: Option<bool>,
+    // `indent_size`, or `tab_width` when the indentation size is given as `tab`
+    pub tab_width: Option<usize>,
+    pub line_ending: Option<LineEnding>,
+    pub trim_trailing_whitespace: Option<bool>,
+    pub final_newline: Option<bool>,
+}
+
+impl EditorConfig {
+    // The settings for `file_name` from every `.editorconfig` in its directory and the ones above
+    // it, up to one marked `root = true`. Nearer files win over farther ones, and in a file later
+    // sections win over earlier ones.
+    #[must_use]
+    pub fn find(file_name: &str) -> Self {
+        let path = match absolute(file_name) {
+            Some(path) => path,
+            None => return Self::default(),
+        };
+
+        let mut files = Vec::new();
+        for dir in path.ancestors().skip(1) {
+            if let Ok(contents) = fs::read_to_string(dir.join(".editorconfig")) {
+                let root = is_root(&contents);
+                files.push((dir.to_path_buf(), contents));
+                if root {
+                    break;
+                }
+            }
+        }
+
+        let mut settings = Self::default();
+        let mut tab_width = None;
+        let mut indent_size = None;
+        for (dir, contents) in files.iter().rev() {
+            let relative = match path.strip_prefix(dir) {
+                Ok(relative) => relative.to_string_lossy().replace('\\', "/"),
+                Err(_) => continue,
+            };
+            for (key, value) in properties(contents, &relative) {
+                let unset = value == "unset";
+                match key.as_str() {
+                    "indent_style" => settings.soft_tabs = match value.as_str() {
+                        "space" => Some(true),
+                        "tab" => Some(false),
+                        _ => None,
+                    },
+                    "indent_size" => indent_size = if unset { None } else { Some(value) },
+                    "tab_width" => tab_width = value.parse().ok(),
+                    "end_of_line" => settings.line_ending = match value.as_str() {
+                        "lf" => Some(LineEnding::Lf),
+                        "crlf" => Some(LineEnding::Crlf),
+                        _ => None,
+                    },
+                    "trim_trailing_whitespace" => settings.trim_trailing_whitespace = flag(&value),
+                    "insert_final_newline" => settings.final_newline = flag(&value),
+                    _ => (),
+                }
+            }
+        }
+        settings.tab_width = match indent_size.as_deref() {
+            Some("tab") => tab_width,
+            Some(size) => size.parse().ok(),
+            None => tab_width,
+        };
+
+        settings
+    }
+}
+
+fn absolute(file_name: &str) -> Option<PathBuf> {
+    let path = Path::new(file_name);
+    if path.is_absolute() {
+        return Some(path.to_path_buf());
+    }
+    Some(env::current_dir().ok()?.join(path))
+}
+
+fn flag(value: &str) -> Option<bool> {
+    match value {
+        "true" => Some(true),
+        "false" => Some(false),
+        _ => None,
+    }
+}
+
+// whether the preamble, before the first section, has `root = true`
+fn is_root(contents: &str) -> bool {
+    contents
+        .lines()
+        .map(str::trim)
+        .take_while(|line| !line.starts_with('['))
+        .filter_map(|line| line.split_once('='))
+        .any(|(key, value)| key.trim().eq_ignore_ascii_case("root") && value.trim().eq_ignore_ascii_case("true"))
+}
+
+// The properties of every section whose glob matches `relative`, the file's path from the
+// `.editorconfig`'s directory, in the order they are given; keys and values come lowercased
+fn properties(contents: &str, relative: &str) -> Vec<(String, String)> {
+    let mut properties = Vec::new();
+    let mut matches = false;
+    for line in contents.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if let Some(section) = line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+            matches = section_matches(section, relative);
+        } else if let Some((key, value)) = line.split_once('=') {
+            if matches {
+                properties.push((key.trim().to_lowercase(), value.trim().to_lowercase()));
+            }
+        }
+    }
+
+    properties
+}
+
+// A glob without a `/` goes for files of that name in any directory, one with a `/` for paths
+// from the `.editorconfig`'s directory
+fn section_matches(glob: &str, relative: &str) -> bool {
+    let glob = if glob.contains('/') {
+        glob.trim_start_matches('/').to_string()
+    } else {
+        format!("**/{}", glob)
+    };
+    let glob: Vec<char> = glob.chars().collect();
+    let relative: Vec<char> = relative.chars().collect();
+    glob_matches(&glob, &relative)
+        || (glob.starts_with(&['*', '*', '/']) && glob.get(3..).map_or(false, |glob| glob_matches(glob, &relative)))
+}
+
+// `*` matches anything but a `/`, `**` anything at all, `?` any one character but a `/`, `[abc]`
+// and `[a-z]` one of the characters, `[!abc]` one that isn't, and `{a,b}` either of the words
+#[allow(clippy::indexing_slicing, clippy::integer_arithmetic)]
+fn glob_matches(glob: &[char], text: &[char]) -> bool {
+    let (first, rest) = match glob.split_first() {
+        Some(split) => split,
+        None => return text.is_empty(),
+    };
+    match first {
+        '*' if rest.first() == Some(&'*') => {
+            (0..=text.len()).any(|skip| glob_matches(&rest[1..], &text[skip..]))
+        },
+        '*' => {
+            let end = text.iter().position(|&c| c == '/').unwrap_or(text.len());
+            (0..=end).any(|skip| glob_matches(rest, &text[skip..]))
+        },
+        '?' => text.first().map_or(false, |&c| c != '/') && glob_matches(rest, &text[1..]),
+        '[' => match rest.iter().position(|&c| c == ']') {
+            Some(end) if !text.is_empty() => {
+                let (negated, class) = match rest[..end].split_first() {
+                    Some(('!', class)) => (true, class),
+                    _ => (false, &rest[..end]),
+                };
+                in_class(class, text[0]) != negated && glob_matches(&rest[end + 1..], &text[1..])
+            },
+            Some(_) => false,
+            None => text.first() == Some(&'[') && glob_matches(rest, &text[1..]),
+        },
+        '{' => match closing_brace(rest) {
+            Some(end) => alternatives(&rest[..end]).iter().any(|alternative| {
+                let glob: Vec<char> = alternative.iter().chain(&rest[end + 1..]).copied().collect();
+                glob_matches(&glob, text)
+            }),
+            None => text.first() == Some(&'{') && glob_matches(rest, &text[1..]),
+        },
+        '\\' if !rest.is_empty() => text.first() == Some(&rest[0]) && glob_matches(&rest[1..], &text[1..]),
+        _ => text.first() == Some(first) && glob_matches(rest, &text[1..]),
+    }
+}
+
+fn in_class(class: &[char], c: char) -> bool {
+    let mut i = 0;
+    while i < class.len() {
+        match (class.get(i), class.get(i.saturating_add(1)), class.get(i.saturating_add(2))) {
+            (Some(&low), Some('-'), Some(&high)) => {
+                if low <= c && c <= high {
+                    return true;
+                }
+                i = i.saturating_add(3);
+            },
+            (Some(&single), ..) => {
+                if single == c {
+                    return true;
+                }
+                i = i.saturating_add(1);
+            },
+            _ => break,
+        }
+    }
+
+    false
+}
+
+// where the `}` closing a `{` that came just before `glob` is, braces nesting
+fn closing_brace(glob: &[char]) -> Option<usize> {
+    let mut depth = 0_usize;
+    for (i, &c) in glob.iter().enumerate() {
+        match c {
+            '{' => depth = depth.saturating_add(1),
+            '}' if depth == 0 => return Some(i),
+            '}' => depth = depth.saturating_sub(1),
+            _ => (),
+        }
+    }
+
+    None
+}
+
+// the words between the commas of a `{...}` that aren't in a nested one
+fn alternatives(glob: &[char]) -> Vec<Vec<char>> {
+    let mut alternatives = vec![Vec::new()];
+    let mut depth = 0_usize;
+    for &c in glob {
+        match c {
+            '{' => depth = depth.saturating_add(1),
+            '}' => depth = depth.saturating_sub(1),
+            ',' if depth == 0 => {
+                alternatives.push(Vec::new());
+                continue;
+            },
+            _ => (),
+        }
+        if let Some(alternative) = alternatives.last_mut() {
+            alternative.push(c);
+        }
+    }
+
+    alternatives
+}
@@ -1,4 +1,4 @@
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct HighlightingOptions {
     numbers: bool,
     strings: bool,
@@ -7,6 +7,9 @@ pub struct HighlightingOptions {
     multiline_comments: bool,
     primary_keywords: Vec<String>,
     secondary_keywords: Vec<String>,
+    mixed_indentation: bool,
+    suspicious_characters: Vec<char>,
+    line_length_limit: Option<usize>,
 }
 
 impl HighlightingOptions {
@@ -44,11 +47,50 @@ impl HighlightingOptions {
     pub fn secondary_keywords(&self) -> &Vec<String> {
         &self.secondary_keywords
     }
+
+    #[must_use]
+    pub fn mixed_indentation(&self) -> bool {
+        self.mixed_indentation
+    }
+
+    // the characters to flag wherever they turn up; none when the warning is off
+    #[must_use]
+    pub fn suspicious_characters(&self) -> &[char] {
+        &self.suspicious_characters
+    }
+
+    // how long a row can get before the rest of it is flagged
+    #[must_use]
+    pub fn line_length_limit(&self) -> Option<usize> {
+        self.line_length_limit
+    }
 }
 
 pub struct FileType {
     name: String,
     hl_opts: HighlightingOptions,
+    // whether indentation mixing tabs and spaces is worth flagging in this language at all
+    mixed_indentation_matters: bool,
+    // whether invisible and look-alike characters are worth flagging, which they aren't in prose
+    suspicious_characters_matter: bool,
+    // whether blocks are delimited by brackets, so indentation can be worked out from them
+    bracket_blocks: bool,
+    // the opening and closing delimiters of a block comment
+    block_comment: Option<(String, String)>,
+    // which rows are listed in the outline
+    outline: Outline,
+    // whether the file is writing rather than code, which gets its words counted
+    prose: bool,
+}
+
+// What the outline of a file lists
+#[derive(Clone, Copy, PartialEq)]
+pub enum Outline {
+    Nothing,
+    // rows that open with one of the keywords, after any of the qualifiers
+    Definitions(&'static [&'static str], &'static [&'static str]),
+    // rows starting with `#`, leaving out fenced code
+    Headings,
 }
 
 impl Default for FileType {
@@ -56,6 +98,12 @@ impl Default for FileType {
         Self {
             name: String::from("No filetype"),
             hl_opts: HighlightingOptions::default(),
+            mixed_indentation_matters: true,
+            suspicious_characters_matter: true,
+            bracket_blocks: false,
+            block_comment: None,
+            outline: Outline::Nothing,
+            prose: false,
         }
     }
 }
@@ -71,6 +119,47 @@ impl FileType {
         &self.hl_opts
     }
 
+    #[must_use]
+    pub fn bracket_blocks(&self) -> bool {
+        self.bracket_blocks
+    }
+
+    #[must_use]
+    pub fn block_comment(&self) -> Option<(&str, &str)> {
+        self.block_comment
+            .as_ref()
+            .map(|(open, close)| (open.as_str(), close.as_str()))
+    }
+
+    #[must_use]
+    pub fn outline(&self) -> Outline {
+        self.outline
+    }
+
+    #[must_use]
+    pub fn is_prose(&self) -> bool {
+        self.prose
+    }
+
+    // Turns the mixed indentation warning on or off, as far as this file type cares about it
+    pub fn warn_mixed_indentation(&mut self, enabled: bool) {
+        self.hl_opts.mixed_indentation = enabled && self.mixed_indentation_matters;
+    }
+
+    // Flags what rows have past `limit` characters, or nothing with `None`
+    pub fn limit_line_length(&mut self, limit: Option<usize>) {
+        self.hl_opts.line_length_limit = limit;
+    }
+
+    // Flags `characters`, as far as this file type cares about them; none turns the warning off
+    pub fn warn_suspicious_characters(&mut self, characters: &[char]) {
+        self.hl_opts.suspicious_characters = if self.suspicious_characters_matter {
+            characters.to_vec()
+        } else {
+            Vec::new()
+        };
+    }
+
     #[must_use]
     pub fn from(file_name: &str) -> Self {
         if file_name.ends_with(".rs") {
@@ -152,10 +241,172 @@ impl FileType {
                             "f32".to_string(),
                             "f64".to_string(),
                         ],
+                        mixed_indentation: false,
+                        suspicious_characters: Vec::new(),
+                        line_length_limit: None,
                 },
+                mixed_indentation_matters: true,
+                suspicious_characters_matter: true,
+                bracket_blocks: true,
+                block_comment: Some(("/*".to_string(), "*/".to_string())),
+                outline: Outline::Definitions(
+                    &["fn", "struct", "enum", "trait", "impl", "mod", "type", "macro_rules!"],
+                    &["pub", "async", "unsafe", "const", "extern", "default"],
+                ),
+                prose: false,
+            };
+        }
+        if file_name.ends_with(".md") || file_name.ends_with(".markdown") {
+            return Self {
+                name: String::from("Markdown"),
+                suspicious_characters_matter: false,
+                outline: Outline::Headings,
+                prose: true,
+                ..Self::default()
             };
         }
+        if file_name.ends_with(".txt") {
+            return Self {
+                name: String::from("Text"),
+                suspicious_characters_matter: false,
+                prose: true,
+                ..Self::default()
+            };
+        }
+        if file_name.ends_with(".sh") || file_name.ends_with(".bash") {
+            return Self::shell();
+        }
+        if file_name.ends_with(".py") {
+            return Self::python();
+        }
+        if file_name.ends_with(".js") || file_name.ends_with(".mjs") {
+            return Self::javascript();
+        }
 
         Self::default()
     }
+
+    // Like `from`, but when the file name says nothing about the file type, a `#!` on the first
+    // line gets a say, as scripts often go without an extension
+    #[must_use]
+    pub fn detect(file_name: &str, first_line: &str) -> Self {
+        let file_type = Self::from(file_name);
+        if file_type.name != Self::default().name {
+            return file_type;
+        }
+
+        Self::from_shebang(first_line).unwrap_or(file_type)
+    }
+
+    // The file type of the interpreter a `#!` line runs, looking past `env` and its flags, and past
+    // version numbers like the 3 in `python3`
+    #[must_use]
+    pub fn from_shebang(line: &str) -> Option<Self> {
+        let mut words = line.strip_prefix("#!")?.split_whitespace();
+        let mut program = words.next()?.rsplit('/').next()?;
+        if program == "env" {
+            program = words.find(|word| !word.starts_with('-'))?;
+        }
+
+        match program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.') {
+            "sh" | "bash" | "dash" | "zsh" | "ksh" => Some(Self::shell()),
+            "python" => Some(Self::python()),
+            "node" | "nodejs" => Some(Self::javascript()),
+            _ => None,
+        }
+    }
+
+    // the highlighter only knows `//` comments, so those starting with `#` here and in Python go
+    // unmarked
+    fn shell() -> Self {
+        Self {
+            name: String::from("Shell"),
+            hl_opts: HighlightingOptions {
+                numbers: true,
+                strings: true,
+                primary_keywords: words(&[
+                    "if", "then", "else", "elif", "fi", "case", "esac", "for", "while", "until", "do", "done",
+                    "in", "function", "return", "local", "export", "readonly", "select", "time",
+                ]),
+                ..HighlightingOptions::default()
+            },
+            outline: Outline::Definitions(&["function"], &[]),
+            ..Self::default()
+        }
+    }
+
+    fn python() -> Self {
+        Self {
+            name: String::from("Python"),
+            hl_opts: HighlightingOptions {
+                numbers: true,
+                strings: true,
+                primary_keywords: words(&[
+                    "and", "as", "assert", "async", "await", "break", "class", "continue", "def", "del",
+                    "elif", "else", "except", "finally", "for", "from", "global", "if", "import", "in", "is",
+                    "lambda", "nonlocal", "not", "or", "pass", "raise", "return", "try", "while", "with",
+                    "yield",
+                ]),
+                secondary_keywords: words(&[
+                    "True", "False", "None", "self", "int", "float", "str", "bool", "list", "dict", "tuple",
+                    "set", "bytes",
+                ]),
+                ..HighlightingOptions::default()
+            },
+            outline: Outline::Definitions(&["def", "class"], &["async"]),
+            ..Self::default()
+        }
+    }
+
+    fn javascript() -> Self {
+        Self {
+            name: String::from("JavaScript"),
+            hl_opts: HighlightingOptions {
+                numbers: true,
+                strings: true,
+                comments: true,
+                multiline_comments: true,
+                primary_keywords: words(&[
+                    "async", "await", "break", "case", "catch", "class", "const", "continue", "debugger",
+                    "default", "delete", "do", "else", "export", "extends", "finally", "for", "function", "if",
+                    "import", "in", "instanceof", "let", "new", "of", "return", "static", "super", "switch",
+                    "this", "throw", "try", "typeof", "var", "void", "while", "with", "yield",
+                ]),
+                secondary_keywords: words(&["true", "false", "null", "undefined", "NaN", "Infinity"]),
+                ..HighlightingOptions::default()
+            },
+            bracket_blocks: true,
+            block_comment: Some(("/*".to_string(), "*/".to_string())),
+            outline: Outline::Definitions(&["function", "class"], &["export", "default", "async"]),
+            ..Self::default()
+        }
+    }
+}
+
+fn words(list: &[&str]) -> Vec<String> {
+    list.iter().map(|word| (*word).to_string()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shebang_name(line: &str) -> Option<String> {
+        FileType::from_shebang(line).map(|file_type| file_type.name())
+    }
+
+    #[test]
+    fn knows_the_interpreter_from_a_shebang() {
+        assert_eq!(shebang_name("#!/bin/bash").as_deref(), Some("Shell"));
+        assert_eq!(shebang_name("#!/usr/bin/env -S python3").as_deref(), Some("Python"));
+        assert_eq!(shebang_name("#!/usr/bin/env node").as_deref(), Some("JavaScript"));
+        assert_eq!(shebang_name("#!/usr/bin/env ruby"), None);
+        assert_eq!(shebang_name("echo hi"), None);
+    }
+
+    #[test]
+    fn goes_by_the_extension_over_the_shebang() {
+        assert_eq!(FileType::detect("build", "#!/bin/sh").name(), "Shell");
+        assert_eq!(FileType::detect("main.rs", "#!/bin/sh").name(), "Rust");
+    }
 }
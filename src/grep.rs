@@ -0,0 +1,48 @@
+use crate::diagnostics;
+use std::process::Command;
+
+// One line a search program found
+#[derive(Clone)]
+pub struct Match {
+    pub file_name: String,
+    // counted from 0, like rows are
+    pub row: usize,
+    // counted from 1; always 1 unless the program prints columns, like `rg --vimgrep` does
+    pub column: usize,
+    pub text: String,
+}
+
+// Runs `command`, a program and its arguments split on whitespace, with `pattern` going on the
+// end as a single argument, and collects every `file:line:text` or `file:line:col:text` line it
+// prints. Finding nothing makes `grep` and `rg` exit with an error as well, so that only counts
+// as failing when they complain about something too.
+/// # Errors
+///
+pub fn run(command: &str, pattern: &str) -> Result<Vec<Match>, String> {
+    let mut words = command.split_whitespace();
+    let program = words.next().ok_or_else(|| "No grep command".to_string())?;
+    let output = Command::new(program)
+        .args(words)
+        .arg(pattern)
+        .output()
+        .map_err(|error| format!("Couldn't run {}: {}", program, error))?;
+
+    let printed = String::from_utf8_lossy(&output.stdout);
+    let matches: Vec<Match> = printed
+        .lines()
+        .filter_map(diagnostics::parse_line)
+        .filter(|(_, line, _, _)| *line > 0)
+        .map(|(file_name, line, column, text)| Match {
+            file_name: file_name.to_string(),
+            row: line.saturating_sub(1),
+            column,
+            text: text.to_string(),
+        })
+        .collect();
+
+    let complaint = String::from_utf8_lossy(&output.stderr);
+    match complaint.lines().next() {
+        Some(first) if matches.is_empty() && !output.status.success() => Err(first.to_string()),
+        _ => Ok(matches),
+    }
+}
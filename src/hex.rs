@@ -0,0 +1,136 @@
+use crate::document::write_atomically;
+
+use std::fs;
+use std::io::{Error, Write};
+
+pub const BYTES_PER_ROW: usize = 16;
+// "00000010  " in front of the bytes of every row
+const ADDRESS_WIDTH: usize = 10;
+// every byte takes two hex digits and a space
+const BYTE_WIDTH: usize = 3;
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum HexPane {
+    Bytes,
+    Ascii,
+}
+
+// A byte-oriented view of a file, shown as rows of `BYTES_PER_ROW` bytes. Bytes can be
+// overwritten but never inserted or removed, so the file keeps its size and layout.
+#[derive(Default)]
+pub struct HexDocument {
+    bytes: Vec<u8>,
+    pub file_name: Option<String>,
+    dirty: bool,
+}
+
+impl HexDocument {
+    /// # Errors
+    ///
+    pub fn open(filename: &str) -> Result<Self, Error> {
+        Ok(Self {
+            bytes: fs::read(filename)?,
+            file_name: Some(filename.to_string()),
+            dirty: false,
+        })
+    }
+
+    // number of rows, not bytes
+    #[must_use]
+    #[allow(clippy::integer_division)]
+    pub fn len(&self) -> usize {
+        self.bytes.len().saturating_add(BYTES_PER_ROW.saturating_sub(1)) / BYTES_PER_ROW
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    #[must_use]
+    pub fn byte_count(&self) -> usize {
+        self.bytes.len()
+    }
+
+    // number of bytes on row `y`
+    #[must_use]
+    pub fn row_len(&self, y: usize) -> usize {
+        let start = y.saturating_mul(BYTES_PER_ROW);
+        self.bytes.len().saturating_sub(start).min(BYTES_PER_ROW)
+    }
+
+    #[must_use]
+    pub fn byte(&self, index: usize) -> Option<u8> {
+        self.bytes.get(index).copied()
+    }
+
+    pub fn set_byte(&mut self, index: usize, value: u8) {
+        if let Some(byte) = self.bytes.get_mut(index) {
+            if *byte != value {
+                *byte = value;
+                self.dirty = true;
+            }
+        }
+    }
+
+    #[must_use]
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// # Errors
+    ///
+    pub fn save(&mut self) -> Result<(), Error> {
+        if let Some(file_name) = &self.file_name {
+            let bytes = &self.bytes;
+            write_atomically(file_name, |file| file.write_all(bytes))?;
+            self.dirty = false;
+        }
+
+        Ok(())
+    }
+
+    // Renders row `y` as its address, the hex value of each byte, and the bytes as ASCII, where
+    // anything that isn't printable shows up as a dot:
+    //
+    // 00000000  48 65 6c 6c 6f 0a                                |Hello.|
+    #[must_use]
+    pub fn render_row(&self, y: usize) -> Option<String> {
+        let start = y.saturating_mul(BYTES_PER_ROW);
+        let bytes = self.bytes.get(start..start.saturating_add(self.row_len(y)))?;
+        if bytes.is_empty() {
+            return None;
+        }
+
+        let mut hex = String::new();
+        let mut ascii = String::new();
+        for byte in bytes {
+            hex.push_str(&format!("{:02x} ", byte));
+            ascii.push(if byte.is_ascii_graphic() || *byte == b' ' {
+                char::from(*byte)
+            } else {
+                '.'
+            });
+        }
+
+        Some(format!(
+            "{:08x}  {:width$}|{}|",
+            start,
+            hex,
+            ascii,
+            width = BYTES_PER_ROW.saturating_mul(BYTE_WIDTH)
+        ))
+    }
+}
+
+// Screen column of the byte in column `x` of a row, in the given pane
+#[must_use]
+pub fn screen_column(x: usize, pane: HexPane) -> usize {
+    match pane {
+        HexPane::Bytes => ADDRESS_WIDTH.saturating_add(x.saturating_mul(BYTE_WIDTH)),
+        HexPane::Ascii => ADDRESS_WIDTH
+            .saturating_add(BYTES_PER_ROW.saturating_mul(BYTE_WIDTH))
+            .saturating_add(1)
+            .saturating_add(x),
+    }
+}
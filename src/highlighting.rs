@@ -1,6 +1,6 @@
 use termion::color;
 
-#[derive(PartialEq, Clone, Copy)]
+#[derive(PartialEq, Clone, Copy, Debug)]
 pub enum Type {
     None,
     Number,
@@ -11,19 +11,167 @@ pub enum Type {
     PrimaryKeywords,
     SecondaryKeywords,
     Match,
+    Occurrence,
+    MixedIndentation,
+    Suspicious,
+    Overflow,
 }
 
 impl Type {
     pub fn to_color(self) -> impl color::Color {
-        match self {
-            Type::Number => color::Rgb(220, 163, 163),
-            Type::String => color::Rgb(211, 54, 130),
-            Type::Character => color::Rgb(108, 113, 196),
-            Type::Comment | Type::MultilineComment => color::Rgb(133, 153, 0),
-            Type::PrimaryKeywords => color::Rgb(181, 137, 0),
-            Type::SecondaryKeywords => color::Rgb(42, 161, 152),
-            Type::Match => color::Rgb(38, 139, 210),
-            _ => color::Rgb(255, 255, 255),
+        Palette::default().color(self)
+    }
+}
+
+// Text attributes that go on top of a color
+#[derive(PartialEq, Clone, Copy, Default)]
+pub struct Style {
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+}
+
+impl Style {
+    // The escape codes that turn on what the style has
+    #[must_use]
+    pub fn on(self) -> String {
+        let mut codes = String::new();
+        if self.bold {
+            codes.push_str("\x1b[1m");
+        }
+        if self.italic {
+            codes.push_str("\x1b[3m");
+        }
+        if self.underline {
+            codes.push_str("\x1b[4m");
+        }
+        codes
+    }
+
+    // The escape codes that turn it off again, leaving other attributes such as the inverted
+    // selection alone; 22 is normal intensity, as 21 is a double underline on some terminals
+    #[must_use]
+    pub fn off(self) -> String {
+        let mut codes = String::new();
+        if self.bold {
+            codes.push_str("\x1b[22m");
+        }
+        if self.italic {
+            codes.push_str("\x1b[23m");
+        }
+        if self.underline {
+            codes.push_str("\x1b[24m");
+        }
+        codes
+    }
+}
+
+// The style each kind of token is drawn in, all plain unless set
+#[derive(PartialEq, Clone, Default)]
+pub struct Styles {
+    pub number: Style,
+    pub string: Style,
+    pub character: Style,
+    // both single and multiline comments
+    pub comment: Style,
+    pub primary_keyword: Style,
+    pub secondary_keyword: Style,
+    pub search_match: Style,
+    pub occurrence: Style,
+}
+
+// The color each highlighting type is drawn in. Override single entries with
+// `Palette { string: color::Rgb(..), ..Palette::default() }` to keep the built-in colors for the
+// rest.
+#[derive(PartialEq, Clone)]
+pub struct Palette {
+    pub text: color::Rgb,
+    pub number: color::Rgb,
+    pub string: color::Rgb,
+    pub character: color::Rgb,
+    // both single and multiline comments
+    pub comment: color::Rgb,
+    pub primary_keyword: color::Rgb,
+    pub secondary_keyword: color::Rgb,
+    pub search_match: color::Rgb,
+    pub occurrence: color::Rgb,
+    // a background, since indentation has nothing to color in
+    pub mixed_indentation: color::Rgb,
+    // a background as well, behind the placeholder invisible characters are drawn as
+    pub suspicious: color::Rgb,
+    // behind the part of a row past the line length limit
+    pub overflow: color::Rgb,
+    // background behind the cursor line and column; foreground highlights are drawn on top of it
+    pub crosshair: color::Rgb,
+    // the dots and arrows that stand in for spaces and tabs when whitespace is shown
+    pub whitespace: color::Rgb,
+    // bold, italic or underlined tokens, e.g. `styles.comment.italic = true`
+    pub styles: Styles,
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self {
+            text: color::Rgb(255, 255, 255),
+            number: color::Rgb(220, 163, 163),
+            string: color::Rgb(211, 54, 130),
+            character: color::Rgb(108, 113, 196),
+            comment: color::Rgb(133, 153, 0),
+            primary_keyword: color::Rgb(181, 137, 0),
+            secondary_keyword: color::Rgb(42, 161, 152),
+            search_match: color::Rgb(38, 139, 210),
+            occurrence: color::Rgb(147, 161, 161),
+            mixed_indentation: color::Rgb(203, 75, 22),
+            suspicious: color::Rgb(220, 50, 47),
+            overflow: color::Rgb(88, 24, 24),
+            crosshair: color::Rgb(48, 48, 48),
+            whitespace: color::Rgb(88, 88, 88),
+            styles: Styles::default(),
+        }
+    }
+}
+
+impl Palette {
+    #[must_use]
+    pub fn color(&self, highlighting_type: Type) -> color::Rgb {
+        match highlighting_type {
+            Type::Number => self.number,
+            Type::String => self.string,
+            Type::Character => self.character,
+            Type::Comment | Type::MultilineComment => self.comment,
+            Type::PrimaryKeywords => self.primary_keyword,
+            Type::SecondaryKeywords => self.secondary_keyword,
+            Type::Match => self.search_match,
+            Type::Occurrence => self.occurrence,
+            Type::MixedIndentation => self.mixed_indentation,
+            // the placeholder has to stand out from the background it is drawn on
+            Type::Suspicious | Type::Overflow | Type::None => self.text,
+        }
+    }
+
+    #[must_use]
+    pub fn style(&self, highlighting_type: Type) -> Style {
+        match highlighting_type {
+            Type::Number => self.styles.number,
+            Type::String => self.styles.string,
+            Type::Character => self.styles.character,
+            Type::Comment | Type::MultilineComment => self.styles.comment,
+            Type::PrimaryKeywords => self.styles.primary_keyword,
+            Type::SecondaryKeywords => self.styles.secondary_keyword,
+            Type::Match => self.styles.search_match,
+            Type::Occurrence => self.styles.occurrence,
+            _ => Style::default(),
+        }
+    }
+
+    // The color drawn behind the types that are shown by their background rather than their text
+    #[must_use]
+    pub fn background(&self, highlighting_type: Type) -> Option<color::Rgb> {
+        match highlighting_type {
+            Type::MixedIndentation => Some(self.mixed_indentation),
+            Type::Suspicious => Some(self.suspicious),
+            Type::Overflow => Some(self.overflow),
+            _ => None,
         }
     }
 }
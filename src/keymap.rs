@@ -0,0 +1,280 @@
+use std::collections::HashMap;
+use termion::event::Key;
+
+// What a key, or a chord of keys pressed one after the other, can be bound to
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub enum Action {
+    Quit,
+    Save,
+    Command,
+    Find,
+    Replace,
+    GotoLine,
+    Undo,
+    Redo,
+    Copy,
+    Cut,
+    Paste,
+    RepeatChange,
+    Reload,
+    ToggleOverwrite,
+    ToggleSelection,
+    NextGrepMatch,
+    PreviousGrepMatch,
+    NextDiagnostic,
+    PreviousDiagnostic,
+    Increment,
+    Decrement,
+    ToggleLineNumbers,
+    ToggleAutoIndent,
+    ToggleFold,
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    PageUp,
+    PageDown,
+    Home,
+    End,
+}
+
+// the name of every action, as a `.simrc` gives it
+const ACTION_NAMES: [(Action, &str); 32] = [
+    (Action::Quit, "quit"),
+    (Action::Save, "save"),
+    (Action::Command, "command"),
+    (Action::Find, "find"),
+    (Action::Replace, "replace"),
+    (Action::GotoLine, "goto-line"),
+    (Action::Undo, "undo"),
+    (Action::Redo, "redo"),
+    (Action::Copy, "copy"),
+    (Action::Cut, "cut"),
+    (Action::Paste, "paste"),
+    (Action::RepeatChange, "repeat"),
+    (Action::Reload, "reload"),
+    (Action::ToggleOverwrite, "overwrite"),
+    (Action::ToggleSelection, "select"),
+    (Action::NextGrepMatch, "next-match"),
+    (Action::PreviousGrepMatch, "previous-match"),
+    (Action::NextDiagnostic, "next-diagnostic"),
+    (Action::PreviousDiagnostic, "previous-diagnostic"),
+    (Action::Increment, "increment"),
+    (Action::Decrement, "decrement"),
+    (Action::ToggleLineNumbers, "line-numbers"),
+    (Action::ToggleAutoIndent, "auto-indent"),
+    (Action::ToggleFold, "fold"),
+    (Action::MoveUp, "up"),
+    (Action::MoveDown, "down"),
+    (Action::MoveLeft, "left"),
+    (Action::MoveRight, "right"),
+    (Action::PageUp, "page-up"),
+    (Action::PageDown, "page-down"),
+    (Action::Home, "home"),
+    (Action::End, "end"),
+];
+
+impl Action {
+    #[must_use]
+    pub fn named(name: &str) -> Option<Self> {
+        ACTION_NAMES.iter().find(|(_, action_name)| *action_name == name).map(|(action, _)| *action)
+    }
+
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        ACTION_NAMES.iter().find(|(action, _)| *action == self).map_or("", |(_, name)| name)
+    }
+}
+
+// What the keys pressed so far come to
+#[derive(PartialEq, Clone, Copy)]
+pub enum Lookup {
+    Action(Action),
+    // the start of a chord, waiting for the rest of it
+    Prefix,
+    Unbound,
+    // a chord that turned out not to be bound, dropped whole rather than typing its last key
+    Swallowed,
+}
+
+// Which keys do what. The default binds the keys sim has always used.
+#[derive(Clone)]
+pub struct Keymap {
+    bindings: HashMap<Vec<Key>, Action>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let bindings = [
+            (Key::Ctrl('q'), Action::Quit),
+            (Key::Ctrl('s'), Action::Save),
+            (Key::Ctrl('e'), Action::Command),
+            (Key::Ctrl('f'), Action::Find),
+            (Key::Ctrl('r'), Action::Replace),
+            (Key::Ctrl('g'), Action::GotoLine),
+            (Key::Ctrl('z'), Action::Undo),
+            (Key::Ctrl('y'), Action::Redo),
+            (Key::Ctrl('c'), Action::Copy),
+            (Key::Ctrl('x'), Action::Cut),
+            (Key::Ctrl('v'), Action::Paste),
+            (Key::Alt('.'), Action::RepeatChange),
+            (Key::Alt('r'), Action::Reload),
+            (Key::Insert, Action::ToggleOverwrite),
+            (Key::Null, Action::ToggleSelection),
+            (Key::Ctrl('n'), Action::NextGrepMatch),
+            (Key::Ctrl('p'), Action::PreviousGrepMatch),
+            (Key::Alt('n'), Action::NextDiagnostic),
+            (Key::Alt('p'), Action::PreviousDiagnostic),
+            (Key::Ctrl('a'), Action::Increment),
+            (Key::Alt('a'), Action::Decrement),
+            (Key::Ctrl('l'), Action::ToggleLineNumbers),
+            (Key::Alt('i'), Action::ToggleAutoIndent),
+            (Key::Ctrl('t'), Action::ToggleFold),
+            (Key::Up, Action::MoveUp),
+            (Key::Down, Action::MoveDown),
+            (Key::Left, Action::MoveLeft),
+            (Key::Right, Action::MoveRight),
+            (Key::PageUp, Action::PageUp),
+            (Key::PageDown, Action::PageDown),
+            (Key::Home, Action::Home),
+            (Key::End, Action::End),
+        ];
+
+        Self {
+            bindings: bindings.iter().map(|(key, action)| (vec![*key], *action)).collect(),
+        }
+    }
+}
+
+impl Keymap {
+    // Binds `keys` to `action`, over whatever they did before. Keys that start a longer chord, or
+    // that a shorter binding starts, would keep one of the two from ever being pressed, so those
+    // aren't bound.
+    /// # Errors
+    ///
+    pub fn bind(&mut self, keys: Vec<Key>, action: Action) -> Result<(), String> {
+        if keys.is_empty() {
+            return Err("no keys to bind".to_string());
+        }
+        let conflict = self.bindings.iter().find(|(bound, _)| {
+            **bound != keys && (bound.starts_with(&keys) || keys.starts_with(bound))
+        });
+        if let Some((bound, bound_action)) = conflict {
+            return Err(format!(
+                "{} conflicts with {}, which is {}",
+                describe(&keys),
+                describe(bound),
+                bound_action.name()
+            ));
+        }
+
+        self.bindings.insert(keys, action);
+        Ok(())
+    }
+
+    pub fn unbind(&mut self, keys: &[Key]) {
+        self.bindings.remove(keys);
+    }
+
+    #[must_use]
+    pub fn lookup(&self, keys: &[Key]) -> Lookup {
+        if let Some(action) = self.bindings.get(keys) {
+            return Lookup::Action(*action);
+        }
+        if self.bindings.keys().any(|bound| bound.starts_with(keys)) {
+            return Lookup::Prefix;
+        }
+
+        Lookup::Unbound
+    }
+
+    // The shortest keys bound to `action`, to tell the user what to press, if any are
+    #[must_use]
+    pub fn keys_for(&self, action: Action) -> Option<&[Key]> {
+        self.bindings
+            .iter()
+            .filter(|(_, bound_action)| **bound_action == action)
+            .map(|(keys, _)| keys.as_slice())
+            .min_by_key(|keys| (keys.len(), describe(keys)))
+    }
+}
+
+// Keys written the way a `.simrc` gives them, separated by spaces: `ctrl-k d`, `alt-.`, `f5`,
+// `page-down`
+/// # Errors
+///
+pub fn parse_keys(text: &str) -> Result<Vec<Key>, String> {
+    text.split_whitespace().map(parse_key).collect()
+}
+
+fn parse_key(text: &str) -> Result<Key, String> {
+    let lowered = text.to_lowercase();
+    let single = |rest: &str| {
+        let mut chars = rest.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Some(c),
+            _ => None,
+        }
+    };
+    if let Some(c) = lowered.strip_prefix("ctrl-").and_then(single) {
+        return Ok(Key::Ctrl(c));
+    }
+    if let Some(c) = text.get(4..).filter(|_| lowered.starts_with("alt-")).and_then(single) {
+        return Ok(Key::Alt(c));
+    }
+    if let Some(number) = lowered.strip_prefix('f').and_then(|number| number.parse().ok()) {
+        return Ok(Key::F(number));
+    }
+
+    match lowered.as_str() {
+        "ctrl-space" => Ok(Key::Null),
+        "up" => Ok(Key::Up),
+        "down" => Ok(Key::Down),
+        "left" => Ok(Key::Left),
+        "right" => Ok(Key::Right),
+        "page-up" => Ok(Key::PageUp),
+        "page-down" => Ok(Key::PageDown),
+        "home" => Ok(Key::Home),
+        "end" => Ok(Key::End),
+        "insert" => Ok(Key::Insert),
+        "delete" => Ok(Key::Delete),
+        "backspace" => Ok(Key::Backspace),
+        "esc" => Ok(Key::Esc),
+        "tab" => Ok(Key::Char('\t')),
+        "enter" => Ok(Key::Char('\n')),
+        "space" => Ok(Key::Char(' ')),
+        _ => single(text).map(Key::Char).ok_or_else(|| format!("unknown key {}", text)),
+    }
+}
+
+// e.g. "Ctrl-K D"
+#[must_use]
+pub fn describe(keys: &[Key]) -> String {
+    keys.iter().map(|key| describe_key(*key)).collect::<Vec<String>>().join(" ")
+}
+
+fn describe_key(key: Key) -> String {
+    match key {
+        Key::Ctrl(c) => format!("Ctrl-{}", c.to_uppercase()),
+        Key::Alt(c) => format!("Alt-{}", c),
+        Key::F(number) => format!("F{}", number),
+        Key::Null => "Ctrl-Space".to_string(),
+        Key::Char('\t') => "Tab".to_string(),
+        Key::Char('\n') => "Enter".to_string(),
+        Key::Char(' ') => "Space".to_string(),
+        Key::Char(c) => c.to_string(),
+        Key::Up => "Up".to_string(),
+        Key::Down => "Down".to_string(),
+        Key::Left => "Left".to_string(),
+        Key::Right => "Right".to_string(),
+        Key::PageUp => "PageUp".to_string(),
+        Key::PageDown => "PageDown".to_string(),
+        Key::Home => "Home".to_string(),
+        Key::End => "End".to_string(),
+        Key::Insert => "Insert".to_string(),
+        Key::Delete => "Delete".to_string(),
+        Key::Backspace => "Backspace".to_string(),
+        Key::Esc => "Esc".to_string(),
+        _ => "?".to_string(),
+    }
+}
@@ -0,0 +1,76 @@
+//! The editing core of the SIM editor.
+//!
+//! Everything needed to load, edit, search, highlight, and save a buffer lives in `Document`,
+//! `Row`, `Position`, `SearchDirection`, `Pattern`, `FileType`, and `HighlightingOptions`, with
+//! `HexDocument` as the byte-oriented counterpart for binary files and `StreamDocument` as a
+//! read-only one for files too big to load; none of these talk to the terminal, so they can be
+//! embedded in any front end, and what a `Row` was highlighted as can be read back as
+//! `HighlightType`s. `Editor` is the terminal UI built on top of them and draws through a
+//! `Backend`: `Terminal` for a real tty, or `TestBackend` to drive it with scripted keys, and is
+//! tuned through `Config`, whose `Theme` holds every color it draws with.
+
+// https://www.philippflenker.com/hecto/
+
+#![warn(clippy::all, clippy::pedantic, clippy::restriction)]
+#![allow(
+    clippy::missing_docs_in_private_items,
+    clippy::implicit_return,
+    clippy::shadow_reuse,
+    clippy::print_stdout,
+    clippy::wildcard_enum_match_arm,
+    clippy::else_if_without_else
+)]
+
+mod editor;
+mod terminal;
+mod document;
+mod row;
+mod highlighting;
+mod filetype;
+mod config;
+mod hex;
+mod diff;
+mod theme;
+mod undo;
+mod state;
+mod diagnostics;
+mod stream;
+mod grep;
+mod editorconfig;
+mod pattern;
+mod keymap;
+
+pub use editor::Editor;
+pub use terminal::Backend;
+pub use terminal::Size;
+pub use terminal::Terminal;
+pub use terminal::TestBackend;
+pub use editor::Position;
+pub use editor::SearchDirection;
+pub use document::Document;
+pub use document::LineEnding;
+pub use document::Match;
+pub use undo::Selection;
+pub use hex::HexDocument;
+pub use stream::StreamDocument;
+pub use row::Row;
+pub use filetype::FileType;
+pub use filetype::HighlightingOptions;
+pub use filetype::Outline;
+pub use config::Bell;
+pub use config::Config;
+pub use keymap::Action;
+pub use keymap::Keymap;
+pub use config::LineNumbers;
+pub use config::MissingDirectories;
+pub use config::SUSPICIOUS_CHARACTERS;
+pub use diagnostics::Diagnostic;
+pub use editorconfig::EditorConfig;
+pub use pattern::Pattern;
+pub use pattern::SearchMode;
+pub use diagnostics::Severity;
+pub use highlighting::Palette;
+pub use highlighting::Style;
+pub use highlighting::Styles;
+pub use highlighting::Type as HighlightType;
+pub use theme::Theme;
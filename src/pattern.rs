@@ -0,0 +1,148 @@
+use regex::{Regex, RegexBuilder};
+
+// How a search query is matched: as the text it is, or as a regular expression
+#[derive(PartialEq, Clone, Copy)]
+pub enum SearchMode {
+    Plain,
+    Regex,
+}
+
+impl Default for SearchMode {
+    fn default() -> Self {
+        Self::Plain
+    }
+}
+
+impl SearchMode {
+    // the other mode, for switching back and forth
+    #[must_use]
+    pub fn toggled(self) -> Self {
+        match self {
+            Self::Plain => Self::Regex,
+            Self::Regex => Self::Plain,
+        }
+    }
+}
+
+// A search query made ready for matching rows against, compiled once if it is a regular
+// expression or has to ignore case
+pub struct Pattern {
+    query: String,
+    regex: Option<Regex>,
+}
+
+impl Pattern {
+    // Ignoring case goes through a regular expression made of the escaped query, as lowercasing
+    // the text can change how many bytes a character takes, which would throw the match's place in
+    // the original off
+    #[must_use]
+    pub fn plain(query: &str, ignore_case: bool) -> Self {
+        let regex = if ignore_case && !query.is_empty() {
+            RegexBuilder::new(&regex::escape(query)).case_insensitive(true).build().ok()
+        } else {
+            None
+        };
+
+        Self {
+            query: query.to_string(),
+            regex,
+        }
+    }
+
+    // The pattern for `query` in `mode`, failing with what is wrong with a regular expression that
+    // doesn't compile
+    /// # Errors
+    ///
+    pub fn new(query: &str, mode: SearchMode, ignore_case: bool) -> Result<Self, String> {
+        if mode == SearchMode::Plain {
+            return Ok(Self::plain(query, ignore_case));
+        }
+        let regex = RegexBuilder::new(query)
+            .case_insensitive(ignore_case)
+            .build()
+            .map_err(|error| {
+                // the full message draws the pattern with a pointer under it over several lines
+                let message = error.to_string();
+                let last = message.lines().last().unwrap_or_default().trim();
+                last.strip_prefix("error: ").unwrap_or(last).to_string()
+            })?;
+
+        Ok(Self {
+            query: query.to_string(),
+            regex: Some(regex),
+        })
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.query.is_empty()
+    }
+
+    // The byte range of the first match in `text[from..to]`, or with `last` of the last one. A
+    // regular expression still sees the text around the range, so `^` and `\b` mean what they do
+    // in the whole row, and its empty matches are passed over, as they have nothing to show or
+    // step past.
+    #[must_use]
+    pub fn find_in(&self, text: &str, from: usize, to: usize, last: bool) -> Option<(usize, usize)> {
+        let range = text.get(from..to)?;
+        if let Some(regex) = &self.regex {
+            let mut found = None;
+            let mut at = from;
+            while let Some(next) = regex.find_at(text, at) {
+                if next.end() > to {
+                    break;
+                }
+                if next.start() < next.end() {
+                    found = Some((next.start(), next.end()));
+                    if !last {
+                        break;
+                    }
+                }
+                // carry on a character past where this match starts, not where it ends, so that a
+                // later match overlapping it is found too, and an empty match is stepped over
+                let step = text.get(next.start()..).and_then(|rest| rest.chars().next());
+                at = step.map_or(to.saturating_add(1), |c| next.start().saturating_add(c.len_utf8()));
+                if at > to {
+                    break;
+                }
+            }
+            return found;
+        }
+
+        let start = if last { range.rfind(&self.query) } else { range.find(&self.query) }?;
+        Some((from.saturating_add(start), from.saturating_add(start).saturating_add(self.query.len())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ignoring_case_finds_the_match_where_it_is_in_the_original() {
+        // the Kelvin sign takes 3 bytes and lowercases to a 1 byte `k`
+        let text = "\u{212a}elvin and KELVIN";
+        let pattern = Pattern::plain("kelvin", true);
+        assert_eq!(pattern.find_in(text, 0, text.len(), false), Some((0, 8)));
+        assert_eq!(pattern.find_in(text, 0, text.len(), true), Some((13, 19)));
+
+        // a dotted capital I lowercases to 3 bytes from 2
+        let text = "\u{130}\u{130} foo";
+        assert_eq!(Pattern::plain("FOO", true).find_in(text, 0, text.len(), false), Some((5, 8)));
+    }
+
+    #[test]
+    fn respecting_case_skips_other_cases() {
+        let text = "Foo foo";
+        assert_eq!(Pattern::plain("foo", false).find_in(text, 0, text.len(), false), Some((4, 7)));
+        assert_eq!(Pattern::plain("foo", true).find_in(text, 0, text.len(), false), Some((0, 3)));
+    }
+
+    #[test]
+    fn last_regex_match_may_overlap_an_earlier_one() {
+        let pattern = Pattern::new("aa", SearchMode::Regex, false).unwrap();
+        assert_eq!(pattern.find_in("aaa", 0, 3, true), Some((1, 3)));
+        assert_eq!(pattern.find_in("aaa", 0, 3, false), Some((0, 2)));
+        assert_eq!(pattern.find_in("aaa", 0, 2, true), Some((0, 2)));
+    }
+}
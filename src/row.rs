@@ -1,16 +1,39 @@
+use std::cell::RefCell;
 use std::cmp;
-use termion::color;
+use std::fmt;
+use termion::{color, style};
 use unicode_segmentation::UnicodeSegmentation;
 
-use crate::highlighting;
+use crate::highlighting::{self, Palette};
 use crate::HighlightingOptions;
+use crate::Pattern;
 use crate::SearchDirection;
 
+// what a flagged character is drawn as, since it may well have no look of its own
+const SUSPICIOUS_PLACEHOLDER: char = '\u{fffd}';
+// how far apart `render` puts the tab stops
+const TAB_WIDTH: usize = 4;
+
+// what a cached render was produced for
+#[derive(PartialEq)]
+struct RenderKey {
+    start: usize,
+    end: usize,
+    column: Option<usize>,
+    selected: Option<(usize, usize)>,
+    show_whitespace: bool,
+    tab_width: usize,
+    palette: Palette,
+}
+
 #[derive(Default)]
 pub struct Row {
     string: String,
     len: usize,
     highlighting: Vec<highlighting::Type>,
+    // the last output of `render` and what it was rendered for; dropped whenever the text or its
+    // highlighting changes
+    rendered: RefCell<Option<(RenderKey, String)>>,
 }
 
 impl From<&str> for Row {
@@ -19,18 +42,65 @@ impl From<&str> for Row {
             string: String::from(slice),
             len: slice.graphemes(true).count(),
             highlighting: Vec::new(),
+            rendered: RefCell::new(None),
         }
     }
 }
 
+impl fmt::Display for Row {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.string)
+    }
+}
+
 impl Row {
     #[must_use]
     pub fn render(&self, start: usize, end: usize) -> String {
+        self.render_with(start, end, None, None, false, TAB_WIDTH, &Palette::default())
+    }
+
+    // Like `render`, but puts the crosshair background behind the grapheme at `column`, padding
+    // the row out to it when the row is shorter, and inverts the graphemes in the `selected`
+    // range. A selected grapheme shows as selected even where the crosshair runs through it.
+    // With `show_whitespace`, spaces show as `·` and tabs as `→`. Tabs reach to the next
+    // multiple of `tab_width` columns from the start of the row. Highlighted text is drawn in the
+    // colors of `palette`.
+    #[must_use]
+    #[allow(clippy::too_many_arguments)]
+    pub fn render_with(
+        &self,
+        start: usize,
+        end: usize,
+        column: Option<usize>,
+        selected: Option<(usize, usize)>,
+        show_whitespace: bool,
+        tab_width: usize,
+        palette: &Palette,
+    ) -> String {
+        let key = RenderKey {
+            start,
+            end,
+            column,
+            selected,
+            show_whitespace,
+            tab_width,
+            palette: palette.clone(),
+        };
+        if let Some((cached_key, rendered)) = &*self.rendered.borrow() {
+            if *cached_key == key {
+                return rendered.clone();
+            }
+        }
+
+        let window = (start, end);
         let end = cmp::min(end, self.string.len());
         let start = cmp::min(start, end);
 
         let mut result = String::new();
         let mut current_highlighting = &highlighting::Type::None;
+        let mut in_selection = false;
+        // the screen column the grapheme being drawn starts at, counted from the start of the row
+        let mut screen_column = self.render_width(0, start, tab_width);
         #[allow(clippy::integer_arithmetic)]
         for (index, grapheme) in self.string[..]
             .graphemes(true)
@@ -44,33 +114,141 @@ impl Row {
                     .get(index)
                     .unwrap_or(&highlighting::Type::None);
                 if highlighting_type != current_highlighting {
+                    // whitespace and invisible characters have nothing to color in, so these go
+                    // in the background
+                    if let Some(background) = palette.background(*highlighting_type) {
+                        result.push_str(&format!("{}", color::Bg(background)));
+                    } else if palette.background(*current_highlighting).is_some() {
+                        result.push_str(&format!("{}", color::Bg(color::Reset)));
+                    }
+                    result.push_str(&palette.style(*current_highlighting).off());
                     current_highlighting = highlighting_type;
                     let start_highlight =
-                        format!("{}", termion::color::Fg(highlighting_type.to_color()));
+                        format!("{}", termion::color::Fg(palette.color(*highlighting_type)));
                     result.push_str(&start_highlight[..]);
+                    result.push_str(&palette.style(*highlighting_type).on());
+                }
+
+                let is_selected = selected.map_or(false, |(from, to)| index >= from && index < to);
+                if is_selected != in_selection {
+                    in_selection = is_selected;
+                    if is_selected {
+                        result.push_str(&format!("{}", style::Invert));
+                    } else {
+                        result.push_str(&format!("{}", style::NoInvert));
+                    }
                 }
 
-                // replace tab with two spaces
-                if c == '\t' {
-                    result.push_str("  ");
+                let tinted = column == Some(index) && !is_selected;
+                if tinted {
+                    result.push_str(&format!("{}", color::Bg(palette.crosshair)));
+                }
+                if *highlighting_type == highlighting::Type::Suspicious {
+                    result.push(SUSPICIOUS_PLACEHOLDER);
+                } else if show_whitespace && (c == ' ' || c == '\t') {
+                    let shown = if c == ' ' {
+                        "\u{b7}".to_string()
+                    } else {
+                        format!("\u{2192}{}", " ".repeat(tab_stop(screen_column, tab_width).saturating_sub(1)))
+                    };
+                    result.push_str(&format!(
+                        "{}{}{}",
+                        color::Fg(palette.whitespace),
+                        shown,
+                        color::Fg(palette.color(*current_highlighting))
+                    ));
+                } else if c == '\t' {
+                    result.push_str(&" ".repeat(tab_stop(screen_column, tab_width)));
                 } else {
                     result.push(c);
                 }
+                if tinted {
+                    result.push_str(&format!("{}", color::Bg(color::Reset)));
+                }
+                screen_column = screen_column.saturating_add(grapheme_width(grapheme, screen_column, tab_width));
+            }
+        }
+
+        if in_selection {
+            result.push_str(&format!("{}", style::NoInvert));
+        }
+        if palette.background(*current_highlighting).is_some() {
+            result.push_str(&format!("{}", color::Bg(color::Reset)));
+        }
+        result.push_str(&palette.style(*current_highlighting).off());
+
+        if let Some(column) = column {
+            let padding_start = cmp::max(end, window.0);
+            if column >= padding_start && column < window.1 {
+                result.push_str(&" ".repeat(column.saturating_sub(padding_start)));
+                result.push_str(&format!(
+                    "{} {}",
+                    color::Bg(palette.crosshair),
+                    color::Bg(color::Reset)
+                ));
             }
         }
 
         let end_highlighting = format!("{}", termion::color::Fg(color::Reset));
         result.push_str(&end_highlighting[..]);
 
+        *self.rendered.borrow_mut() = Some((key, result.clone()));
         result
     }
 
+    // How many screen columns `render_with` takes up for the same window and tab width
+    #[must_use]
+    pub fn render_width(&self, start: usize, end: usize, tab_width: usize) -> usize {
+        let mut screen_column: usize = 0;
+        let mut width: usize = 0;
+        for (index, grapheme) in self.string[..].graphemes(true).enumerate().take(end) {
+            let grapheme_width = grapheme_width(grapheme, screen_column, tab_width);
+            if index >= start {
+                width = width.saturating_add(grapheme_width);
+            }
+            screen_column = screen_column.saturating_add(grapheme_width);
+        }
+
+        width
+    }
+
+    // Where a window starting at `start` has to end to fit in `width` screen columns
+    #[must_use]
+    pub fn fit(&self, start: usize, width: usize, tab_width: usize) -> usize {
+        let mut screen_column = self.render_width(0, start, tab_width);
+        let mut room = width;
+        for (index, grapheme) in self.string[..].graphemes(true).enumerate().skip(start) {
+            let grapheme_width = grapheme_width(grapheme, screen_column, tab_width);
+            if grapheme_width > room {
+                return index;
+            }
+            room = room.saturating_sub(grapheme_width);
+            screen_column = screen_column.saturating_add(grapheme_width);
+        }
+
+        // past the end of the row, each column left is one more grapheme's worth of room
+        cmp::max(start, self.len).saturating_add(room)
+    }
+
+    fn invalidate_render(&mut self) {
+        *self.rendered.get_mut() = None;
+    }
+
+    #[must_use]
+    pub fn word_count(&self) -> usize {
+        self.string
+            .split_whitespace()
+            .filter(|word| word.chars().any(char::is_alphanumeric))
+            .count()
+    }
+
     #[must_use]
     pub fn len(&self) -> usize {
         self.string[..].graphemes(true).count()
     }
 
     pub fn insert(&mut self, at: usize, c: char) {
+        self.invalidate_render();
         if at >= self.len() {
             self.string.push(c);
 
@@ -105,6 +283,7 @@ impl Row {
 
     #[allow(clippy::integer_arithmetic)]
     pub fn delete(&mut self, at: usize) {
+        self.invalidate_render();
         if at >= self.len() {
             return;
         }
@@ -124,12 +303,14 @@ impl Row {
 
     #[allow(clippy::integer_arithmetic)]
     pub fn append(&mut self, new: &Self) {
+        self.invalidate_render();
         self.string = format!("{}{}", self.string, new.string);
         self.len += new.len;
     }
 
     #[allow(clippy::integer_arithmetic)]
     pub fn split(&mut self, at: usize) -> Self {
+        self.invalidate_render();
         let mut row: String = String::new();
         let mut length = 0;
         let mut splitted_row: String = String::new();
@@ -151,9 +332,96 @@ impl Row {
             string: splitted_row,
             len: splitted_row_length,
             highlighting: Vec::new(),
+            rendered: RefCell::new(None),
         }
     }
 
+    // Swaps the `find_len` graphemes at `at` for `with`
+    pub fn replace(&mut self, at: usize, find_len: usize, with: &str) {
+        self.invalidate_render();
+        let mut result: String = self.string[..].graphemes(true).take(at).collect();
+        result.push_str(with);
+        result.extend(self.string[..].graphemes(true).skip(at.saturating_add(find_len)));
+
+        self.len = result[..].graphemes(true).count();
+        self.string = result;
+    }
+
+    // the graphemes from `start` up to, but not including, `end`
+    #[must_use]
+    pub fn slice(&self, start: usize, end: usize) -> String {
+        self.string[..]
+            .graphemes(true)
+            .skip(start)
+            .take(end.saturating_sub(start))
+            .collect()
+    }
+
+    // the spaces and tabs the row starts with
+    #[must_use]
+    #[allow(clippy::indexing_slicing)]
+    pub fn indentation(&self) -> &str {
+        let end = self
+            .string
+            .find(|c: char| c != ' ' && c != '\t')
+            .unwrap_or_else(|| self.string.len());
+
+        &self.string[..end]
+    }
+
+    // Swaps the leading spaces and tabs for `indentation`
+    pub fn set_indentation(&mut self, indentation: &str) {
+        self.invalidate_render();
+        let rest = self.string[self.indentation().len()..].to_string();
+        self.string = format!("{}{}", indentation, rest);
+        self.len = self.string[..].graphemes(true).count();
+    }
+
+    // The brackets in the row that are code, leaving out those in strings and comments as of the
+    // last `highlight`
+    #[must_use]
+    pub fn code_brackets(&self) -> Vec<char> {
+        self.string
+            .chars()
+            .enumerate()
+            .filter(|(_, c)| "{}()[]".contains(*c))
+            .filter(|(index, _)| {
+                !matches!(
+                    self.highlighting.get(*index),
+                    Some(
+                        highlighting::Type::String
+                            | highlighting::Type::Character
+                            | highlighting::Type::Comment
+                            | highlighting::Type::MultilineComment
+                    )
+                )
+            })
+            .map(|(_, c)| c)
+            .collect()
+    }
+
+    // What the last `highlight` and the passes after it made of each character, empty until the
+    // row is highlighted
+    #[must_use]
+    pub fn highlighting(&self) -> &[highlighting::Type] {
+        &self.highlighting
+    }
+
+    // Every character along with how it is highlighted, `None` for any the highlighting doesn't
+    // reach
+    pub fn highlighted_chars(&self) -> impl Iterator<Item = (char, highlighting::Type)> + '_ {
+        self.string.chars().enumerate().map(move |(index, c)| {
+            (c, self.highlighting.get(index).copied().unwrap_or(highlighting::Type::None))
+        })
+    }
+
+    // Whether the row starts out inside a multiline comment
+    #[must_use]
+    pub fn starts_in_comment(&self) -> bool {
+        self.highlighting.first() == Some(&highlighting::Type::MultilineComment)
+            && !self.string.starts_with("/*")
+    }
+
     #[must_use]
     pub fn as_bytes(&self) -> &[u8] {
         self.string.as_bytes()
@@ -164,41 +432,43 @@ impl Row {
         self.string.is_empty()
     }
 
+    // The first match of `query` from `at` on, or the last one before it, as the index it starts
+    // at and how many graphemes it takes up, which need not be as many as the query has
+    #[must_use]
+    pub fn find(
+        &self,
+        query: &str,
+        at: usize,
+        direction: SearchDirection,
+        ignore_case: bool,
+    ) -> Option<(usize, usize)> {
+        self.find_pattern(&Pattern::plain(query, ignore_case), at, direction)
+    }
+
+    // Like `find`, for a plain query or a regular expression
     #[must_use]
-    pub fn find(&self, query: &str, at: usize, direction: SearchDirection) -> Option<usize> {
-        if at > self.len || query.is_empty() {
+    pub fn find_pattern(&self, pattern: &Pattern, at: usize, direction: SearchDirection) -> Option<(usize, usize)> {
+        if at > self.len || pattern.is_empty() {
             return None;
         }
-        let start = if direction == SearchDirection::Forward {
-            at
-        } else {
-            0
-        };
-        let end = if direction == SearchDirection::Forward {
-            self.len
-        } else {
-            at
+        let byte_index = |index: usize| {
+            self.string[..]
+                .grapheme_indices(true)
+                .nth(index)
+                .map_or(self.string.len(), |(byte_index, _)| byte_index)
         };
-        #[allow(clippy::integer_arithmetic)]
-        let substring: String = self.string[..]
-            .graphemes(true)
-            .skip(start)
-            .take(end - start)
-            .collect();
-        let matching_byte_index = if direction == SearchDirection::Forward {
-            substring.find(query)
+        let at = byte_index(at);
+        let (from, to) = if direction == SearchDirection::Forward {
+            (at, self.string.len())
         } else {
-            substring.rfind(query)
+            (0, at)
         };
 
-        if let Some(matching_byte_index) = matching_byte_index {
-            for (grapheme_index, (byte_index, _)) in
-                substring[..].grapheme_indices(true).enumerate()
-            {
-                if matching_byte_index == byte_index {
-                    #[allow(clippy::integer_arithmetic)]
-                    return Some(start + grapheme_index);
-                }
+        let (start, end) = pattern.find_in(&self.string, from, to, direction == SearchDirection::Backward)?;
+        let matched = self.string.get(start..end.min(self.string.len()))?;
+        for (grapheme_index, (byte_index, _)) in self.string[..].grapheme_indices(true).enumerate() {
+            if start == byte_index {
+                return Some((grapheme_index, matched.graphemes(true).count()));
             }
         }
 
@@ -212,6 +482,7 @@ impl Row {
         word: Option<&str>,
         starts_with_comment: bool,
     ) -> bool {
+        self.invalidate_render();
         self.highlighting = Vec::new();
         let chars: Vec<char> = self.string.chars().collect();
         let mut index = 0;
@@ -246,31 +517,122 @@ impl Row {
             self.highlighting.push(highlighting::Type::None);
             index += 1;
         }
-        self.highlight_match(word);
+        if opts.mixed_indentation() {
+            self.highlight_mixed_indentation();
+        }
+        if let Some(limit) = opts.line_length_limit() {
+            self.highlight_overflow(limit);
+        }
+        if !opts.suspicious_characters().is_empty() {
+            self.highlight_suspicious(opts.suspicious_characters());
+        }
+        self.highlight_match(word.map(|word| Pattern::plain(word, false)).as_ref());
         if in_ml_comment && &self.string[self.string.len().saturating_sub(2)..] != "*/" {
             return true;
         }
         false
     }
 
-    fn highlight_match(&mut self, word: Option<&str>) {
-        if let Some(word) = word {
-            if word.is_empty() {
+    // Flags the indentation when it has both tabs and spaces in it
+    fn highlight_mixed_indentation(&mut self) {
+        let indentation = self.indentation();
+        if !indentation.contains(' ') || !indentation.contains('\t') {
+            return;
+        }
+
+        let len = indentation.len();
+        for highlighting_type in self.highlighting.iter_mut().take(len) {
+            *highlighting_type = highlighting::Type::MixedIndentation;
+        }
+    }
+
+    // Flags whatever comes after the first `limit` characters
+    fn highlight_overflow(&mut self, limit: usize) {
+        for highlighting_type in self.highlighting.iter_mut().skip(limit) {
+            *highlighting_type = highlighting::Type::Overflow;
+        }
+    }
+
+    // Flags every grapheme holding one of `characters`, such as one with a zero-width joiner
+    // stuck to it
+    fn highlight_suspicious(&mut self, characters: &[char]) {
+        let flagged: Vec<usize> = self.string[..]
+            .graphemes(true)
+            .enumerate()
+            .filter(|(_, grapheme)| grapheme.chars().any(|c| characters.contains(&c)))
+            .map(|(index, _)| index)
+            .collect();
+        for index in flagged {
+            if let Some(highlighting_type) = self.highlighting.get_mut(index) {
+                *highlighting_type = highlighting::Type::Suspicious;
+            }
+        }
+    }
+
+    // the word the grapheme at `at` belongs to, if it's part of one
+    #[must_use]
+    #[allow(clippy::indexing_slicing)]
+    pub fn word_at(&self, at: usize) -> Option<String> {
+        let graphemes: Vec<&str> = self.string[..].graphemes(true).collect();
+        if !graphemes.get(at).map_or(false, |grapheme| is_word(grapheme)) {
+            return None;
+        }
+
+        let start = graphemes[..at]
+            .iter()
+            .rposition(|grapheme| !is_word(grapheme))
+            .map_or(0, |index| index.saturating_add(1));
+        let end = graphemes[at..]
+            .iter()
+            .position(|grapheme| !is_word(grapheme))
+            .map_or(graphemes.len(), |index| index.saturating_add(at));
+
+        Some(graphemes[start..end].concat())
+    }
+
+    // Marks every whole-word occurrence of `word`, leaving search matches alone. Meant to run
+    // right after `highlight`, which clears the marks again.
+    pub fn highlight_occurrences(&mut self, word: &str) {
+        self.invalidate_render();
+        if word.is_empty() {
+            return;
+        }
+
+        let graphemes: Vec<&str> = self.string[..].graphemes(true).collect();
+        let mut index = 0;
+        while let Some((start, len)) = self.find(word, index, SearchDirection::Forward, false) {
+            let end = start.saturating_add(len);
+            let before = start.checked_sub(1).and_then(|before| graphemes.get(before));
+            let whole_word = !before.map_or(false, |grapheme| is_word(grapheme))
+                && !graphemes.get(end).map_or(false, |grapheme| is_word(grapheme));
+            if whole_word {
+                for highlighting_type in self.highlighting.iter_mut().take(end).skip(start) {
+                    if *highlighting_type != highlighting::Type::Match {
+                        *highlighting_type = highlighting::Type::Occurrence;
+                    }
+                }
+            }
+            index = end;
+        }
+    }
+
+    // Marks the search matches for `pattern`. `highlight` does this for a plain, case-sensitive
+    // word on its own; run it again afterwards to mark the matches of any other search.
+    pub fn highlight_match(&mut self, pattern: Option<&Pattern>) {
+        self.invalidate_render();
+        if let Some(pattern) = pattern {
+            if pattern.is_empty() {
                 return;
             }
 
             let mut index = 0;
-            while let Some(search_match) = self.find(word, index, SearchDirection::Forward) {
-                if let Some(next_index) = search_match.checked_add(word[..].graphemes(true).count())
-                {
-                    #[allow(clippy::indexing_slicing)]
-                    for i in index.saturating_add(search_match)..next_index {
-                        self.highlighting[i] = highlighting::Type::Match;
-                    }
-                    index = next_index;
-                } else {
-                    break;
+            while let Some((start, len)) = self.find_pattern(pattern, index, SearchDirection::Forward) {
+                let next_index = start.saturating_add(len);
+                for highlighting_type in self.highlighting.iter_mut().take(next_index).skip(start) {
+                    *highlighting_type = highlighting::Type::Match;
                 }
+                // an empty match would find itself again
+                index = next_index.max(start.saturating_add(1));
             }
         }
     }
@@ -494,6 +856,24 @@ impl Row {
     }
 }
 
+// how many columns a tab at `screen_column` takes up to get to the next tab stop
+fn tab_stop(screen_column: usize, tab_width: usize) -> usize {
+    let tab_width = tab_width.max(1);
+    tab_width.saturating_sub(screen_column % tab_width)
+}
+
+fn grapheme_width(grapheme: &str, screen_column: usize, tab_width: usize) -> usize {
+    if grapheme == "\t" {
+        tab_stop(screen_column, tab_width)
+    } else {
+        1
+    }
+}
+
+fn is_word(grapheme: &str) -> bool {
+    grapheme.chars().all(|c| c.is_alphanumeric() || c == '_')
+}
+
 fn is_separator(c: char) -> bool {
     c.is_ascii_punctuation() || c.is_ascii_whitespace()
 }
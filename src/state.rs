@@ -0,0 +1,27 @@
+use std::env;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+// Where what sim remembers about the file at `path` between sessions goes: one file per source
+// file in the `store` directory under `~/.local/share/sim/`, named after a hash of where the file
+// really is
+pub fn file_for(store: &str, path: &str) -> Result<PathBuf, Error> {
+    let data_dir = match env::var_os("XDG_DATA_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => env::var_os("HOME")
+            .map(|home| Path::new(&home).join(".local").join("share"))
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, "no home directory"))?,
+    };
+    let path = fs::canonicalize(path)?;
+    let key = hash(path.to_string_lossy().as_bytes());
+
+    Ok(data_dir.join("sim").join(store).join(format!("{:016x}", key)))
+}
+
+// 64-bit FNV-1a, which unlike the standard hasher is the same across builds
+pub fn hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
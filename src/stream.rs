@@ -0,0 +1,211 @@
+use crate::Position;
+
+use std::cell::{Cell, RefCell};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Error, Read, Seek, SeekFrom};
+use unicode_segmentation::UnicodeSegmentation;
+
+// the most of a single row read for showing it, so that one endless line can't fill up memory
+// either
+const MAX_ROW_BYTES: u64 = 64 * 1024;
+
+// A read-only view of a file too big to load, the way `less` shows one. All that is kept is where
+// each row starts, as far into the file as has been looked at yet, and rows are read from the
+// file whenever they are shown.
+pub struct StreamDocument {
+    pub file_name: Option<String>,
+    reader: RefCell<BufReader<File>>,
+    // where every row found so far starts
+    line_starts: RefCell<Vec<u64>>,
+    // whether the rows found so far are all there are
+    complete: Cell<bool>,
+    size: u64,
+}
+
+impl StreamDocument {
+    /// # Errors
+    ///
+    pub fn open(filename: &str) -> Result<Self, Error> {
+        let file = File::open(filename)?;
+        let size = file.metadata()?.len();
+
+        Ok(Self {
+            file_name: Some(filename.to_string()),
+            reader: RefCell::new(BufReader::new(file)),
+            line_starts: RefCell::new(if size == 0 { Vec::new() } else { vec![0] }),
+            complete: Cell::new(size == 0),
+            size,
+        })
+    }
+
+    // number of rows found so far
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.line_starts.borrow().len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    #[must_use]
+    pub fn is_complete(&self) -> bool {
+        self.complete.get()
+    }
+
+    #[must_use]
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    // Reads through to the end of the file to count every row
+    #[must_use]
+    pub fn count_rows(&self) -> usize {
+        let _ = self.index_to(usize::MAX);
+        self.len()
+    }
+
+    // Row `y` without its line ending, cut short if it is very long; `None` past the end of the
+    // file or when it can't be read
+    #[must_use]
+    pub fn row(&self, y: usize) -> Option<String> {
+        self.index_to(y).ok()?;
+        let start = *self.line_starts.borrow().get(y)?;
+        let mut reader = self.reader.borrow_mut();
+        reader.seek(SeekFrom::Start(start)).ok()?;
+
+        let mut line = Vec::new();
+        (&mut *reader).take(MAX_ROW_BYTES).read_until(b'\n', &mut line).ok()?;
+        if line.last() == Some(&b'\n') {
+            line.pop();
+            if line.last() == Some(&b'\r') {
+                line.pop();
+            }
+        }
+
+        Some(String::from_utf8_lossy(&line).into_owned())
+    }
+
+    // The first match of `query` from `at` on, reading on through the file a row at a time and
+    // finding row starts along the way
+    #[must_use]
+    pub fn find(&self, query: &str, at: &Position) -> Option<Position> {
+        self.index_to(at.y).ok()?;
+        let mut starts = self.line_starts.borrow_mut();
+        let mut offset = *starts.get(at.y)?;
+        let mut reader = self.reader.borrow_mut();
+        reader.seek(SeekFrom::Start(offset)).ok()?;
+
+        let mut line = Vec::new();
+        let mut y = at.y;
+        loop {
+            line.clear();
+            let read = reader.read_until(b'\n', &mut line).ok()?;
+            if read == 0 {
+                self.complete.set(true);
+                return None;
+            }
+            offset = offset.saturating_add(read as u64);
+            if starts.len() == y.saturating_add(1) && offset < self.size {
+                starts.push(offset);
+            }
+
+            let text = String::from_utf8_lossy(&line);
+            let from = if y == at.y {
+                text.grapheme_indices(true).nth(at.x).map_or(text.len(), |(index, _)| index)
+            } else {
+                0
+            };
+            if let Some(index) = text.get(from..).and_then(|rest| rest.find(query)) {
+                let x = text.get(..from.saturating_add(index))?.graphemes(true).count();
+                return Some(Position { x, y });
+            }
+            y = y.saturating_add(1);
+        }
+    }
+
+    // Finds row starts until row `y` has one or the file ends
+    fn index_to(&self, y: usize) -> Result<(), Error> {
+        let mut starts = self.line_starts.borrow_mut();
+        if self.complete.get() || starts.len() > y {
+            return Ok(());
+        }
+
+        // the last row found is read again to find where it ends
+        let mut offset = starts.last().copied().unwrap_or(0);
+        let mut reader = self.reader.borrow_mut();
+        reader.seek(SeekFrom::Start(offset))?;
+        while starts.len() <= y {
+            let buffer = reader.fill_buf()?;
+            if buffer.is_empty() {
+                self.complete.set(true);
+                break;
+            }
+            let (used, ends_row) = match buffer.iter().position(|&byte| byte == b'\n') {
+                Some(index) => (index.saturating_add(1), true),
+                None => (buffer.len(), false),
+            };
+            reader.consume(used);
+
+            offset = offset.saturating_add(used as u64);
+            if ends_row && offset < self.size {
+                starts.push(offset);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+
+    // writes `contents` to a file of its own for a test named `name`
+    fn written(name: &str, contents: &[u8]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("sim-stream-{}-{}.txt", name, std::process::id()));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn last_row_without_a_newline() {
+        let path = written("unterminated", b"one\ntwo");
+        let stream = StreamDocument::open(&path.to_string_lossy()).unwrap();
+        assert_eq!(stream.count_rows(), 2);
+        assert!(stream.is_complete());
+        assert_eq!(stream.row(1).as_deref(), Some("two"));
+        assert_eq!(stream.row(2), None);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn crlf_line_endings() {
+        let path = written("crlf", b"one\r\ntwo\r\n");
+        let stream = StreamDocument::open(&path.to_string_lossy()).unwrap();
+        assert_eq!(stream.row(0).as_deref(), Some("one"));
+        assert_eq!(stream.row(1).as_deref(), Some("two"));
+        // the final line ending doesn't start another row
+        assert_eq!(stream.count_rows(), 2);
+        assert_eq!(stream.find("two", &Position::default()).map(|at| (at.x, at.y)), Some((0, 1)));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn search_across_a_chunk_boundary() {
+        // one row runs over the end of the reader's first 8 KiB with the match straddling it
+        let mut contents = b"first\n".to_vec();
+        contents.extend(vec![b'x'; 8189]);
+        contents.extend(b"needle\nafter\n");
+        let path = written("chunks", &contents);
+        let stream = StreamDocument::open(&path.to_string_lossy()).unwrap();
+        assert_eq!(stream.find("needle", &Position::default()).map(|at| (at.x, at.y)), Some((8189, 1)));
+        assert_eq!(stream.row(2).as_deref(), Some("after"));
+        assert_eq!(stream.count_rows(), 3);
+        assert_eq!(stream.find("after", &Position { x: 0, y: 1 }).map(|at| (at.x, at.y)), Some((0, 2)));
+        fs::remove_file(&path).unwrap();
+    }
+}
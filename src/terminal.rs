@@ -1,7 +1,13 @@
 use crate::Position;
+use crate::Style;
 
-use std::io::{self, stdout, Write};
-use termion::event::Key;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io::{self, stdout, Read, Write};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+use termion::event::{Event, Key};
 use termion::input::TermRead;
 use termion::raw::{IntoRawMode, RawTerminal};
 use termion::color;
@@ -12,6 +18,50 @@ pub struct Size {
     pub height: u16,
 }
 
+// Everything the editor needs from the screen and the keyboard. `Terminal` talks to the real
+// tty while `TestBackend` replays scripted keys and records the output, so the editor can be
+// driven headlessly
+pub trait Backend {
+    fn size(&self) -> &Size;
+    // Checks the size of the screen again, saying whether it changed since the last time
+    fn update_size(&mut self) -> bool {
+        false
+    }
+    fn clear_screen(&self);
+    fn clear_current_line(&self);
+    fn set_bg_color(&self, color: color::Rgb);
+    fn reset_bg_color(&self);
+    fn set_fg_color(&self, color: color::Rgb);
+    fn reset_fg_color(&self);
+    fn set_style(&self, style: Style) {
+        self.write(&style.on());
+    }
+    fn reset_style(&self, style: Style) {
+        self.write(&style.off());
+    }
+    fn cursor_position(&self, position: &Position);
+    fn cursor_hide(&self);
+    fn cursor_show(&self);
+    fn write(&self, text: &str);
+    fn bell(&self);
+    // briefly shows the whole screen in reverse video
+    fn flash(&self);
+    /// # Errors
+    ///
+    fn flush(&self) -> Result<(), std::io::Error>;
+    /// # Errors
+    ///
+    fn read_key(&self) -> Result<Key, std::io::Error>;
+    // like `read_key`, but also passes on what isn't a key, such as the markers around a paste
+    /// # Errors
+    ///
+    fn read_event(&self) -> Result<Event, std::io::Error>;
+    // like `read_event`, but gives up with `None` once `timeout` passes without any input
+    /// # Errors
+    ///
+    fn poll_event(&self, timeout: Duration) -> Result<Option<Event>, std::io::Error>;
+}
+
 // ASCII codes 32–126 are all printable
 // ASCII codes 0–31 are all control characters, and 127 is also a control character Control
 // characters are non-printable characters
@@ -20,26 +70,82 @@ pub struct Terminal {
     size: Size,
     // _stdout needed to keep terminal in raw mode not in canonical mode
     _stdout: RawTerminal<std::io::Stdout>,
+    // stdin is read on a thread of its own, so that waiting for input can time out
+    events: Receiver<Result<Event, std::io::Error>>,
 }
 
 impl Terminal {
+    /// # Errors
+    ///
     pub fn default() -> Result<Self, std::io::Error> {
         let size = termion::terminal_size()?;
+        let stdout = stdout().into_raw_mode()?;
+        // bracketed paste: the terminal wraps pasted text in `PASTE_START` and `PASTE_END`
+        print!("\x1b[?2004h");
+        // focus reporting: the terminal sends `FOCUS_OUT` when it stops being the focused window
+        print!("\x1b[?1004h");
+
+        // with the text piped in on stdin, as a pager gets it, keys come from the tty instead
+        let tty = if termion::is_tty(&io::stdin()) { None } else { Some(termion::get_tty()?) };
+        let (sender, events) = mpsc::channel();
+        thread::spawn(move || {
+            let input: Box<dyn Read> = match tty {
+                Some(tty) => Box::new(tty),
+                None => Box::new(io::stdin()),
+            };
+            for event in input.events() {
+                if sender.send(event).is_err() {
+                    break;
+                }
+            }
+        });
 
         Ok(Self {
-            size: Size {
-                width: size.0,
-                height: size.1.saturating_sub(2),
-            },
-            _stdout: stdout().into_raw_mode()?,
+            size: text_size(size),
+            _stdout: stdout,
+            events,
         })
     }
+}
+
+// the room a terminal of `size` columns and rows leaves for the text, with the status and message
+// bars below it
+fn text_size(size: (u16, u16)) -> Size {
+    Size {
+        width: size.0,
+        height: size.1.saturating_sub(2),
+    }
+}
 
-    pub fn size(&self) -> &Size {
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        print!("\x1b[?2004l");
+        print!("\x1b[?1004l");
+        print!("{}", MOUSE_OFF);
+        io::stdout().flush().ok();
+    }
+}
+
+impl Backend for Terminal {
+    fn size(&self) -> &Size {
         &self.size
     }
 
-    pub fn clear_screen() {
+    // asked every time the editor waits for input, as that is cheaper than catching SIGWINCH
+    // without a crate to do it
+    fn update_size(&mut self) -> bool {
+        let size = match termion::terminal_size() {
+            Ok(size) => text_size(size),
+            Err(_) => return false,
+        };
+        if size.width == self.size.width && size.height == self.size.height {
+            return false;
+        }
+        self.size = size;
+        true
+    }
+
+    fn clear_screen(&self) {
         // \x1b => Esc: 27,
         // [2J => J: Erase in Display, 2: argument means clear entire screen
         // https://vt100.net/docs/vt100-ug/chapter3.html#ED
@@ -50,67 +156,219 @@ impl Terminal {
         print!("{}", termion::clear::All);
     }
 
-    pub fn clear_current_line() {
+    fn clear_current_line(&self) {
         // \x1b[K
         print!("{}", termion::clear::CurrentLine);
     }
 
-    pub fn set_bg_color(color: color::Rgb) {
+    fn set_bg_color(&self, color: color::Rgb) {
         print!("{}", color::Bg(color));
     }
 
-    pub fn reset_bg_color() {
+    fn reset_bg_color(&self) {
         print!("{}", color::Bg(color::Reset));
     }
 
-    pub fn set_fg_color(color: color::Rgb) {
+    fn set_fg_color(&self, color: color::Rgb) {
         print!("{}", color::Fg(color));
     }
 
-    pub fn reset_fg_color() {
+    fn reset_fg_color(&self) {
         print!("{}", color::Fg(color::Reset));
     }
 
     // 0-based in contrast to vt100 which is 1-based
-    #[allow(clippy::cast_possible_truncation)]
-    pub fn cursor_position(position: &Position) {
+    fn cursor_position(&self, position: &Position) {
         // https://vt100.net/docs/vt100-ug/chapter3.html#CUP
         // print!("\x1b[1;1H");
         // OR
-
-        // `saturating_add()`: attempts to add 1, and if that’s not possible, it just returns the
-        // maximum value; no overflow
-        let Position{mut x, mut y} = position;
-        x = x.saturating_add(1);
-        y = y.saturating_add(1);
-        let x = x as u16;
-        let y = y as u16;
-        print!("{}", termion::cursor::Goto(x, y));
+        print!("{}", goto(position));
     }
 
-    pub fn flush() -> Result<(), std::io::Error> {
-        io::stdout().flush()
-    }
-
-    pub fn cursor_hide() {
+    fn cursor_hide(&self) {
         // \x1b[25h
         // Set Mode
         // http://vt100.net/docs/vt100-ug/chapter3.html#SM
         print!("{}", termion::cursor::Hide);
     }
 
-    pub fn cursor_show() {
+    fn cursor_show(&self) {
         // \x1b[25l
         // Reset Mode
         // http://vt100.net/docs/vt100-ug/chapter3.html#RM
         print!("{}", termion::cursor::Show);
     }
 
-    pub fn read_key() -> Result<Key, std::io::Error> {
+    fn write(&self, text: &str) {
+        print!("{}", text);
+    }
+
+    fn bell(&self) {
+        print!("\x07");
+    }
+
+    fn flash(&self) {
+        // DECSCNM: reverse video for the entire screen
+        // https://vt100.net/docs/vt100-ug/chapter3.html#DECSCNM
+        print!("\x1b[?5h");
+        io::stdout().flush().ok();
+        thread::sleep(Duration::from_millis(100));
+        print!("\x1b[?5l");
+    }
+
+    fn flush(&self) -> Result<(), std::io::Error> {
+        io::stdout().flush()
+    }
+
+    fn read_key(&self) -> Result<Key, std::io::Error> {
         loop {
-            if let Some(key) = io::stdin().lock().keys().next() {
-                return key;
+            if let Event::Key(key) = self.read_event()? {
+                return Ok(key);
             }
         }
     }
+
+    fn read_event(&self) -> Result<Event, std::io::Error> {
+        self.events.recv().unwrap_or_else(|_| Err(stdin_closed()))
+    }
+
+    fn poll_event(&self, timeout: Duration) -> Result<Option<Event>, std::io::Error> {
+        match self.events.recv_timeout(timeout) {
+            Ok(event) => event.map(Some),
+            Err(RecvTimeoutError::Timeout) => Ok(None),
+            Err(RecvTimeoutError::Disconnected) => Err(stdin_closed()),
+        }
+    }
+}
+
+fn stdin_closed() -> std::io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "stdin was closed")
+}
+
+// A backend with a fixed size that feeds the editor a script of keys and keeps everything the
+// editor would have printed. Once the script runs out, reading fails with `UnexpectedEof`.
+pub struct TestBackend {
+    size: Size,
+    events: RefCell<VecDeque<Event>>,
+    output: RefCell<String>,
+}
+
+impl TestBackend {
+    #[must_use]
+    pub fn new(width: u16, height: u16, keys: Vec<Key>) -> Self {
+        Self::with_events(width, height, keys.into_iter().map(Event::Key).collect())
+    }
+
+    #[must_use]
+    pub fn with_events(width: u16, height: u16, events: Vec<Event>) -> Self {
+        Self {
+            size: Size { width, height },
+            events: RefCell::new(events.into()),
+            output: RefCell::new(String::new()),
+        }
+    }
+
+    #[must_use]
+    pub fn output(&self) -> String {
+        self.output.borrow().clone()
+    }
+}
+
+impl Backend for TestBackend {
+    fn size(&self) -> &Size {
+        &self.size
+    }
+
+    fn clear_screen(&self) {
+        self.write(&termion::clear::All.to_string());
+    }
+
+    fn clear_current_line(&self) {
+        self.write(&termion::clear::CurrentLine.to_string());
+    }
+
+    fn set_bg_color(&self, color: color::Rgb) {
+        self.write(&color::Bg(color).to_string());
+    }
+
+    fn reset_bg_color(&self) {
+        self.write(&color::Bg(color::Reset).to_string());
+    }
+
+    fn set_fg_color(&self, color: color::Rgb) {
+        self.write(&color::Fg(color).to_string());
+    }
+
+    fn reset_fg_color(&self) {
+        self.write(&color::Fg(color::Reset).to_string());
+    }
+
+    fn cursor_position(&self, position: &Position) {
+        self.write(&goto(position).to_string());
+    }
+
+    fn cursor_hide(&self) {
+        self.write(&termion::cursor::Hide.to_string());
+    }
+
+    fn cursor_show(&self) {
+        self.write(&termion::cursor::Show.to_string());
+    }
+
+    fn write(&self, text: &str) {
+        self.output.borrow_mut().push_str(text);
+    }
+
+    fn bell(&self) {
+        self.write("\x07");
+    }
+
+    fn flash(&self) {
+        self.write("\x1b[?5h\x1b[?5l");
+    }
+
+    fn flush(&self) -> Result<(), std::io::Error> {
+        Ok(())
+    }
+
+    fn read_key(&self) -> Result<Key, std::io::Error> {
+        loop {
+            if let Event::Key(key) = self.read_event()? {
+                return Ok(key);
+            }
+        }
+    }
+
+    fn read_event(&self) -> Result<Event, std::io::Error> {
+        self.events.borrow_mut().pop_front().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::UnexpectedEof, "no more scripted keys")
+        })
+    }
+
+    // scripted events are always there right away
+    fn poll_event(&self, _timeout: Duration) -> Result<Option<Event>, std::io::Error> {
+        self.read_event().map(Some)
+    }
+}
+
+pub const PASTE_START: &[u8] = b"\x1b[200~";
+pub const PASTE_END: &[u8] = b"\x1b[201~";
+pub const FOCUS_OUT: &[u8] = b"\x1b[O";
+// mouse reporting: presses, releases, and moves with a button held, in the SGR encoding
+pub const MOUSE_ON: &str = "\x1b[?1000h\x1b[?1002h\x1b[?1006h";
+pub const MOUSE_OFF: &str = "\x1b[?1006l\x1b[?1002l\x1b[?1000l";
+// termion has no keys for Shift with the arrows, so they arrive as sequences it doesn't know
+pub const SHIFT_LEFT: &[u8] = b"\x1b[1;2D";
+pub const SHIFT_RIGHT: &[u8] = b"\x1b[1;2C";
+
+#[allow(clippy::cast_possible_truncation)]
+fn goto(position: &Position) -> termion::cursor::Goto {
+    // `saturating_add()`: attempts to add 1, and if that’s not possible, it just returns the
+    // maximum value; no overflow
+    let Position{mut x, mut y} = position;
+    x = x.saturating_add(1);
+    y = y.saturating_add(1);
+    let x = x as u16;
+    let y = y as u16;
+    termion::cursor::Goto(x, y)
 }
@@ -0,0 +1,109 @@
+use crate::Palette;
+use crate::Styles;
+use termion::color;
+
+// The names `Theme::named` knows, in the order the `theme` command cycles through them
+pub const THEME_NAMES: [&str; 3] = ["dark", "light", "solarized"];
+
+// Every color the editor draws with. Start from one of the presets and override single entries
+// as needed, e.g. `theme.palette.comment = color::Rgb(..)`.
+#[derive(PartialEq, Clone)]
+pub struct Theme {
+    pub name: String,
+    pub palette: Palette,
+    pub status_bg: color::Rgb,
+    pub status_fg: color::Rgb,
+    // line numbers, and the numbers of lines holding a search match
+    pub gutter_fg: color::Rgb,
+    pub gutter_match_fg: color::Rgb,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::dark()
+    }
+}
+
+impl Theme {
+    // The colors sim has always used, meant for a dark terminal
+    #[must_use]
+    pub fn dark() -> Self {
+        Self {
+            name: "dark".to_string(),
+            palette: Palette::default(),
+            status_bg: color::Rgb(239, 239, 239),
+            status_fg: color::Rgb(63, 63, 63),
+            gutter_fg: color::Rgb(110, 110, 110),
+            gutter_match_fg: color::Rgb(38, 139, 210),
+        }
+    }
+
+    // Darker text colors that stay readable on a light terminal
+    #[must_use]
+    pub fn light() -> Self {
+        Self {
+            name: "light".to_string(),
+            palette: Palette {
+                text: color::Rgb(40, 40, 40),
+                number: color::Rgb(152, 104, 1),
+                string: color::Rgb(121, 116, 14),
+                character: color::Rgb(66, 123, 88),
+                comment: color::Rgb(146, 131, 116),
+                primary_keyword: color::Rgb(157, 0, 6),
+                secondary_keyword: color::Rgb(7, 102, 120),
+                search_match: color::Rgb(0, 92, 197),
+                occurrence: color::Rgb(143, 63, 113),
+                mixed_indentation: color::Rgb(251, 178, 99),
+                suspicious: color::Rgb(251, 73, 52),
+                overflow: color::Rgb(250, 215, 205),
+                crosshair: color::Rgb(230, 230, 230),
+                whitespace: color::Rgb(189, 174, 147),
+                styles: Styles::default(),
+            },
+            status_bg: color::Rgb(60, 56, 54),
+            status_fg: color::Rgb(235, 219, 178),
+            gutter_fg: color::Rgb(168, 153, 132),
+            gutter_match_fg: color::Rgb(0, 92, 197),
+        }
+    }
+
+    // Ethan Schoonover's Solarized accents over its dark base tones
+    #[must_use]
+    pub fn solarized() -> Self {
+        Self {
+            name: "solarized".to_string(),
+            palette: Palette {
+                text: color::Rgb(131, 148, 150),
+                number: color::Rgb(211, 54, 130),
+                string: color::Rgb(42, 161, 152),
+                character: color::Rgb(108, 113, 196),
+                comment: color::Rgb(88, 110, 117),
+                primary_keyword: color::Rgb(133, 153, 0),
+                secondary_keyword: color::Rgb(181, 137, 0),
+                search_match: color::Rgb(38, 139, 210),
+                occurrence: color::Rgb(147, 161, 161),
+                mixed_indentation: color::Rgb(203, 75, 22),
+                suspicious: color::Rgb(220, 50, 47),
+                overflow: color::Rgb(73, 38, 48),
+                crosshair: color::Rgb(7, 54, 66),
+                whitespace: color::Rgb(88, 110, 117),
+                styles: Styles::default(),
+            },
+            status_bg: color::Rgb(7, 54, 66),
+            status_fg: color::Rgb(147, 161, 161),
+            gutter_fg: color::Rgb(88, 110, 117),
+            gutter_match_fg: color::Rgb(38, 139, 210),
+        }
+    }
+
+    // One of the presets in `THEME_NAMES`
+    #[must_use]
+    pub fn named(name: &str) -> Option<Self> {
+        match name {
+            "dark" => Some(Self::dark()),
+            "light" => Some(Self::light()),
+            "solarized" => Some(Self::solarized()),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,234 @@
+use crate::Position;
+use crate::state::{self, hash};
+use std::fs;
+use std::io::Error;
+
+// One change to a document, with enough of the old text kept to take it back
+#[derive(Clone)]
+pub enum Edit {
+    // a character inserted at the position
+    Insert(Position, char),
+    // the grapheme removed from the position
+    Delete(Position, String),
+    // the row split in two at the position
+    NewlineInsert(Position),
+    // the row after `y` appended to it, `x` being where they meet
+    RowJoin(Position),
+    // an empty row pushed onto the end as row `y`
+    AddRow(usize),
+    // the whole text of row `y`, before and after
+    Replace(usize, String, String),
+    // the whole text of the document, before and after
+    Rewrite(String, String),
+}
+
+// A selection's two ends, the anchor it was started from and the head the cursor is on, and
+// whether it is a block
+#[derive(Clone)]
+pub struct Selection {
+    pub anchor: Position,
+    pub head: Position,
+    pub block: bool,
+}
+
+// Edits that are undone and redone together, with the selections from before and after them
+#[derive(Clone, Default)]
+pub struct Group {
+    pub edits: Vec<Edit>,
+    pub selection_before: Option<Selection>,
+    pub selection_after: Option<Selection>,
+}
+
+// Groups of edits that are undone and redone together, newest last
+#[derive(Default)]
+pub struct History {
+    undo: Vec<Group>,
+    redo: Vec<Group>,
+    // whether the next edit joins the newest group rather than starting one
+    group_open: bool,
+    // the selection last noted, which a group started now was made from
+    selection: Option<Selection>,
+    // how many groups were on the undo stack when the document was last saved, and whether that
+    // got lost, which it does when an edit throws away the redo stack it was on
+    saved: usize,
+    save_lost: bool,
+}
+
+impl History {
+    pub fn record(&mut self, edit: Edit) {
+        if self.undo.len() < self.saved {
+            self.save_lost = true;
+        }
+        self.redo.clear();
+        if self.group_open {
+            if let Some(group) = self.undo.last_mut() {
+                group.edits.push(edit);
+                return;
+            }
+        }
+        self.undo.push(Group {
+            edits: vec![edit],
+            selection_before: self.selection.clone(),
+            selection_after: self.selection.clone(),
+        });
+        self.group_open = true;
+    }
+
+    // Keeps the selection as it is now, as the one the newest group left behind if that is still
+    // open, and as the one the next group starts from
+    pub fn note_selection(&mut self, selection: Option<Selection>) {
+        if self.group_open {
+            if let Some(group) = self.undo.last_mut() {
+                group.selection_after = selection.clone();
+            }
+        }
+        self.selection = selection;
+    }
+
+    pub fn break_group(&mut self) {
+        self.group_open = false;
+    }
+
+    // Takes where the history is now as what the file on disk holds
+    pub fn mark_saved(&mut self) {
+        self.saved = self.undo.len();
+        self.save_lost = false;
+        self.group_open = false;
+    }
+
+    // whether undoing and redoing have come back to what was last saved
+    #[must_use]
+    pub fn is_at_save(&self) -> bool {
+        !self.save_lost && self.undo.len() == self.saved
+    }
+
+    pub fn undo(&mut self) -> Option<Group> {
+        self.group_open = false;
+        let group = self.undo.pop()?;
+        self.redo.push(group.clone());
+        Some(group)
+    }
+
+    pub fn redo(&mut self) -> Option<Group> {
+        self.group_open = false;
+        let group = self.redo.pop()?;
+        self.undo.push(group.clone());
+        Some(group)
+    }
+
+    // Writes both stacks for the file at `path`, along with the hash of the contents they lead
+    // up to; selections are left out, they don't mean anything once the file is closed
+    pub fn save(&self, path: &str, contents: &str) -> Result<(), Error> {
+        let file = state::file_for("undo", path)?;
+        if let Some(dir) = file.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let mut out = format!("{}\n{:016x}\n", HEADER, hash(contents.as_bytes()));
+        for (name, stack) in &[("undo", &self.undo), ("redo", &self.redo)] {
+            out.push_str(name);
+            out.push('\n');
+            for group in stack.iter() {
+                for edit in &group.edits {
+                    out.push_str(&encode(edit));
+                    out.push('\n');
+                }
+                out.push_str("-\n");
+            }
+        }
+
+        fs::write(file, out)
+    }
+
+    // The history saved for the file at `path`, if there is one and it was saved for `contents`.
+    // Any other history is stale and gets thrown away.
+    #[must_use]
+    pub fn load(path: &str, contents: &str) -> Option<Self> {
+        let file = state::file_for("undo", path).ok()?;
+        let saved = fs::read_to_string(&file).ok()?;
+        let mut lines = saved.lines();
+        if lines.next() != Some(HEADER) {
+            return None;
+        }
+        if lines.next()? != format!("{:016x}", hash(contents.as_bytes())) {
+            let _ = fs::remove_file(&file);
+            return None;
+        }
+
+        let mut history = Self::default();
+        let mut stack = None;
+        let mut group = Vec::new();
+        for line in lines {
+            match line {
+                "undo" => stack = Some(&mut history.undo),
+                "redo" => stack = Some(&mut history.redo),
+                "-" => stack.as_mut()?.push(Group {
+                    edits: std::mem::take(&mut group),
+                    ..Group::default()
+                }),
+                _ => group.push(decode(line)?),
+            }
+        }
+
+        Some(history)
+    }
+}
+
+const HEADER: &str = "sim undo 1";
+
+// Edits go one per line: a letter for the kind, then numbers, then text as hex so that spaces and
+// anything else the rows hold come back as they were
+fn encode(edit: &Edit) -> String {
+    match edit {
+        Edit::Insert(at, c) => format!("i {} {} {}", at.x, at.y, to_hex(&c.to_string())),
+        Edit::Delete(at, text) => format!("d {} {} {}", at.x, at.y, to_hex(text)),
+        Edit::NewlineInsert(at) => format!("n {} {}", at.x, at.y),
+        Edit::RowJoin(at) => format!("j {} {}", at.x, at.y),
+        Edit::AddRow(y) => format!("a {}", y),
+        Edit::Replace(y, old, new) => format!("r {} {} {}", y, to_hex(old), to_hex(new)),
+        Edit::Rewrite(old, new) => format!("w {} {}", to_hex(old), to_hex(new)),
+    }
+}
+
+fn decode(line: &str) -> Option<Edit> {
+    let mut fields = line.split(' ');
+    let kind = fields.next()?;
+    let mut number = || fields.next()?.parse::<usize>().ok();
+    let edit = match kind {
+        "i" | "d" | "n" | "j" => {
+            let at = Position { x: number()?, y: number()? };
+            match kind {
+                "i" => Edit::Insert(at, from_hex(fields.next()?)?.chars().next()?),
+                "d" => Edit::Delete(at, from_hex(fields.next()?)?),
+                "n" => Edit::NewlineInsert(at),
+                _ => Edit::RowJoin(at),
+            }
+        },
+        "a" => Edit::AddRow(number()?),
+        "r" => Edit::Replace(number()?, from_hex(fields.next()?)?, from_hex(fields.next()?)?),
+        "w" => Edit::Rewrite(from_hex(fields.next()?)?, from_hex(fields.next()?)?),
+        _ => return None,
+    };
+
+    Some(edit)
+}
+
+// an empty string is written as a lone `.`, so every field has something in it
+fn to_hex(text: &str) -> String {
+    if text.is_empty() {
+        return ".".to_string();
+    }
+    text.bytes().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(field: &str) -> Option<String> {
+    if field == "." {
+        return Some(String::new());
+    }
+    let bytes = (0..field.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(field.get(i..i.saturating_add(2))?, 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+
+    String::from_utf8(bytes).ok()
+}